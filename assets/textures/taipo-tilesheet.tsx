@@ -0,0 +1,4 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.5" tiledversion="1.7.2" name="taipo-tilesheet" tilewidth="32" tileheight="32" tilecount="256" columns="16">
+ <image source="taipo-tilesheet.png" width="512" height="512"/>
+</tileset>
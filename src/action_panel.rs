@@ -1,26 +1,38 @@
 use bevy::prelude::*;
 
 use crate::{
-    loading::{FontHandles, UiTextureHandles},
-    tower::{TowerKind, TowerState, TowerStats, TOWER_PRICE},
+    loading::{FontHandles, TextureHandles, UiTextureHandles},
+    status_effect::StatusEffects,
+    tower::{
+        effective_dps, tower_tint, LockedTowers, TargetingMode, TowerConfig, TowerKind, TowerState,
+        TowerStats,
+    },
     typing::{
-        TypingTarget, TypingTargetBundle, TypingTargetSettings, TypingTargetText, TypingTargets,
+        FuriganaText, TypingTarget, TypingTargetBundle, TypingTargetSettings, TypingTargetText,
+        TypingTargets,
     },
-    ui_color, Action, AfterUpdate, Currency, TaipoState, TowerSelection,
+    ui_color, Action, AfterUpdate, CleanupBeforeNewGame, Currency, TaipoState, TowerSelection,
+    FONT_SIZE_FURIGANA,
 };
 
 pub struct ActionPanelPlugin;
 
 impl Plugin for ActionPanelPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<ActionPanel>();
+        app.init_resource::<ActionPanel>()
+            .init_resource::<ActionPanelSettings>();
 
-        // `update_actions_panel` needs to be aware of `TowerStats` components that get queued to
-        // spawn in `CoreSet::Update`
+        // `update_action_panel` needs to be aware of `TowerStats` components that get queued to
+        // spawn in `Update`
         app.add_systems(
             AfterUpdate,
             update_action_panel.run_if(in_state(TaipoState::Playing)),
         );
+        app.add_systems(
+            Update,
+            (update_action_panel_hints, animate_action_panel_items)
+                .run_if(in_state(TaipoState::Playing)),
+        );
 
         app.add_systems(OnEnter(TaipoState::Spawn), setup_action_panel);
     }
@@ -28,6 +40,15 @@ impl Plugin for ActionPanelPlugin {
 
 pub static FONT_SIZE_ACTION_PANEL: f32 = 22.0;
 pub static FONT_SIZE_COST: f32 = 12.0;
+pub static FONT_SIZE_HINT: f32 = 12.0;
+
+/// The number of items in the action panel, each of which needs a word from the
+/// word lists.
+pub const NUM_ACTION_PANEL_ITEMS: usize = 11;
+
+const ITEM_HEIGHT: f32 = 42.0;
+/// Time in seconds for an item to slide fully in or out.
+const ITEM_TRANSITION_DURATION: f32 = 0.15;
 
 #[derive(Resource, Default)]
 pub struct ActionPanel {
@@ -35,9 +56,28 @@ pub struct ActionPanel {
     entities: Vec<Entity>,
 }
 
+#[derive(Resource)]
+pub struct ActionPanelSettings {
+    /// If true, show a short description of each action beside its typing target.
+    pub show_hints: bool,
+    /// If true, items appear and disappear instantly instead of sliding. Also applies
+    /// to the wave banner.
+    pub reduce_motion: bool,
+}
+impl Default for ActionPanelSettings {
+    fn default() -> Self {
+        Self {
+            show_hints: true,
+            reduce_motion: false,
+        }
+    }
+}
+
 struct ActionPanelItem {
     icon: Handle<Image>,
-    target: TypingTarget,
+    /// The word to type, unless the word lists ran out, in which case the item is
+    /// never shown.
+    target: Option<TypingTarget>,
     action: Action,
     visible: bool,
 }
@@ -51,13 +91,41 @@ pub struct ActionPanelItemImage;
 pub struct ActionPanelItemPriceContainer;
 #[derive(Component)]
 pub struct ActionPanelItemPriceText;
+#[derive(Component)]
+pub struct ActionPanelItemHint;
+
+/// Tracks how far an item has slid in, from `0.0` (hidden) to `1.0` (fully shown).
+#[derive(Component)]
+struct ActionPanelItemTransition {
+    visible: bool,
+    progress: f32,
+}
+
+fn action_hint(action: &Action) -> &'static str {
+    match action {
+        Action::GenerateMoney => "Earn",
+        Action::BuildTower(TowerKind::Basic) => "Build Shuriken",
+        Action::BuildTower(TowerKind::Support) => "Build Support",
+        Action::BuildTower(TowerKind::Debuff) => "Build Debuff",
+        Action::BuildTower(TowerKind::Poison) => "Build Poison",
+        Action::BuildTower(TowerKind::Splash) => "Build Splash",
+        Action::BuildTower(TowerKind::Railgun) => "Build Railgun",
+        Action::UpgradeTower => "Upgrade",
+        Action::SellTower => "Sell",
+        Action::CycleTargeting => "Targeting",
+        Action::UnselectTower => "Back",
+        _ => "",
+    }
+}
 
 fn setup_action_panel(
     mut commands: Commands,
     mut action_panel: ResMut<ActionPanel>,
     mut typing_targets: ResMut<TypingTargets>,
     ui_texture_handles: ResMut<UiTextureHandles>,
+    texture_handles: Res<TextureHandles>,
     font_handles: Res<FontHandles>,
+    settings: Res<ActionPanelSettings>,
 ) {
     let action_container = commands
         .spawn((
@@ -73,10 +141,11 @@ fn setup_action_panel(
             },
             BackgroundColor(ui_color::TRANSPARENT_BACKGROUND.into()),
             ActionPanelContainer,
+            CleanupBeforeNewGame,
         ))
         .id();
 
-    let actions = vec![
+    let actions: [ActionPanelItem; NUM_ACTION_PANEL_ITEMS] = [
         ActionPanelItem {
             icon: ui_texture_handles.coin_ui.clone(),
             target: typing_targets.pop_front(),
@@ -101,6 +170,24 @@ fn setup_action_panel(
             action: Action::BuildTower(TowerKind::Debuff),
             visible: false,
         },
+        ActionPanelItem {
+            icon: ui_texture_handles.debuff_tower_ui.clone(),
+            target: typing_targets.pop_front(),
+            action: Action::BuildTower(TowerKind::Poison),
+            visible: false,
+        },
+        ActionPanelItem {
+            icon: ui_texture_handles.shuriken_tower_ui.clone(),
+            target: typing_targets.pop_front(),
+            action: Action::BuildTower(TowerKind::Splash),
+            visible: false,
+        },
+        ActionPanelItem {
+            icon: ui_texture_handles.shuriken_tower_ui.clone(),
+            target: typing_targets.pop_front(),
+            action: Action::BuildTower(TowerKind::Railgun),
+            visible: false,
+        },
         ActionPanelItem {
             icon: ui_texture_handles.upgrade_ui.clone(),
             target: typing_targets.pop_front(),
@@ -113,6 +200,12 @@ fn setup_action_panel(
             action: Action::SellTower,
             visible: false,
         },
+        ActionPanelItem {
+            icon: texture_handles.reticle.clone(),
+            target: typing_targets.pop_front(),
+            action: Action::CycleTargeting,
+            visible: false,
+        },
         ActionPanelItem {
             icon: ui_texture_handles.back_ui.clone(),
             target: typing_targets.pop_front(),
@@ -130,11 +223,12 @@ fn setup_action_panel(
                 &mut commands,
                 &font_handles,
                 &ui_texture_handles,
+                &settings,
             )
         })
         .collect();
 
-    action_panel.actions = actions;
+    action_panel.actions = actions.into();
     action_panel.entities = entities;
 }
 
@@ -144,6 +238,7 @@ fn spawn_action_panel_item(
     commands: &mut Commands,
     font_handles: &FontHandles,
     texture_handles: &UiTextureHandles,
+    settings: &ActionPanelSettings,
 ) -> Entity {
     let child = commands
         .spawn((
@@ -156,19 +251,23 @@ fn spawn_action_panel_item(
                 justify_content: JustifyContent::FlexStart,
                 align_items: AlignItems::Center,
                 width: Val::Percent(100.0),
-                height: Val::Px(42.0),
+                height: Val::Px(if item.visible { ITEM_HEIGHT } else { 0.0 }),
+                overflow: Overflow::clip(),
                 ..default()
             },
-            TypingTargetBundle {
-                target: item.target.clone(),
-                action: item.action.clone(),
-                settings: TypingTargetSettings::default(),
+            ActionPanelItemTransition {
+                visible: item.visible,
+                progress: if item.visible { 1.0 } else { 0.0 },
             },
         ))
         .with_children(|parent| {
             parent.spawn((
                 ImageNode {
                     image: item.icon.clone(),
+                    color: match item.action {
+                        Action::BuildTower(kind) => tower_tint(kind),
+                        _ => Color::WHITE,
+                    },
                     ..default()
                 },
                 Node {
@@ -229,6 +328,27 @@ fn spawn_action_panel_item(
                         ActionPanelItemPriceText,
                     ));
                 });
+            parent.spawn((
+                Text::new(action_hint(&item.action)),
+                TextFont {
+                    font: font_handles.jptext.clone(),
+                    font_size: FONT_SIZE_HINT,
+                    ..default()
+                },
+                TextColor(ui_color::BUTTON_TEXT.into()),
+                Node {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(4.0),
+                    bottom: Val::Px(1.0),
+                    ..default()
+                },
+                if settings.show_hints {
+                    Visibility::Inherited
+                } else {
+                    Visibility::Hidden
+                },
+                ActionPanelItemHint,
+            ));
             parent
                 .spawn((
                     Text::default(),
@@ -240,18 +360,61 @@ fn spawn_action_panel_item(
                     TextColor(ui_color::GOOD_TEXT.into()),
                     TypingTargetText,
                 ))
-                .with_child((
-                    TextSpan::new(item.target.displayed_chunks.join("")),
-                    TextFont {
-                        font: font_handles.jptext.clone(),
-                        font_size: FONT_SIZE_ACTION_PANEL,
-                        ..default()
-                    },
-                    TextColor(ui_color::NORMAL_TEXT.into()),
-                ));
+                .with_children(|parent| {
+                    parent.spawn((
+                        TextSpan::default(),
+                        TextFont {
+                            font: font_handles.jptext.clone(),
+                            font_size: FONT_SIZE_ACTION_PANEL,
+                            ..default()
+                        },
+                        TextColor(ui_color::PARTIAL_TEXT.into()),
+                    ));
+                    parent.spawn((
+                        TextSpan::new(
+                            item.target
+                                .as_ref()
+                                .map_or(String::new(), |target| target.displayed_chunks.join("")),
+                        ),
+                        TextFont {
+                            font: font_handles.jptext.clone(),
+                            font_size: FONT_SIZE_ACTION_PANEL,
+                            ..default()
+                        },
+                        TextColor(ui_color::NORMAL_TEXT.into()),
+                    ));
+                });
+            // Above the start of the word, which follows the icon
+            parent.spawn((
+                Text::default(),
+                TextFont {
+                    font: font_handles.jptext.clone(),
+                    font_size: FONT_SIZE_FURIGANA,
+                    ..default()
+                },
+                TextColor(ui_color::NORMAL_TEXT.into()),
+                Node {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(0.0),
+                    left: Val::Px(42.0),
+                    ..default()
+                },
+                Visibility::Hidden,
+                FuriganaText,
+            ));
         })
         .id();
 
+    if let Some(target) = &item.target {
+        commands.entity(child).insert(TypingTargetBundle {
+            target: target.clone(),
+            action: item.action.clone(),
+            settings: TypingTargetSettings::default(),
+        });
+    } else {
+        warn!("No unambiguous words left for {:?}", item.action);
+    }
+
     commands.entity(container).add_child(child);
 
     child
@@ -262,9 +425,19 @@ fn update_action_panel(
     mut node_query: Query<&mut Node>,
     text_query: Query<(), With<TypingTargetText>>,
     price_text_query: Query<(), With<ActionPanelItemPriceText>>,
+    hint_query: Query<(), With<ActionPanelItemHint>>,
     tower_query: Query<(&TowerState, &TowerKind, &TowerStats)>,
+    targeting_query: Query<&TargetingMode>,
+    dps_query: Query<(&TowerState, &TowerKind, &TowerStats, &StatusEffects)>,
     price_query: Query<(Entity, &Children), With<ActionPanelItemPriceContainer>>,
-    (actions, currency, selection): (Res<ActionPanel>, Res<Currency>, Res<TowerSelection>),
+    mut transition_query: Query<&mut ActionPanelItemTransition>,
+    (actions, currency, selection, tower_config, locked_towers): (
+        Res<ActionPanel>,
+        Res<Currency>,
+        Res<TowerSelection>,
+        Res<TowerConfig>,
+        Res<LockedTowers>,
+    ),
     mut writer: TextUiWriter,
 ) {
     if !actions.is_changed() {
@@ -282,29 +455,28 @@ fn update_action_panel(
             Action::GenerateMoney => selection.selected.is_none(),
             Action::UnselectTower => selection.selected.is_some(),
             Action::UpgradeTower => match selection.selected {
-                Some(tower_slot) => {
-                    match tower_query.get(tower_slot) {
-                        Ok((_, _, stats)) => {
-                            // TODO allow more upgrades?
-                            stats.level < 2
-                        }
-                        Err(_) => false,
-                    }
-                }
+                Some(tower_slot) => match tower_query.get(tower_slot) {
+                    Ok((_, kind, stats)) => stats.level < tower_config.get(*kind).max_level,
+                    Err(_) => false,
+                },
                 None => false,
             },
             Action::SellTower => match selection.selected {
                 Some(tower_slot) => tower_query.get(tower_slot).is_ok(),
                 None => false,
             },
+            Action::CycleTargeting => match selection.selected {
+                Some(tower_slot) => match tower_query.get(tower_slot) {
+                    Ok((_, kind, _)) => *kind != TowerKind::Support,
+                    Err(_) => false,
+                },
+                None => false,
+            },
             _ => false,
-        };
+        } && item.target.is_some();
 
         let price = match item.action {
-            Action::BuildTower(tower_type) => match tower_type {
-                // All towers are currently the same price.
-                TowerKind::Basic | TowerKind::Support | TowerKind::Debuff => TOWER_PRICE,
-            },
+            Action::BuildTower(tower_type) => tower_config.get(tower_type).price,
             Action::UpgradeTower => match selection.selected {
                 Some(tower_slot) => match tower_query.get(tower_slot) {
                     Ok((_, _, stats)) => stats.upgrade_price,
@@ -315,17 +487,18 @@ fn update_action_panel(
             _ => 0,
         };
 
-        let disabled = price > currency.current;
+        let locked = match item.action {
+            Action::BuildTower(tower_type) => locked_towers.is_locked(tower_type),
+            _ => false,
+        };
+
+        let disabled = locked || price > currency.current;
         let price_visible = visible && price > 0;
 
-        // visibility
+        // visibility, which is applied gradually by `animate_action_panel_items`
 
-        if let Ok(mut node) = node_query.get_mut(*entity) {
-            node.display = if visible {
-                Display::Flex
-            } else {
-                Display::None
-            };
+        if let Ok(mut transition) = transition_query.get_mut(*entity) {
+            transition.visible = visible;
         }
 
         // price
@@ -343,7 +516,11 @@ fn update_action_panel(
 
                     for child in children.iter() {
                         if price_text_query.get(*child).is_ok() {
-                            *writer.text(*child, 0) = format!("{}", price);
+                            *writer.text(*child, 0) = if locked {
+                                "locked".to_string()
+                            } else {
+                                format!("{}", price)
+                            };
                             writer.color(*child, 0).0 = if disabled {
                                 ui_color::BAD_TEXT.into()
                             } else {
@@ -355,6 +532,34 @@ fn update_action_panel(
             }
         }
 
+        // hint, which shows the selected tower's current targeting mode or damage
+
+        let hint = match item.action {
+            Action::CycleTargeting => selection
+                .selected
+                .and_then(|tower_slot| targeting_query.get(tower_slot).ok())
+                .map(|targeting| format!("Target: {}", targeting.label())),
+            Action::UpgradeTower => selection
+                .selected
+                .and_then(|tower_slot| dps_query.get(tower_slot).ok())
+                .map(|(state, kind, stats, status_effects)| match kind {
+                    TowerKind::Support => action_hint(&item.action).to_string(),
+                    _ => format!(
+                        "Upgrade ({:.1} DPS)",
+                        effective_dps(stats, state, status_effects)
+                    ),
+                }),
+            _ => None,
+        };
+
+        if let (Some(hint), Ok((_, target_children))) = (hint, typing_target_query.get(*entity)) {
+            for target_child in target_children.iter() {
+                if hint_query.get(*target_child).is_ok() {
+                    *writer.text(*target_child, 0) = hint.clone();
+                }
+            }
+        }
+
         // disabledness
         // we could probably roll this into the vis queries at the expense of a headache
 
@@ -368,6 +573,11 @@ fn update_action_panel(
                     };
                     writer.color(*target_child, 1).0 = if disabled {
                         ui_color::BAD_TEXT.into()
+                    } else {
+                        ui_color::PARTIAL_TEXT.into()
+                    };
+                    writer.color(*target_child, 2).0 = if disabled {
+                        ui_color::BAD_TEXT.into()
                     } else {
                         ui_color::NORMAL_TEXT.into()
                     };
@@ -378,7 +588,134 @@ fn update_action_panel(
         // we don't want invisible typing targets to get updated or make
         // sounds or whatever
         if let Ok((mut settings, _)) = typing_target_query.get_mut(*entity) {
-            settings.disabled = !visible;
+            settings.disabled = !visible || locked;
         }
     }
 }
+
+fn update_action_panel_hints(
+    settings: Res<ActionPanelSettings>,
+    mut query: Query<&mut Visibility, With<ActionPanelItemHint>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    for mut visibility in query.iter_mut() {
+        *visibility = if settings.show_hints {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+fn animate_action_panel_items(
+    mut query: Query<(&mut Node, &mut ActionPanelItemTransition)>,
+    settings: Res<ActionPanelSettings>,
+    time: Res<Time<Real>>,
+) {
+    for (mut node, mut transition) in query.iter_mut() {
+        let target = if transition.visible { 1.0 } else { 0.0 };
+
+        if transition.progress == target {
+            continue;
+        }
+
+        transition.progress = if settings.reduce_motion {
+            target
+        } else {
+            let step = time.delta_secs() / ITEM_TRANSITION_DURATION;
+            if target > transition.progress {
+                (transition.progress + step).min(target)
+            } else {
+                (transition.progress - step).max(target)
+            }
+        };
+
+        node.height = Val::Px(ITEM_HEIGHT * transition.progress);
+        node.display = if transition.progress > 0.0 {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{ecs::system::RunSystemOnce, text::TextPlugin};
+
+    use super::*;
+    use crate::data::parse_plain;
+
+    #[test]
+    fn unaffordable_tower_is_shown_as_disabled() {
+        let tower_config: TowerConfig = ron::de::from_str("{ Railgun: (price: 30) }").unwrap();
+        let currency = Currency {
+            current: 20,
+            total_earned: 20,
+        };
+        assert!(!tower_config.can_afford(TowerKind::Railgun, &currency));
+        assert!(tower_config.can_afford(TowerKind::Basic, &currency));
+
+        // For the resources that writing to text needs.
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), TextPlugin));
+        let world = app.world_mut();
+
+        let price_text = world
+            .spawn((
+                Text::new(""),
+                TextFont::default(),
+                TextColor::default(),
+                ActionPanelItemPriceText,
+            ))
+            .id();
+        let price = world
+            .spawn((Node::default(), ActionPanelItemPriceContainer))
+            .add_child(price_text)
+            .id();
+        let item = world
+            .spawn((
+                TypingTargetSettings::default(),
+                ActionPanelItemTransition {
+                    visible: false,
+                    progress: 0.0,
+                },
+            ))
+            .add_child(price)
+            .id();
+        let tower_slot = world.spawn_empty().id();
+
+        world.insert_resource(ActionPanel {
+            actions: vec![ActionPanelItem {
+                icon: Handle::default(),
+                target: parse_plain("railgun").unwrap().pop(),
+                action: Action::BuildTower(TowerKind::Railgun),
+                visible: false,
+            }],
+            entities: vec![item],
+        });
+        world.insert_resource(currency);
+        world.insert_resource(TowerSelection {
+            selected: Some(tower_slot),
+        });
+        world.insert_resource(tower_config);
+        world.insert_resource(LockedTowers::default());
+
+        world.run_system_once(update_action_panel).unwrap();
+
+        assert_eq!(world.get::<Text>(price_text).unwrap().0, "30");
+        assert_eq!(
+            world.get::<TextColor>(price_text).unwrap().0,
+            ui_color::BAD_TEXT.into()
+        );
+        assert!(
+            world
+                .get::<ActionPanelItemTransition>(item)
+                .unwrap()
+                .visible
+        );
+    }
+}
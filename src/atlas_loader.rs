@@ -1,9 +1,12 @@
 use bevy::{
     asset::{io::Reader, AssetLoader, LoadContext},
     prelude::*,
+    utils::HashMap,
 };
 use serde::Deserialize;
 
+use crate::data::{AnimationData, AnimationLocation};
+
 #[derive(Asset, TypePath, Deserialize)]
 struct AtlasImageDescriptor {
     path: String,
@@ -12,12 +15,18 @@ struct AtlasImageDescriptor {
     rows: u32,
     padding: Option<UVec2>,
     offset: Option<UVec2>,
+    /// Animations defined inline, as an alternative to a separate `.anim.ron` file.
+    #[serde(default)]
+    animations: Option<HashMap<String, AnimationLocation>>,
+    #[serde(default)]
+    animation_offset: Option<Vec2>,
 }
 
 #[derive(Asset, TypePath)]
 pub struct AtlasImage {
     pub image: Handle<Image>,
     pub layout: Handle<TextureAtlasLayout>,
+    pub animation: Option<Handle<AnimationData>>,
 }
 
 pub struct AtlasImageLoader;
@@ -46,9 +55,25 @@ impl AssetLoader for AtlasImageLoader {
 
         let layout_handle = load_context.add_labeled_asset("layout".to_string(), layout);
 
+        let animation_handle = desc.animations.map(|animations| {
+            let offset = desc.animation_offset.unwrap_or_default();
+
+            load_context.add_labeled_asset(
+                "animation".to_string(),
+                AnimationData {
+                    rows: desc.rows as usize,
+                    cols: desc.columns as usize,
+                    offset_x: offset.x,
+                    offset_y: offset.y,
+                    animations,
+                },
+            )
+        });
+
         Ok(AtlasImage {
             image: load_context.load(desc.path),
             layout: layout_handle,
+            animation: animation_handle,
         })
     }
 
@@ -56,3 +81,66 @@ impl AssetLoader for AtlasImageLoader {
         &["atlas.ron"]
     }
 }
+
+/// Warns when an atlas's grid doesn't match the dimensions of its image, which would
+/// otherwise silently result in the wrong frames being displayed.
+pub fn validate_atlas_images(
+    mut events: EventReader<AssetEvent<AtlasImage>>,
+    atlas_images: Res<Assets<AtlasImage>>,
+    images: Res<Assets<Image>>,
+    layouts: Res<Assets<TextureAtlasLayout>>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in events.read() {
+        let AssetEvent::LoadedWithDependencies { id } = event else {
+            continue;
+        };
+
+        let Some(atlas_image) = atlas_images.get(*id) else {
+            continue;
+        };
+
+        let (Some(image), Some(layout)) = (
+            images.get(&atlas_image.image),
+            layouts.get(&atlas_image.layout),
+        ) else {
+            continue;
+        };
+
+        let path = asset_server
+            .get_path(*id)
+            .map(|path| path.to_string())
+            .unwrap_or_default();
+
+        let image_size = image.size();
+
+        let Some(needed) = layout
+            .textures
+            .iter()
+            .map(|rect| rect.max)
+            .reduce(|a, b| a.max(b))
+        else {
+            warn!("{}: atlas has no frames.", path);
+            continue;
+        };
+
+        if needed.x > image_size.x || needed.y > image_size.y {
+            warn!(
+                "{}: atlas grid needs an image of at least {}x{}, but the image is {}x{}.",
+                path, needed.x, needed.y, image_size.x, image_size.y
+            );
+            continue;
+        }
+
+        // Leftover space big enough for another frame probably means that a column or
+        // row is missing from the descriptor.
+        let tile_size = layout.textures[0].size();
+        let leftover = image_size - needed;
+        if leftover.x >= tile_size.x || leftover.y >= tile_size.y {
+            warn!(
+                "{}: atlas grid only covers {}x{} of a {}x{} image.",
+                path, needed.x, needed.y, image_size.x, image_size.y
+            );
+        }
+    }
+}
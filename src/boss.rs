@@ -0,0 +1,157 @@
+use bevy::{
+    color::palettes::css::{DARK_RED, RED},
+    prelude::*,
+};
+
+use crate::{
+    enemy::EnemyKind, loading::FontHandles, ui_color, CleanupBeforeNewGame, HitPoints, TaipoState,
+    FONT_SIZE_LABEL,
+};
+
+pub struct BossPlugin;
+
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(TaipoState::Spawn), spawn_boss_bar_container);
+
+        app.add_systems(
+            Update,
+            (spawn_boss_bars, update_boss_bars, remove_boss_bars)
+                .chain()
+                .run_if(in_state(TaipoState::Playing)),
+        );
+    }
+}
+
+/// Marks an enemy that gets its own health bar at the top of the screen.
+#[derive(Component, Debug)]
+pub struct Boss;
+
+#[derive(Component)]
+struct BossBarContainer;
+/// A boss health bar, tracking the boss that it belongs to.
+#[derive(Component)]
+struct BossBar(Entity);
+#[derive(Component)]
+struct BossBarFill;
+
+const BOSS_BAR_WIDTH: f32 = 300.0;
+const BOSS_BAR_HEIGHT: f32 = 12.0;
+const BOSS_BAR_BACKGROUND: Srgba = DARK_RED;
+const BOSS_BAR_FILL: Srgba = RED;
+
+fn spawn_boss_bar_container(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            // Below the HUD and the wave banner
+            top: Val::Px(96.0),
+            width: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            row_gap: Val::Px(4.0),
+            ..default()
+        },
+        BossBarContainer,
+        CleanupBeforeNewGame,
+    ));
+}
+
+fn spawn_boss_bars(
+    mut commands: Commands,
+    boss_query: Query<(Entity, &EnemyKind), Added<Boss>>,
+    container_query: Query<Entity, With<BossBarContainer>>,
+    font_handles: Res<FontHandles>,
+) {
+    let Ok(container) = container_query.get_single() else {
+        return;
+    };
+
+    for (boss, kind) in boss_query.iter() {
+        commands.entity(container).with_children(|parent| {
+            parent
+                .spawn((
+                    Node {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        padding: UiRect::all(Val::Px(4.0)),
+                        ..default()
+                    },
+                    BackgroundColor(ui_color::TRANSPARENT_BACKGROUND.into()),
+                    BossBar(boss),
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new(kind.0.clone()),
+                        TextFont {
+                            font: font_handles.jptext.clone(),
+                            font_size: FONT_SIZE_LABEL,
+                            ..default()
+                        },
+                        TextColor(ui_color::NORMAL_TEXT.into()),
+                    ));
+                    parent
+                        .spawn((
+                            Node {
+                                width: Val::Px(BOSS_BAR_WIDTH),
+                                height: Val::Px(BOSS_BAR_HEIGHT),
+                                ..default()
+                            },
+                            BackgroundColor(BOSS_BAR_BACKGROUND.into()),
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                Node {
+                                    width: Val::Percent(100.0),
+                                    height: Val::Percent(100.0),
+                                    ..default()
+                                },
+                                BackgroundColor(BOSS_BAR_FILL.into()),
+                                BossBarFill,
+                            ));
+                        });
+                });
+        });
+    }
+}
+
+fn update_boss_bars(
+    boss_query: Query<&HitPoints, (With<Boss>, Changed<HitPoints>)>,
+    bar_query: Query<(&BossBar, &Children)>,
+    children_query: Query<&Children>,
+    mut fill_query: Query<&mut Node, With<BossBarFill>>,
+) {
+    for (bar, bar_children) in bar_query.iter() {
+        let Ok(hp) = boss_query.get(bar.0) else {
+            continue;
+        };
+
+        let frac = (hp.current as f32 / hp.max as f32).clamp(0.0, 1.0);
+
+        for child in bar_children
+            .iter()
+            .filter_map(|child| children_query.get(*child).ok())
+            .flatten()
+        {
+            if let Ok(mut node) = fill_query.get_mut(*child) {
+                node.width = Val::Percent(frac * 100.0);
+            }
+        }
+    }
+}
+
+/// Removes the bars of bosses that have died or despawned. The remaining bars shift
+/// up to fill the gap.
+fn remove_boss_bars(
+    mut commands: Commands,
+    bar_query: Query<(Entity, &BossBar)>,
+    boss_query: Query<&HitPoints, With<Boss>>,
+) {
+    for (entity, bar) in bar_query.iter() {
+        let alive = boss_query.get(bar.0).is_ok_and(|hp| hp.current > 0);
+
+        if !alive {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
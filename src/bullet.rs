@@ -1,6 +1,11 @@
 use bevy::prelude::*;
 
-use crate::{enemy::death, layer, Armor, HitPoints, StatusEffect, StatusEffects, TaipoState};
+use crate::{
+    enemy::{death, ArmorRegen, EnemyKind},
+    layer,
+    status_effect::{StatusEffect, StatusEffectKind, StatusEffects},
+    Armor, CleanupBeforeNewGame, HitPoints, TaipoState,
+};
 
 pub struct BulletPlugin;
 
@@ -8,7 +13,9 @@ impl Plugin for BulletPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
             Update,
-            update.before(death).run_if(in_state(TaipoState::Playing)),
+            (update, update_piercing)
+                .before(death)
+                .run_if(in_state(TaipoState::Playing)),
         );
     }
 }
@@ -19,7 +26,8 @@ pub struct Bullet {
     target: Entity,
     damage: u32,
     speed: f32,
-    status_effect: Option<StatusEffect>,
+    status_effects: Vec<StatusEffect>,
+    splash: Option<Splash>,
 }
 impl Bullet {
     pub fn bundle(
@@ -28,7 +36,8 @@ impl Bullet {
         target: Entity,
         damage: u32,
         speed: f32,
-        status_effect: Option<StatusEffect>,
+        status_effects: Vec<StatusEffect>,
+        splash: Option<Splash>,
     ) -> impl Bundle {
         (
             Sprite { image, ..default() },
@@ -37,30 +46,67 @@ impl Bullet {
                 target,
                 damage,
                 speed,
-                status_effect,
+                status_effects,
+                splash,
             },
+            CleanupBeforeNewGame,
         )
     }
 }
 
+/// Makes a bullet fly in a straight line instead of homing in on its target, damaging
+/// each enemy that it passes through until it has hit `remaining` enemies or flown
+/// its full range.
+#[derive(Component)]
+pub struct Piercing {
+    direction: Vec2,
+    remaining: u32,
+    /// The distance left for the bullet to travel.
+    range: f32,
+    hit: Vec<Entity>,
+}
+impl Piercing {
+    pub fn new(direction: Vec2, count: u32, range: f32) -> Self {
+        Self {
+            direction: direction.normalize_or_zero(),
+            remaining: count,
+            range,
+            hit: vec![],
+        }
+    }
+}
+
+/// How close a piercing bullet must pass to an enemy to hit it.
+const PIERCE_HIT_RADIUS: f32 = 12.0;
+
+/// Damage that a bullet also deals to other enemies near its target when it hits.
+#[derive(Clone, Copy, Debug)]
+pub struct Splash {
+    pub radius: f32,
+    /// If true, splash damage decreases from the bullet's full damage at the target to
+    /// nothing at the edge of the radius. Otherwise, every enemy in the radius takes
+    /// full damage.
+    pub falloff: bool,
+}
+
 fn update(
     mut commands: Commands,
     time: Res<Time>,
-    mut query: Query<(Entity, &mut Transform, &mut Bullet)>,
+    mut query: Query<(Entity, &mut Transform, &mut Bullet), Without<Piercing>>,
     mut target_query: Query<
         (
+            Entity,
             &Transform,
             &mut HitPoints,
             &Armor,
             Option<&mut StatusEffects>,
+            Option<&ArmorRegen>,
         ),
-        Without<Bullet>,
+        (With<EnemyKind>, Without<Bullet>),
     >,
 ) {
     for (entity, mut transform, mut bullet) in query.iter_mut() {
-        let Ok((target_transform, mut target_hp, target_armor, target_status)) =
-            target_query.get_mut(bullet.target)
-        else {
+        let Ok((_, target_transform, _, _, _, _)) = target_query.get(bullet.target) else {
             commands.entity(entity).despawn_recursive();
             continue;
         };
@@ -85,20 +131,261 @@ fn update(
 
         // bullet has hit its target
 
-        let mut armor = target_armor.0;
+        let status_effects = std::mem::take(&mut bullet.status_effects);
+
+        if let Some(splash) = bullet.splash {
+            for (enemy, enemy_transform, hp, armor, status, armor_regen) in target_query.iter_mut()
+            {
+                // The target is hit below, and dead enemies stay dead.
+                if enemy == bullet.target || hp.current == 0 {
+                    continue;
+                }
 
-        if let Some(mut target_status) = target_status {
-            armor = armor.saturating_sub(target_status.get_max_sub_armor());
+                let dist = enemy_transform.translation.truncate().distance(target_pos);
+                if dist > splash.radius {
+                    continue;
+                }
 
-            if let Some(bullet_status) = bullet.status_effect.take() {
-                target_status.0.push(bullet_status);
+                let damage = if splash.falloff {
+                    (bullet.damage as f32 * (1.0 - dist / splash.radius)).round() as u32
+                } else {
+                    bullet.damage
+                };
+
+                hit(
+                    damage,
+                    status_effects.clone(),
+                    hp,
+                    armor,
+                    status,
+                    armor_regen,
+                );
             }
         }
 
-        let damage = bullet.damage.saturating_sub(armor);
-
-        target_hp.current = target_hp.current.saturating_sub(damage);
+        if let Ok((_, _, hp, armor, status, armor_regen)) = target_query.get_mut(bullet.target) {
+            hit(
+                bullet.damage,
+                status_effects,
+                hp,
+                armor,
+                status,
+                armor_regen,
+            );
+        }
 
         commands.entity(entity).despawn_recursive();
     }
 }
+
+fn update_piercing(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Transform, &Bullet, &mut Piercing)>,
+    mut target_query: Query<
+        (
+            Entity,
+            &Transform,
+            &mut HitPoints,
+            &Armor,
+            Option<&mut StatusEffects>,
+            Option<&ArmorRegen>,
+        ),
+        (With<EnemyKind>, Without<Bullet>),
+    >,
+) {
+    for (entity, mut transform, bullet, mut piercing) in query.iter_mut() {
+        let delta = time.delta_secs();
+        let step = (bullet.speed * delta).min(piercing.range);
+
+        let start = transform.translation.truncate();
+        let direction = piercing.direction;
+
+        transform.translation += (direction * step).extend(0.);
+        piercing.range -= step;
+
+        // ten radians per second, clockwise
+        transform.rotate(Quat::from_rotation_z(-10.0 * delta));
+
+        // Check everything along the path travelled this frame, so that the bullet
+        // can't skip over enemies when the frame rate is low.
+        let mut hits = target_query
+            .iter()
+            .filter(|(enemy, _, hp, _, _, _)| hp.current > 0 && !piercing.hit.contains(enemy))
+            .filter_map(|(enemy, enemy_transform, _, _, _, _)| {
+                let pos = enemy_transform.translation.truncate();
+                let along = (pos - start).dot(direction).clamp(0.0, step);
+
+                (pos.distance(start + direction * along) <= PIERCE_HIT_RADIUS)
+                    .then_some((enemy, along))
+            })
+            .collect::<Vec<_>>();
+
+        // Enemies reached first are hit first.
+        hits.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        for (enemy, _) in hits {
+            if piercing.remaining == 0 {
+                break;
+            }
+
+            if let Ok((_, _, hp, armor, status, armor_regen)) = target_query.get_mut(enemy) {
+                hit(
+                    bullet.damage,
+                    bullet.status_effects.clone(),
+                    hp,
+                    armor,
+                    status,
+                    armor_regen,
+                );
+            }
+
+            piercing.hit.push(enemy);
+            piercing.remaining -= 1;
+        }
+
+        if piercing.remaining == 0 || piercing.range <= 0.0 {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Applies `status_effects` to an enemy and deals `damage` to it, reduced by its armor.
+fn hit(
+    damage: u32,
+    mut status_effects: Vec<StatusEffect>,
+    mut hp: Mut<HitPoints>,
+    armor: &Armor,
+    status: Option<Mut<StatusEffects>>,
+    armor_regen: Option<&ArmorRegen>,
+) {
+    let mut armor = armor.0;
+
+    if let Some(mut status) = status {
+        armor = armor.saturating_sub(status.get_max_sub_armor());
+
+        // Enemies with regenerating armor shrug off armor debuffs after a while.
+        if let Some(armor_regen) = armor_regen {
+            for effect in status_effects.iter_mut() {
+                if matches!(effect.kind, StatusEffectKind::SubArmor(_)) && effect.timer.is_none() {
+                    effect.timer = Some(Timer::from_seconds(armor_regen.0, TimerMode::Once));
+                }
+            }
+        }
+
+        for effect in status_effects {
+            status.add(effect);
+        }
+    }
+
+    let damage = damage.saturating_sub(armor);
+
+    hp.current = hp.current.saturating_sub(damage);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::{ecs::system::RunSystemOnce, state::app::StatesPlugin, time::TimeUpdateStrategy};
+
+    use super::*;
+    use crate::status_effect::StatusEffectPlugin;
+
+    #[test]
+    fn piercing_bullet_hits_limited_enemies() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+                0.1,
+            )))
+            .add_systems(Update, update_piercing);
+
+        let enemies = [50.0, 100.0, 150.0].map(|x| {
+            app.world_mut()
+                .spawn((
+                    EnemyKind::default(),
+                    Transform::from_xyz(x, 0.0, 0.0),
+                    HitPoints::full(10),
+                    Armor::default(),
+                ))
+                .id()
+        });
+
+        let bullet = app
+            .world_mut()
+            .spawn((
+                Bullet::bundle(Vec2::ZERO, default(), enemies[0], 3, 10000.0, vec![], None),
+                Piercing::new(Vec2::X, 2, 200.0),
+            ))
+            .id();
+
+        for _ in 0..3 {
+            app.update();
+        }
+
+        let hp = enemies.map(|enemy| app.world().get::<HitPoints>(enemy).unwrap().current);
+        assert_eq!(hp, [7, 7, 10]);
+        assert!(app.world().get_entity(bullet).is_err());
+    }
+
+    #[test]
+    fn regenerating_armor_is_restored_after_debuff_expires() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin, StatusEffectPlugin))
+            .insert_state(TaipoState::Playing)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+                0.1,
+            )));
+
+        let enemy = app
+            .world_mut()
+            .spawn((
+                HitPoints::full(10),
+                Armor(2),
+                ArmorRegen(1.0),
+                StatusEffects::default(),
+            ))
+            .id();
+
+        let hit_once =
+            |mut query: Query<(&mut HitPoints, &Armor, &mut StatusEffects, &ArmorRegen)>| {
+                for (hp, armor, status_effects, armor_regen) in query.iter_mut() {
+                    let debuff = StatusEffect {
+                        kind: StatusEffectKind::SubArmor(2),
+                        timer: None,
+                    };
+                    hit(
+                        3,
+                        vec![debuff],
+                        hp,
+                        armor,
+                        Some(status_effects),
+                        Some(armor_regen),
+                    );
+                }
+            };
+
+        // The first hit is reduced by full armor, and the debuff applies afterwards.
+        app.world_mut().run_system_once(hit_once).unwrap();
+        assert_eq!(app.world().get::<HitPoints>(enemy).unwrap().current, 9);
+
+        // While debuffed, hits ignore armor.
+        app.world_mut().run_system_once(hit_once).unwrap();
+        assert_eq!(app.world().get::<HitPoints>(enemy).unwrap().current, 6);
+
+        for _ in 0..15 {
+            app.update();
+        }
+        assert!(app
+            .world()
+            .get::<StatusEffects>(enemy)
+            .unwrap()
+            .0
+            .is_empty());
+
+        // Once the debuffs wear off, hits are reduced by full armor again.
+        app.world_mut().run_system_once(hit_once).unwrap();
+        assert_eq!(app.world().get::<HitPoints>(enemy).unwrap().current, 5);
+    }
+}
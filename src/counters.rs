@@ -0,0 +1,190 @@
+use anyhow::anyhow;
+use serde::Deserialize;
+
+use crate::{japanese_parser, typing::TypingTarget};
+
+/// Describes a generated word list for practicing dates, times, and counters, as
+/// written in a `.counters.ron` file. Each range is inclusive.
+#[derive(Debug, Deserialize)]
+#[serde(rename = "CounterWordList")]
+pub struct CounterWordList {
+    /// Every date in these months, e.g. "7月7日".
+    #[serde(default)]
+    pub dates: Option<(u32, u32)>,
+    /// Hours, e.g. "4時".
+    #[serde(default)]
+    pub hours: Option<(u32, u32)>,
+    /// Things counted with つ, e.g. "3つ".
+    #[serde(default)]
+    pub things: Option<(u32, u32)>,
+}
+
+/// A word that is written after a number to count something.
+struct Counter {
+    suffix: &'static str,
+    reading: &'static str,
+    /// Readings of the ones digit that differ from `ONES` when followed by this counter.
+    ones: &'static [(u32, &'static str)],
+    /// Readings of the number and of the counter for numbers that can't be read by
+    /// joining their parts.
+    irregular: &'static [(u32, &'static str, &'static str)],
+    /// The largest number that can be counted.
+    max: u32,
+}
+
+const ONES: [&str; 10] = [
+    "",
+    "いち",
+    "に",
+    "さん",
+    "よん",
+    "ご",
+    "ろく",
+    "なな",
+    "はち",
+    "きゅう",
+];
+
+const MONTH: Counter = Counter {
+    suffix: "月",
+    reading: "がつ",
+    ones: &[(4, "し"), (7, "しち"), (9, "く")],
+    irregular: &[],
+    max: 12,
+};
+
+const DAY: Counter = Counter {
+    suffix: "日",
+    reading: "にち",
+    ones: &[(7, "しち"), (9, "く")],
+    irregular: &[
+        (1, "つい", "たち"),
+        (2, "ふつ", "か"),
+        (3, "みっ", "か"),
+        (4, "よっ", "か"),
+        (5, "いつ", "か"),
+        (6, "むい", "か"),
+        (7, "なの", "か"),
+        (8, "よう", "か"),
+        (9, "ここの", "か"),
+        (10, "とお", "か"),
+        (14, "じゅうよっ", "か"),
+        (20, "はつ", "か"),
+        (24, "にじゅうよっ", "か"),
+    ],
+    max: 31,
+};
+
+const HOUR: Counter = Counter {
+    suffix: "時",
+    reading: "じ",
+    ones: &[(4, "よ"), (7, "しち"), (9, "く")],
+    irregular: &[],
+    max: 24,
+};
+
+// Ten is just "とお", without the counter, so it doesn't fit here.
+const THING: Counter = Counter {
+    suffix: "つ",
+    reading: "つ",
+    ones: &[],
+    irregular: &[
+        (1, "ひと", "つ"),
+        (2, "ふた", "つ"),
+        (3, "みっ", "つ"),
+        (4, "よっ", "つ"),
+        (5, "いつ", "つ"),
+        (6, "むっ", "つ"),
+        (7, "なな", "つ"),
+        (8, "やっ", "つ"),
+        (9, "ここの", "つ"),
+    ],
+    max: 9,
+};
+
+/// Days in each month, including leap days.
+const DAYS_IN_MONTH: [u32; 12] = [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+impl Counter {
+    /// Returns `n` with this counter, written like the word lists, e.g. "4(よ)時(じ)".
+    fn write(&self, n: u32) -> String {
+        let (number, counter) = match self.irregular.iter().find(|(i, _, _)| *i == n) {
+            Some((_, number, counter)) => (number.to_string(), *counter),
+            None => (self.read_number(n), self.reading),
+        };
+
+        // A sokuon can't end a reading, so a number read with one shares its reading
+        // with the counter, e.g. "3日(みっか)".
+        if number.ends_with('っ') {
+            format!("{}{}({}{})", n, self.suffix, number, counter)
+        } else if self.suffix == counter {
+            // Kana counters are typed as they are written.
+            format!("{}({}){}", n, number, self.suffix)
+        } else {
+            format!("{}({}){}({})", n, number, self.suffix, counter)
+        }
+    }
+
+    fn read_number(&self, n: u32) -> String {
+        let (tens, ones) = (n / 10, n % 10);
+
+        let mut reading = match tens {
+            0 => String::new(),
+            1 => "じゅう".to_string(),
+            _ => format!("{}じゅう", ONES[tens as usize]),
+        };
+
+        if ones > 0 {
+            let ones_reading = self
+                .ones
+                .iter()
+                .find(|(i, _)| *i == ones)
+                .map_or(ONES[ones as usize], |(_, reading)| *reading);
+
+            reading.push_str(ones_reading);
+        }
+
+        reading
+    }
+
+    fn check_range(&self, (start, end): (u32, u32)) -> anyhow::Result<()> {
+        if start < 1 || end > self.max || start > end {
+            return Err(anyhow!(
+                "range for {} must be within 1 to {}.",
+                self.suffix,
+                self.max
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl CounterWordList {
+    /// Generates a typing target for every date, time, and counter described.
+    pub fn generate(&self) -> anyhow::Result<Vec<TypingTarget>> {
+        let mut words = vec![];
+
+        if let Some(range) = self.dates {
+            MONTH.check_range(range)?;
+
+            for month in range.0..=range.1 {
+                for day in 1..=DAYS_IN_MONTH[month as usize - 1] {
+                    words.push(format!("{}{}", MONTH.write(month), DAY.write(day)));
+                }
+            }
+        }
+
+        for (counter, range) in [(&HOUR, self.hours), (&THING, self.things)] {
+            let Some(range) = range else {
+                continue;
+            };
+
+            counter.check_range(range)?;
+
+            words.extend((range.0..=range.1).map(|n| counter.write(n)));
+        }
+
+        japanese_parser::parse(&words.join("\n"))
+    }
+}
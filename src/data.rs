@@ -5,16 +5,91 @@ use bevy::{
     utils::HashMap,
 };
 
+use anyhow::anyhow;
 use bevy_common_assets::ron::RonAssetPlugin;
 use serde::Deserialize;
 
-use crate::{japanese_parser, TypingTarget};
+use crate::{
+    counters::CounterWordList,
+    japanese_parser::{self, ParserOptions},
+    loading::{FontHandles, GameDataHandles},
+    tower::TowerConfig,
+    typing::{DEFAULT_DIFFICULTY, DEFAULT_WEIGHT},
+    Action, TaipoState, TypingTarget,
+};
 
-// Tower stats, prices, etc should go in here eventually
 #[derive(Debug, Deserialize)]
 #[serde(rename = "GameData")]
 pub struct RawGameData {
     pub word_list_menu: Vec<WordListMenuItem>,
+    #[serde(default)]
+    pub towers: TowerConfig,
+    /// Words that can be typed at any time to perform an action, keyed by the names
+    /// in `FIXED_ACTIONS`.
+    ///
+    /// Words containing non-ASCII characters are parsed like Japanese word lists, so
+    /// they may be written as e.g. `"たすけて(tasukete)"`.
+    #[serde(default)]
+    pub fixed_words: HashMap<String, String>,
+}
+
+/// Actions that can be performed at any time by typing a word: the name that the word
+/// is given in `game.ron`, the word to use if `game.ron` doesn't give one, and the
+/// action.
+const FIXED_ACTIONS: &[(&str, &str, Action)] = &[
+    ("help", "help", Action::SwitchLanguageMode),
+    ("mute", "mute", Action::ToggleMute),
+    ("minimap", "minimap", Action::ToggleMinimap),
+    ("undo", "undo", Action::Undo),
+    ("start", "start", Action::StartWave),
+    ("call", "call", Action::CallWave),
+    ("furigana", "furigana", Action::ToggleFurigana),
+];
+
+/// The parsed words for each of the `FIXED_ACTIONS`, with their actions.
+#[derive(Debug, Clone)]
+pub struct FixedTargets(pub Vec<(TypingTarget, Action)>);
+impl FixedTargets {
+    /// Parses the words for each of the `FIXED_ACTIONS`, using the words in `words`,
+    /// keyed by name, where they are given.
+    pub fn new(words: &HashMap<String, String>) -> anyhow::Result<Self> {
+        if let Some(name) = words
+            .keys()
+            .find(|name| !FIXED_ACTIONS.iter().any(|(fixed, _, _)| fixed == name))
+        {
+            return Err(anyhow!("unknown fixed word \"{}\".", name));
+        }
+
+        let targets = Self(
+            FIXED_ACTIONS
+                .iter()
+                .map(|(name, default, action)| {
+                    let word = words.get(*name).map_or(*default, String::as_str);
+                    Ok((parse_fixed_word(word)?, action.clone()))
+                })
+                .collect::<anyhow::Result<_>>()?,
+        );
+
+        for (i, a) in targets.iter().enumerate() {
+            if targets.iter().skip(i + 1).any(|b| a.is_ambiguous_with(b)) {
+                return Err(anyhow!(
+                    "fixed word \"{}\" is used more than once.",
+                    a.typed_chunks.join("")
+                ));
+            }
+        }
+
+        Ok(targets)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TypingTarget> {
+        self.0.iter().map(|(target, _)| target)
+    }
+}
+impl Default for FixedTargets {
+    fn default() -> Self {
+        Self::new(&HashMap::default()).unwrap()
+    }
 }
 
 #[derive(Component, Debug, Deserialize, Clone)]
@@ -28,16 +103,12 @@ pub struct WordList {
     pub words: Vec<TypingTarget>,
 }
 
-#[derive(Debug, Deserialize)]
-pub enum InputKind {
-    Japanese,
-    Plain,
-}
-
 #[derive(Debug, Asset, TypePath, Default)]
 pub struct GameData {
     pub word_list_menu: Vec<WordListMenuItem>,
     pub word_lists: HashMap<String, Handle<WordList>>,
+    pub towers: TowerConfig,
+    pub fixed_targets: FixedTargets,
 }
 
 #[derive(Debug, Asset, Deserialize, TypePath)]
@@ -47,6 +118,8 @@ pub struct AnimationData {
     pub cols: usize,
     pub offset_x: f32,
     pub offset_y: f32,
+    /// Animations by name. Each enemy needs `walk_*`, `idle_*` and `atk_*` animations
+    /// for the `up`, `down` and `right` directions, and may have a `death` animation.
     pub animations: HashMap<String, AnimationLocation>,
 }
 
@@ -65,7 +138,11 @@ impl Plugin for GameDataPlugin {
             .register_asset_loader(GameDataLoader)
             .register_asset_loader(PlainWordListLoader)
             .register_asset_loader(JapaneseWordListLoader)
+            .register_asset_loader(JapaneseLongVowelWordListLoader)
+            .register_asset_loader(CounterWordListLoader)
             .add_plugins(RonAssetPlugin::<AnimationData>::new(&["anim.ron"]));
+
+        app.add_systems(OnExit(TaipoState::Load), check_word_list_glyphs);
     }
 }
 #[derive(Default)]
@@ -74,6 +151,12 @@ pub struct GameDataLoader;
 pub struct PlainWordListLoader;
 #[derive(Default)]
 pub struct JapaneseWordListLoader;
+/// Loads Japanese word lists where "ー" is typed by repeating the preceding vowel.
+#[derive(Default)]
+pub struct JapaneseLongVowelWordListLoader;
+/// Loads word lists that are generated from a `CounterWordList`.
+#[derive(Default)]
+pub struct CounterWordListLoader;
 
 impl AssetLoader for PlainWordListLoader {
     type Asset = WordList;
@@ -121,6 +204,55 @@ impl AssetLoader for JapaneseWordListLoader {
     }
 }
 
+impl AssetLoader for JapaneseLongVowelWordListLoader {
+    type Asset = WordList;
+    type Settings = ();
+    type Error = anyhow::Error;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let words = japanese_parser::parse_with_options(
+            std::str::from_utf8(&bytes)?,
+            ParserOptions { long_vowels: true },
+        )?;
+        let list = WordList { words };
+        Ok(list)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["long.jp.txt"]
+    }
+}
+
+impl AssetLoader for CounterWordListLoader {
+    type Asset = WordList;
+    type Settings = ();
+    type Error = anyhow::Error;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let words = ron::de::from_bytes::<CounterWordList>(&bytes)?.generate()?;
+        let list = WordList { words };
+        Ok(list)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["counters.ron"]
+    }
+}
+
 impl AssetLoader for GameDataLoader {
     type Asset = GameData;
     type Settings = ();
@@ -153,6 +285,8 @@ impl AssetLoader for GameDataLoader {
         let game_data = GameData {
             word_list_menu: raw_game_data.word_list_menu,
             word_lists: word_list_handles,
+            towers: raw_game_data.towers,
+            fixed_targets: FixedTargets::new(&raw_game_data.fixed_words)?,
         };
 
         Ok(game_data)
@@ -167,13 +301,147 @@ pub fn parse_plain(input: &str) -> Result<Vec<TypingTarget>, anyhow::Error> {
     Ok(input
         .lines()
         .map(|l| l.trim())
-        .filter(|l| !l.is_empty())
+        .filter(|l| !l.is_empty() && !japanese_parser::is_comment(l))
         .map(|l| {
-            let chars = l.chars().map(|c| c.to_string()).collect::<Vec<_>>();
+            let (word, weight) = split_weight(l);
+            let (word, difficulty) = split_difficulty(word);
+            let chars = word.chars().map(|c| c.to_string()).collect::<Vec<_>>();
             TypingTarget {
                 displayed_chunks: chars.clone(),
                 typed_chunks: chars,
+                alt_typed_chunks: vec![],
+                readings: vec![],
+                difficulty,
+                weight,
             }
         })
         .collect::<Vec<_>>())
 }
+
+/// Splits a trailing weight tag like `*2` from a word.
+fn split_weight(line: &str) -> (&str, u32) {
+    line.rsplit_once('*')
+        .and_then(|(word, tag)| Some((word.trim_end(), tag.parse().ok()?)))
+        .filter(|(word, weight)| !word.is_empty() && *weight > 0)
+        .unwrap_or((line, DEFAULT_WEIGHT))
+}
+
+/// Splits a trailing difficulty tag like `[3]` from a word.
+fn split_difficulty(line: &str) -> (&str, u32) {
+    line.strip_suffix(']')
+        .and_then(|rest| rest.rsplit_once('['))
+        .and_then(|(word, tag)| Some((word.trim_end(), tag.parse().ok()?)))
+        .filter(|(word, _)| !word.is_empty())
+        .unwrap_or((line, DEFAULT_DIFFICULTY))
+}
+
+fn parse_fixed_word(word: &str) -> Result<TypingTarget, anyhow::Error> {
+    let mut targets = if word.is_ascii() {
+        parse_plain(word)?
+    } else {
+        japanese_parser::parse(word)?
+    };
+
+    if targets.len() != 1 {
+        return Err(anyhow!("fixed word \"{}\" must be a single word.", word));
+    }
+
+    Ok(targets.remove(0))
+}
+
+/// Warns about any words that contain characters that our font can't display, so that
+/// word list authors can find out about them without playing through the list.
+fn check_word_list_glyphs(
+    game_data_handles: Res<GameDataHandles>,
+    game_data_assets: Res<Assets<GameData>>,
+    word_list_assets: Res<Assets<WordList>>,
+    font_handles: Res<FontHandles>,
+    fonts: Res<Assets<Font>>,
+) {
+    let Some(font) = fonts.get(&font_handles.jptext) else {
+        return;
+    };
+
+    let face = match ttf_parser::Face::parse(&font.data, 0) {
+        Ok(face) => face,
+        Err(err) => {
+            warn!("Unable to check word lists for missing glyphs: {}", err);
+            return;
+        }
+    };
+
+    let Some(game_data) = game_data_assets.get(&game_data_handles.game) else {
+        return;
+    };
+
+    for (path, handle) in game_data.word_lists.iter() {
+        let Some(word_list) = word_list_assets.get(handle) else {
+            continue;
+        };
+
+        for word in word_list.words.iter() {
+            let missing = word
+                .displayed_chunks
+                .iter()
+                .flat_map(|chunk| chunk.chars())
+                .filter(|c| !c.is_whitespace() && face.glyph_index(*c).is_none())
+                .collect::<String>();
+
+            if !missing.is_empty() {
+                warn!(
+                    "{}: \"{}\" contains characters that can't be displayed: {}",
+                    path,
+                    word.displayed_chunks.join(""),
+                    missing
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_shipped_word_lists() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/data/word_list");
+
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_str().unwrap().to_owned();
+            let input = std::fs::read_to_string(&path).unwrap();
+
+            let words = if name.ends_with(".counters.ron") {
+                ron::de::from_str::<CounterWordList>(&input)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|list| list.generate())
+            } else if name.ends_with(".long.jp.txt") {
+                japanese_parser::parse_with_options(&input, ParserOptions { long_vowels: true })
+            } else if name.ends_with(".jp.txt") {
+                japanese_parser::parse(&input)
+            } else {
+                parse_plain(&input)
+            };
+
+            let words = words.unwrap_or_else(|err| panic!("{}: {}", name, err));
+            assert!(!words.is_empty(), "{} has no words", name);
+        }
+    }
+
+    #[test]
+    fn shipped_fixed_words_cover_every_action() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/data/game.ron");
+        let input = std::fs::read_to_string(path).unwrap();
+        let raw: RawGameData = ron::de::from_str(&input).unwrap();
+
+        let targets = FixedTargets::new(&raw.fixed_words).unwrap();
+        assert_eq!(targets.0.len(), FIXED_ACTIONS.len());
+    }
+
+    #[test]
+    fn unknown_fixed_word_is_rejected() {
+        let words = HashMap::from_iter([("hlep".to_string(), "help".to_string())]);
+        assert!(FixedTargets::new(&words).is_err());
+    }
+}
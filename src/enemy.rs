@@ -1,27 +1,39 @@
+use std::time::Duration;
+
 use bevy::{ecs::query::Or, prelude::*};
 
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
 use crate::{
     action_panel::ActionPanel,
+    atlas_loader::AtlasImage,
+    game_over::RunStats,
     healthbar::HealthBar,
     layer,
-    loading::{EnemyAnimationHandles, TextureHandles},
-    update_currency_text, AfterUpdate, AnimationData, Armor, Currency, Goal, HitPoints, Speed,
-    StatusDownSprite, StatusEffects, StatusUpSprite, TaipoState,
+    loading::{EnemyAnimationHandles, EnemyAtlasHandles, TextureHandles},
+    map::YSort,
+    sfx::{Sfx, SfxEvent},
+    status_effect::{update_status_effect_sprites, StatusEffectSprite, StatusEffects},
+    typing::{Combo, TypingTarget, TypingTargetBundle, TypingTargetText, TypingTargets},
+    update_currency_text, AfterUpdate, AnimationData, Armor, CleanupBeforeNewGame, Currency,
+    GameRng, Goal, HitPoints, Speed, TaipoState,
 };
 
 pub struct EnemyPlugin;
 
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<EnemySettings>();
+
         app.add_systems(
             Update,
             (
                 animate,
                 movement,
+                ramp_armor,
                 deal_damage,
                 death.before(update_currency_text),
+                remove_corpse_typing_targets.after(death),
             )
                 .run_if(in_state(TaipoState::Playing)),
         );
@@ -32,6 +44,23 @@ impl Plugin for EnemyPlugin {
         );
     }
 }
+#[derive(Resource)]
+pub struct EnemySettings {
+    /// The maximum amount, in radians, that corpses are randomly rotated by when
+    /// enemies die. Zero leaves corpses upright.
+    pub corpse_rotation: f32,
+    /// If true, newly spawned enemies have a typing target that kills them when typed.
+    pub typing_targets: bool,
+}
+impl Default for EnemySettings {
+    fn default() -> Self {
+        Self {
+            corpse_rotation: 0.2,
+            typing_targets: false,
+        }
+    }
+}
+
 #[derive(Bundle, Default)]
 pub struct EnemyBundle {
     pub kind: EnemyKind,
@@ -45,9 +74,56 @@ pub struct EnemyBundle {
     pub status_effects: StatusEffects,
     pub armor: Armor,
     pub speed: Speed,
+    pub target_goal: TargetGoal,
     pub health_bar: HealthBar,
+    pub bounty: Bounty,
+}
+
+/// The currency awarded for killing this enemy, not including combo bonuses.
+#[derive(Component, Debug)]
+pub struct Bounty(pub u32);
+impl Default for Bounty {
+    fn default() -> Self {
+        Self(DEFAULT_BOUNTY)
+    }
 }
 
+pub const DEFAULT_BOUNTY: u32 = 2;
+pub const BOSS_BOUNTY: u32 = 20;
+
+/// Marks an enemy that flies over the map, which can only be shot by towers with
+/// `TowerStats::anti_air`.
+#[derive(Component, Debug)]
+pub struct Flying;
+
+/// When this enemy dies, it splits into `count` enemies of `kind` which continue
+/// along its path. The new enemies don't split again.
+#[derive(Component, Clone, Debug)]
+pub struct SplitsInto {
+    pub kind: String,
+    pub count: usize,
+}
+
+/// The maximum distance that enemies spawned by `SplitsInto` are randomly offset
+/// from the position of the enemy that split.
+const SPLIT_SPREAD: f32 = 8.0;
+
+/// Armor-reducing status effects applied to this enemy wear off after this many
+/// seconds, restoring its armor.
+#[derive(Component, Debug)]
+pub struct ArmorRegen(pub f32);
+
+/// Increases this enemy's armor by one each time the timer finishes, up to `max`.
+#[derive(Component, Debug)]
+pub struct ArmorRamp {
+    pub timer: Timer,
+    pub max: u32,
+}
+
+/// The index of the `Goal` that an enemy attacks when it reaches the end of its path.
+#[derive(Component, Default, Debug)]
+pub struct TargetGoal(pub i32);
+
 #[derive(Component, Debug, Default)]
 pub enum AnimationState {
     #[default]
@@ -101,6 +177,9 @@ impl Default for AnimationTimer {
         Self(Timer::from_seconds(0.1, TimerMode::Repeating))
     }
 }
+/// The movement speed at which the walk animation plays at its normal rate.
+const WALK_ANIMATION_SPEED: f32 = 20.0;
+
 #[derive(Component)]
 pub struct AttackTimer(pub Timer);
 impl Default for AttackTimer {
@@ -110,20 +189,104 @@ impl Default for AttackTimer {
 }
 
 pub fn death(
-    mut query: Query<(&mut AnimationState, &mut Transform, &HitPoints), Changed<HitPoints>>,
+    mut commands: Commands,
+    mut query: Query<
+        (
+            &mut AnimationState,
+            &mut Transform,
+            &HitPoints,
+            Option<&mut YSort>,
+            &Bounty,
+            Option<&SplitsInto>,
+            (&EnemyPath, &Armor, &Speed, &TargetGoal, Has<Flying>),
+        ),
+        Changed<HitPoints>,
+    >,
     mut currency: ResMut<Currency>,
     mut action_panel: ResMut<ActionPanel>,
+    mut rng: ResMut<GameRng>,
+    settings: Res<EnemySettings>,
+    combo: Res<Combo>,
+    atlas_handles: Res<EnemyAtlasHandles>,
+    atlas_images: Res<Assets<AtlasImage>>,
+    mut run_stats: ResMut<RunStats>,
 ) {
-    for (mut state, mut transform, hp) in query.iter_mut() {
+    for (
+        mut state,
+        mut transform,
+        hp,
+        y_sort,
+        bounty,
+        splits_into,
+        (path, armor, speed, goal, flying),
+    ) in query.iter_mut()
+    {
         if hp.current == 0 && !matches!(*state, AnimationState::Corpse) {
             *state = AnimationState::Corpse;
+            run_stats.enemies_killed += 1;
+
+            if let Some(splits_into) = splits_into {
+                let atlas_image = atlas_images
+                    .get(&atlas_handles.by_key(&splits_into.kind))
+                    .unwrap();
+
+                for _ in 0..splits_into.count {
+                    let offset = Vec2::new(
+                        rng.0.gen_range(-SPLIT_SPREAD..=SPLIT_SPREAD),
+                        rng.0.gen_range(-SPLIT_SPREAD..=SPLIT_SPREAD),
+                    );
+                    let pos = transform.translation.truncate() + offset;
+
+                    let mut child = commands.spawn((
+                        Sprite {
+                            image: atlas_image.image.clone(),
+                            texture_atlas: Some(TextureAtlas {
+                                layout: atlas_image.layout.clone(),
+                                index: 0,
+                            }),
+                            ..default()
+                        },
+                        Transform::from_translation(pos.extend(transform.translation.z)),
+                        EnemyBundle {
+                            kind: EnemyKind(splits_into.kind.clone()),
+                            path: EnemyPath {
+                                path: path.path.clone(),
+                                path_index: path.path_index,
+                            },
+                            // The pieces are weaker than the whole
+                            hit_points: HitPoints::full((hp.max / 2).max(1)),
+                            armor: Armor(armor.0),
+                            speed: Speed(speed.0),
+                            target_goal: TargetGoal(goal.0),
+                            health_bar: HealthBar {
+                                offset: Vec2::new(0.0, 14.0),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        CleanupBeforeNewGame,
+                    ));
+
+                    if flying {
+                        child.insert(Flying);
+                    } else if let Some(y_sort) = &y_sort {
+                        child.insert(YSort(y_sort.0));
+                    }
+                }
+            }
 
-            let mut rng = thread_rng();
-            transform.rotate(Quat::from_rotation_z(rng.gen_range(-0.2..0.2)));
+            if settings.corpse_rotation > 0.0 {
+                let max = settings.corpse_rotation;
+                transform.rotate(Quat::from_rotation_z(rng.0.gen_range(-max..max)));
+            }
             transform.translation.z = layer::CORPSE;
+            if let Some(mut y_sort) = y_sort {
+                y_sort.0 = layer::CORPSE;
+            }
 
-            currency.current = currency.current.saturating_add(2);
-            currency.total_earned = currency.total_earned.saturating_add(2);
+            let reward = bounty.0 + combo.bonus();
+            currency.current = currency.current.saturating_add(reward);
+            currency.total_earned = currency.total_earned.saturating_add(reward);
 
             // Force an action panel update
             action_panel.set_changed();
@@ -133,17 +296,22 @@ pub fn death(
 
 fn deal_damage(
     time: Res<Time>,
-    mut query: Query<(&mut AttackTimer, &AnimationState)>,
-    mut goal_query: Query<&mut HitPoints, With<Goal>>,
+    mut query: Query<(&mut AttackTimer, &AnimationState, &TargetGoal)>,
+    mut goal_query: Query<(&Goal, &mut HitPoints)>,
+    mut sfx_events: EventWriter<SfxEvent>,
 ) {
     // TODO this should really sync up with the animations somehow
 
-    for (mut timer, state) in query.iter_mut() {
+    for (mut timer, state, target_goal) in query.iter_mut() {
         if let AnimationState::Attacking = state {
             timer.0.tick(time.delta());
             if timer.0.finished() {
-                for mut hp in goal_query.iter_mut() {
+                for (_, mut hp) in goal_query
+                    .iter_mut()
+                    .filter(|(goal, _)| goal.index == target_goal.0)
+                {
                     hp.current = hp.current.saturating_sub(1);
+                    sfx_events.send(SfxEvent(Sfx::GoalDamage));
                 }
             }
         }
@@ -162,87 +330,46 @@ fn status_effect_appearance(
         ),
         Or<(Changed<AnimationState>, Changed<StatusEffects>)>,
     >,
-    up_query: Query<Entity, With<StatusUpSprite>>,
-    down_query: Query<Entity, With<StatusDownSprite>>,
+    sprite_query: Query<(), With<StatusEffectSprite>>,
     texture_handles: Res<TextureHandles>,
 ) {
     for (entity, status_effects, state, healthbar, children) in query.iter() {
-        let dead = matches!(state, AnimationState::Corpse);
-
-        let down = status_effects.get_max_sub_armor() > 0;
-        let up = status_effects.get_total_add_damage() > 0;
-
-        let mut down_sprite = None;
-        let mut up_sprite = None;
+        // Corpses don't need to show their status effects.
+        let status_effects = (!matches!(state, AnimationState::Corpse)).then_some(status_effects);
+
+        update_status_effect_sprites(
+            &mut commands,
+            entity,
+            status_effects,
+            children,
+            &sprite_query,
+            &texture_handles,
+            Vec2::new(healthbar.size.x / 2.0 + 6.0, healthbar.offset.y),
+        );
+    }
+}
 
-        if let Some(children) = children {
-            for child in children.iter() {
-                if let Ok(ent) = down_query.get(*child) {
-                    down_sprite = Some(ent);
-                }
-                if let Ok(ent) = up_query.get(*child) {
-                    up_sprite = Some(ent);
-                }
-            }
+/// Returns the typing targets of enemies that died to the word lists, so that the
+/// dead don't keep a word that could be used elsewhere.
+fn remove_corpse_typing_targets(
+    mut commands: Commands,
+    mut typing_targets: ResMut<TypingTargets>,
+    query: Query<(Entity, &AnimationState, &TypingTarget, &Children), Changed<AnimationState>>,
+    text_query: Query<(), With<TypingTargetText>>,
+) {
+    for (entity, state, target, children) in query.iter() {
+        if !matches!(state, AnimationState::Corpse) {
+            continue;
         }
 
-        if dead {
-            if let Some(down_ent) = down_sprite {
-                commands.entity(down_ent).despawn();
-            }
-            if let Some(up_ent) = up_sprite {
-                commands.entity(up_ent).despawn();
-            }
-            break;
-        }
+        typing_targets.push_back(target.clone());
 
-        match (down, down_sprite) {
-            (true, None) => {
-                let down_ent = commands
-                    .spawn((
-                        Sprite {
-                            image: texture_handles.status_down.clone(),
-                            ..default()
-                        },
-                        Transform::from_translation(Vec3::new(
-                            healthbar.size.x / 2.0 + 6.0,
-                            healthbar.offset.y,
-                            layer::HEALTHBAR_BG,
-                        )),
-                        StatusDownSprite,
-                    ))
-                    .id();
-
-                commands.entity(entity).add_child(down_ent);
-            }
-            (false, Some(down_ent)) => {
-                commands.entity(down_ent).despawn_recursive();
-            }
-            _ => {}
-        }
+        commands.entity(entity).remove::<TypingTargetBundle>();
 
-        match (up, up_sprite) {
-            (true, None) => {
-                let up_ent = commands
-                    .spawn((
-                        Sprite {
-                            image: texture_handles.status_up.clone(),
-                            ..default()
-                        },
-                        Transform::from_translation(Vec3::new(
-                            healthbar.size.x / 2.0 + 6.0,
-                            healthbar.offset.y,
-                            layer::HEALTHBAR_BG,
-                        )),
-                        StatusUpSprite,
-                    ))
-                    .id();
-                commands.entity(entity).add_child(up_ent);
-            }
-            (false, Some(up_ent)) => {
-                commands.entity(up_ent).despawn_recursive();
+        for child in children.iter() {
+            if text_query.get(*child).is_ok() {
+                commands.entity(*child).despawn_recursive();
             }
-            _ => {}
         }
     }
 }
@@ -256,17 +383,34 @@ fn animate(
         &Direction,
         &AnimationState,
         &mut AnimationTick,
+        &Speed,
+        &StatusEffects,
     )>,
     anim_handles: Res<EnemyAnimationHandles>,
     anim_data_assets: Res<Assets<AnimationData>>,
+    atlas_handles: Res<EnemyAtlasHandles>,
+    atlas_images: Res<Assets<AtlasImage>>,
 ) {
-    for (mut timer, mut sprite, kind, direction, anim_state, mut tick) in query.iter_mut() {
-        timer.0.tick(time.delta());
+    for (mut timer, mut sprite, kind, direction, anim_state, mut tick, speed, status_effects) in
+        query.iter_mut()
+    {
+        timer.0.tick(animation_delta(
+            time.delta(),
+            anim_state,
+            speed,
+            status_effects,
+        ));
         if !timer.0.just_finished() {
             continue;
         }
 
-        let anim_data = anim_data_assets.get(&anim_handles.by_key(&kind.0)).unwrap();
+        // Prefer animations defined inline in the enemy's atlas
+        let anim_handle = atlas_images
+            .get(&atlas_handles.by_key(&kind.0))
+            .and_then(|atlas_image| atlas_image.animation.clone())
+            .unwrap_or_else(|| anim_handles.by_key(&kind.0));
+
+        let anim_data = anim_data_assets.get(&anim_handle).unwrap();
 
         // TODO there's really more to these animations than just cycling
         // through the frames at some fraction of the frame rate.
@@ -321,12 +465,20 @@ fn animate(
                 (anim.row * anim_data.cols, anim.length, 2, true)
             }
             // I think browserquest just poofs the enemies with a generic death animation,
-            // but I think it would be nice to litter the path with the fallen. We can
-            // just use one of the idle frames for now.
-            (AnimationState::Corpse, _) => {
-                let anim = &anim_data.animations["idle_up"];
-                (anim.row * anim_data.cols, 1, 2, false)
-            }
+            // but I think it would be nice to litter the path with the fallen. Enemies
+            // without a death animation just use one of the idle frames.
+            (AnimationState::Corpse, direction) => match anim_data.animations.get("death") {
+                Some(anim) => (
+                    anim.row * anim_data.cols,
+                    anim.length,
+                    1,
+                    matches!(direction, Direction::Left),
+                ),
+                None => {
+                    let anim = &anim_data.animations["idle_up"];
+                    (anim.row * anim_data.cols, 1, 2, false)
+                }
+            },
         };
 
         sprite.flip_x = flip_x;
@@ -335,19 +487,58 @@ fn animate(
             continue;
         };
 
+        let end = start + length - 1;
+
+        // The death animation plays once, and then holds its last frame.
+        if matches!(anim_state, AnimationState::Corpse) && atlas.index == end {
+            continue;
+        }
+
         tick.0 += 1;
         if tick.0 % modulus == 0 {
             atlas.index += 1;
         }
 
-        let end = start + length - 1;
-
         if !(start..=end).contains(&atlas.index) {
             atlas.index = start;
         }
     }
 }
 
+/// Gives enemies with an `ArmorRamp` more armor over time, up to its maximum.
+fn ramp_armor(mut query: Query<(&mut Armor, &mut ArmorRamp, &AnimationState)>, time: Res<Time>) {
+    for (mut armor, mut ramp, state) in query.iter_mut() {
+        if let AnimationState::Corpse = *state {
+            continue;
+        }
+
+        if ramp.timer.tick(time.delta()).just_finished() && armor.0 < ramp.max {
+            armor.0 += 1;
+        }
+    }
+}
+
+/// Returns how far to advance an enemy's animation after `delta` has passed. Walking
+/// is scaled by the enemy's speed to keep the feet in step with the ground.
+fn animation_delta(
+    delta: Duration,
+    anim_state: &AnimationState,
+    speed: &Speed,
+    status_effects: &StatusEffects,
+) -> Duration {
+    match anim_state {
+        AnimationState::Walking => {
+            delta.mul_f32((effective_speed(speed, status_effects) / WALK_ANIMATION_SPEED).max(0.0))
+        }
+        _ => delta,
+    }
+}
+
+/// An enemy's speed, after any slowing status effects.
+fn effective_speed(speed: &Speed, status_effects: &StatusEffects) -> f32 {
+    speed.0 * (1.0 - status_effects.get_max_slow())
+}
+
 fn movement(
     time: Res<Time>,
     mut query: Query<(
@@ -356,9 +547,12 @@ fn movement(
         &mut EnemyPath,
         &mut Transform,
         &Speed,
+        &StatusEffects,
     )>,
 ) {
-    for (mut anim_state, mut direction, mut path, mut transform, speed) in query.iter_mut() {
+    for (mut anim_state, mut direction, mut path, mut transform, speed, status_effects) in
+        query.iter_mut()
+    {
         if let AnimationState::Corpse = *anim_state {
             continue;
         }
@@ -377,7 +571,7 @@ fn movement(
         let diff = next_waypoint - transform.translation.truncate();
         let dist = diff.length();
 
-        let step = speed.0 * time.delta_secs();
+        let step = effective_speed(speed, status_effects) * time.delta_secs();
 
         if step < dist {
             transform.translation += (diff.normalize_or_zero() * step).extend(0.);
@@ -390,3 +584,53 @@ fn movement(
         *direction = diff.into();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::status_effect::{StatusEffect, StatusEffectKind};
+
+    #[test]
+    fn attacks_damage_the_targeted_goal() {
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        world.init_resource::<Events<SfxEvent>>();
+
+        let goals = [0, 1].map(|index| world.spawn((Goal { index }, HitPoints::full(10))).id());
+        world.spawn((
+            AttackTimer::default(),
+            AnimationState::Attacking,
+            TargetGoal(1),
+        ));
+
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs(1));
+        world.run_system_once(deal_damage).unwrap();
+
+        let hit_points = goals.map(|goal| world.get::<HitPoints>(goal).unwrap().current);
+        assert_eq!(hit_points, [10, 9]);
+    }
+
+    #[test]
+    fn slowed_walk_animation_is_slower() {
+        let delta = Duration::from_millis(100);
+        let speed = Speed(WALK_ANIMATION_SPEED);
+        let unaffected = StatusEffects::default();
+        let slowed = StatusEffects(vec![StatusEffect {
+            kind: StatusEffectKind::Slow(0.5),
+            timer: None,
+        }]);
+
+        let secs = |anim_state, status_effects: &StatusEffects| {
+            animation_delta(delta, &anim_state, &speed, status_effects).as_secs_f32()
+        };
+
+        assert!((secs(AnimationState::Walking, &unaffected) - 0.1).abs() < 1e-6);
+        assert!((secs(AnimationState::Walking, &slowed) - 0.05).abs() < 1e-6);
+        // Only walking is in step with movement.
+        assert!((secs(AnimationState::Attacking, &slowed) - 0.1).abs() < 1e-6);
+    }
+}
@@ -1,58 +1,178 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashMap};
 
 use crate::{
-    enemy::AnimationState, loading::FontHandles, ui_color, wave::Waves, AfterUpdate, Currency,
-    Goal, HitPoints, TaipoState, FONT_SIZE,
+    cleanup_before_new_game,
+    data::WordList,
+    enemy::AnimationState,
+    loading::{FontHandles, GameDataHandles},
+    main_menu::{prepare_typing_targets, SelectedLevel, SelectedWordLists},
+    map::TiledMap,
+    typing::{TypingStats, TypingTargets, TypoStats},
+    ui_color,
+    wave::Waves,
+    AfterUpdate, Currency, GameData, GameRng, Goal, HitPoints, TaipoState, FONT_SIZE,
+    FONT_SIZE_LABEL,
 };
 pub struct GameOverPlugin;
 
 impl Plugin for GameOverPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(TaipoState::GameOver), spawn_game_over);
+        app.add_event::<GameOverEvent>();
+
+        app.init_resource::<BestStats>().init_resource::<RunStats>();
+
+        app.add_systems(
+            OnEnter(TaipoState::GameOver),
+            (update_best_stats, spawn_game_over).chain(),
+        );
 
         app.add_systems(
             AfterUpdate,
             check_game_over.run_if(in_state(TaipoState::Playing)),
         );
 
+        app.add_systems(
+            Update,
+            game_over_button_system.run_if(in_state(TaipoState::GameOver)),
+        );
+
+        // Playing again skips the main menu, so the previous game is cleaned up here
+        // instead.
+        app.add_systems(
+            OnTransition {
+                exited: TaipoState::GameOver,
+                entered: TaipoState::Spawn,
+            },
+            cleanup_before_new_game,
+        );
+
         // TODO maybe keep doing enemy movement and animations?
     }
 }
 
+#[derive(Component, Clone, Copy)]
+enum GameOverButton {
+    PlayAgain,
+    MainMenu,
+}
+impl GameOverButton {
+    fn label(self) -> &'static str {
+        match self {
+            Self::PlayAgain => "Play Again",
+            Self::MainMenu => "Return to Main Menu",
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    Won,
+    Lost,
+}
+
+/// The best results achieved this session, keyed by `BestStats::key` of the word lists
+/// that were played.
+#[derive(Resource, Default)]
+pub struct BestStats(pub HashMap<String, BestStat>);
+impl BestStats {
+    /// A key for a set of word lists that doesn't depend on the order they were chosen in.
+    pub fn key(word_lists: &[String]) -> String {
+        let mut word_lists = word_lists.to_vec();
+        word_lists.sort();
+        word_lists.join(",")
+    }
+
+    pub fn get(&self, word_lists: &[String]) -> Option<&BestStat> {
+        self.0.get(&Self::key(word_lists))
+    }
+}
+
+/// Counts of what happened during the current game, shown when it ends.
+#[derive(Resource, Default, Debug)]
+pub struct RunStats {
+    pub enemies_killed: u32,
+    pub towers_built: u32,
+    pub towers_upgraded: u32,
+    pub towers_sold: u32,
+    pub waves_cleared: u32,
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct BestStat {
+    pub total_earned: u32,
+    pub wpm: f32,
+}
+
+/// Sent once when a game ends, just before entering `TaipoState::GameOver`.
+#[derive(Event, Debug, Clone)]
+pub struct GameOverEvent {
+    pub result: GameResult,
+    pub total_earned: u32,
+}
+
 fn check_game_over(
+    mut commands: Commands,
     query: Query<&AnimationState>,
     goal_query: Query<&HitPoints, With<Goal>>,
     waves: Res<Waves>,
+    currency: Res<Currency>,
     mut next_state: ResMut<NextState<TaipoState>>,
+    mut events: EventWriter<GameOverEvent>,
 ) {
-    let lost = goal_query
-        .get_single()
-        .map(|hp| hp.current == 0)
-        .unwrap_or(false);
+    let lost = goal_query.iter().any(|hp| hp.current == 0);
+
+    // In endless mode, there is always another wave, so the game can only be lost.
+    let won = !lost
+        && waves.current().is_none()
+        && query.iter().all(|x| matches!(x, AnimationState::Corpse));
+
+    let result = match (lost, won) {
+        (true, _) => GameResult::Lost,
+        (_, true) => GameResult::Won,
+        _ => return,
+    };
+
+    commands.insert_resource(result);
+    events.send(GameOverEvent {
+        result,
+        total_earned: currency.total_earned,
+    });
+    next_state.set(TaipoState::GameOver);
+}
 
-    if lost {
-        next_state.set(TaipoState::GameOver);
+fn update_best_stats(
+    mut events: EventReader<GameOverEvent>,
+    mut best_stats: ResMut<BestStats>,
+    selected: Res<SelectedWordLists>,
+    typing_stats: Res<TypingStats>,
+) {
+    let Some(event) = events.read().last() else {
         return;
-    }
+    };
 
-    let won =
-        waves.current().is_none() && query.iter().all(|x| matches!(x, AnimationState::Corpse));
+    info!(
+        "Game over: {:?}, earned {}",
+        event.result, event.total_earned
+    );
 
-    if won {
-        next_state.set(TaipoState::GameOver);
-    }
+    let best = best_stats.0.entry(BestStats::key(&selected.0)).or_default();
+
+    best.total_earned = best.total_earned.max(event.total_earned);
+    best.wpm = best.wpm.max(typing_stats.wpm());
 }
 
 fn spawn_game_over(
     mut commands: Commands,
     font_handles: Res<FontHandles>,
     currency: Res<Currency>,
-    goal_query: Query<&HitPoints, With<Goal>>,
+    typo_stats: Res<TypoStats>,
+    typing_stats: Res<TypingStats>,
+    waves: Res<Waves>,
+    result: Res<GameResult>,
+    run_stats: Res<RunStats>,
 ) {
-    let lost = goal_query
-        .get_single()
-        .map(|hp| hp.current == 0)
-        .unwrap_or(false);
+    let lost = *result == GameResult::Lost;
+    let wave_reached = (waves.current + 1).min(waves.waves.len());
 
     commands
         .spawn((
@@ -100,6 +220,348 @@ fn spawn_game_over(
                             ui_color::NORMAL_TEXT.into()
                         }),
                     ));
+
+                    parent.spawn((
+                        Text::new(format!(
+                            "Wave: {}\nWPM: {:.0}\nAccuracy: {:.0}%",
+                            wave_reached,
+                            typing_stats.wpm(),
+                            typing_stats.accuracy() * 100.0
+                        )),
+                        TextLayout::new_with_justify(JustifyText::Center),
+                        TextFont {
+                            font: font_handles.jptext.clone(),
+                            font_size: FONT_SIZE_LABEL,
+                            ..default()
+                        },
+                        TextColor(ui_color::NORMAL_TEXT.into()),
+                        Node {
+                            margin: UiRect::top(Val::Px(10.0)),
+                            ..default()
+                        },
+                    ));
+
+                    parent.spawn((
+                        Text::new(format!(
+                            "Waves cleared: {}\nEnemies defeated: {}\nTowers built: {}\nUpgrades: {}\nTowers sold: {}",
+                            run_stats.waves_cleared,
+                            run_stats.enemies_killed,
+                            run_stats.towers_built,
+                            run_stats.towers_upgraded,
+                            run_stats.towers_sold
+                        )),
+                        TextLayout::new_with_justify(JustifyText::Center),
+                        TextFont {
+                            font: font_handles.jptext.clone(),
+                            font_size: FONT_SIZE_LABEL,
+                            ..default()
+                        },
+                        TextColor(ui_color::NORMAL_TEXT.into()),
+                        Node {
+                            margin: UiRect::top(Val::Px(10.0)),
+                            ..default()
+                        },
+                    ));
+
+                    let worst = typo_stats.worst(5);
+                    if !worst.is_empty() {
+                        let report = worst
+                            .iter()
+                            .map(|typo| {
+                                format!("{} ({}) × {}", typo.displayed, typo.typed, typo.count)
+                            })
+                            .collect::<Vec<_>>()
+                            .join("\n");
+
+                        parent.spawn((
+                            Text::new(format!("よくまちがえた\n{}", report)),
+                            TextLayout::new_with_justify(JustifyText::Center),
+                            TextFont {
+                                font: font_handles.jptext.clone(),
+                                font_size: FONT_SIZE_LABEL,
+                                ..default()
+                            },
+                            TextColor(ui_color::NORMAL_TEXT.into()),
+                            Node {
+                                margin: UiRect::top(Val::Px(10.0)),
+                                ..default()
+                            },
+                        ));
+                    }
+
+                    for button in [GameOverButton::PlayAgain, GameOverButton::MainMenu] {
+                        parent
+                            .spawn((
+                                Button,
+                                Node {
+                                    width: Val::Px(200.0),
+                                    height: Val::Px(48.0),
+                                    margin: UiRect::all(Val::Px(5.0)),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..default()
+                                },
+                                BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                button,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    Text::new(button.label()),
+                                    TextFont {
+                                        font: font_handles.jptext.clone(),
+                                        font_size: FONT_SIZE_LABEL,
+                                        ..default()
+                                    },
+                                    TextColor(ui_color::BUTTON_TEXT.into()),
+                                ));
+                            });
+                    }
                 });
         });
 }
+
+fn game_over_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &GameOverButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut next_state: ResMut<NextState<TaipoState>>,
+    (mut typing_targets, selected_word_lists, selected_level, maps): (
+        ResMut<TypingTargets>,
+        Res<SelectedWordLists>,
+        Res<SelectedLevel>,
+        Res<Assets<TiledMap>>,
+    ),
+    (game_data_handles, game_data_assets, word_list_assets, mut rng): (
+        Res<GameDataHandles>,
+        Res<Assets<GameData>>,
+        Res<Assets<WordList>>,
+        ResMut<GameRng>,
+    ),
+) {
+    for (interaction, mut background_color, button) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                if let GameOverButton::MainMenu = button {
+                    next_state.set(TaipoState::MainMenu);
+                    continue;
+                }
+
+                let game_data = game_data_assets.get(&game_data_handles.game).unwrap();
+                let tiled_map = maps.get(&selected_level.0).unwrap();
+
+                // The same words were enough last time, but a different order can
+                // leave fewer of them usable.
+                if let Err(err) = prepare_typing_targets(
+                    &mut typing_targets,
+                    &selected_word_lists.0,
+                    tiled_map,
+                    game_data,
+                    &word_list_assets,
+                    &mut rng,
+                ) {
+                    warn!(
+                        "Only {} of the {} words needed are usable, returning to the main menu",
+                        err.available, err.required
+                    );
+
+                    next_state.set(TaipoState::MainMenu);
+                    continue;
+                }
+
+                next_state.set(TaipoState::Spawn);
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{app::MainScheduleOrder, state::app::StatesPlugin};
+
+    use super::*;
+    use crate::{
+        action_panel::{ActionPanel, ActionPanelItemImage},
+        check_spawn,
+        data::parse_plain,
+        wave::Wave,
+        CleanupBeforeNewGame,
+    };
+
+    /// Stands in for `spawn_map_objects` and `startup_system`, spawning just enough
+    /// for `check_spawn` to start the game.
+    fn spawn_game(mut commands: Commands) {
+        commands.spawn((ActionPanelItemImage, CleanupBeforeNewGame));
+        commands.spawn((Goal { index: 0 }, HitPoints::full(10), CleanupBeforeNewGame));
+        commands.insert_resource(Waves {
+            waves: vec![Wave::default()],
+            current: 0,
+        });
+    }
+
+    fn flow_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin::default(),
+            StatesPlugin,
+            GameOverPlugin,
+        ));
+
+        let mut order = app.world_mut().resource_mut::<MainScheduleOrder>();
+        order.insert_after(Update, AfterUpdate);
+
+        app.init_asset::<GameData>()
+            .init_asset::<WordList>()
+            .init_asset::<TiledMap>();
+
+        app.insert_state(TaipoState::MainMenu)
+            .enable_state_scoped_entities::<TaipoState>();
+
+        // These mirror the systems that `main` adds.
+        app.add_systems(OnEnter(TaipoState::MainMenu), cleanup_before_new_game)
+            .add_systems(OnEnter(TaipoState::Spawn), spawn_game)
+            .add_systems(Update, check_spawn.run_if(in_state(TaipoState::Spawn)));
+
+        let words = ('a'..='z')
+            .flat_map(|a| ('a'..='z').map(move |b| format!("{a}{b}\n")))
+            .collect::<String>();
+        let word_list = app
+            .world_mut()
+            .resource_mut::<Assets<WordList>>()
+            .add(WordList {
+                words: parse_plain(&words).unwrap(),
+            });
+        let game = app
+            .world_mut()
+            .resource_mut::<Assets<GameData>>()
+            .add(GameData {
+                word_lists: [("words".to_string(), word_list)].into_iter().collect(),
+                ..default()
+            });
+
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/textures/level1.tmx");
+        let level = app
+            .world_mut()
+            .resource_mut::<Assets<TiledMap>>()
+            .add(TiledMap {
+                map: tiled::Loader::new().load_tmx_map(path).unwrap(),
+                tilemap_textures: default(),
+                collection_textures: default(),
+                image_layer_textures: default(),
+            });
+
+        app.insert_resource(FontHandles { jptext: default() })
+            .insert_resource(GameDataHandles { game })
+            .insert_resource(SelectedLevel(level))
+            .insert_resource(SelectedWordLists(vec!["words".to_string()]))
+            .init_resource::<GameRng>()
+            .init_resource::<TypingTargets>()
+            .init_resource::<TypingStats>()
+            .init_resource::<TypoStats>()
+            .init_resource::<Currency>()
+            .init_resource::<Waves>()
+            .init_resource::<ActionPanel>();
+
+        app
+    }
+
+    fn state(app: &App) -> TaipoState {
+        *app.world().resource::<State<TaipoState>>().get()
+    }
+
+    fn start_game(app: &mut App) {
+        app.world_mut()
+            .resource_mut::<NextState<TaipoState>>()
+            .set(TaipoState::Spawn);
+        app.update();
+        assert_eq!(state(app), TaipoState::Spawn);
+        app.update();
+        assert_eq!(state(app), TaipoState::Playing);
+    }
+
+    /// Clears every wave, earning some money along the way.
+    fn win_game(app: &mut App) {
+        app.world_mut().resource_mut::<Currency>().total_earned = 50;
+        app.world_mut().resource_mut::<Waves>().current = 1;
+        app.update();
+        app.update();
+        assert_eq!(state(app), TaipoState::GameOver);
+        assert_eq!(*app.world().resource::<GameResult>(), GameResult::Won);
+    }
+
+    fn press(app: &mut App, pressed: fn(&GameOverButton) -> bool) {
+        let world = app.world_mut();
+        let button = world
+            .query::<(Entity, &GameOverButton)>()
+            .iter(world)
+            .find(|(_, button)| pressed(button))
+            .unwrap()
+            .0;
+        world.entity_mut(button).insert(Interaction::Pressed);
+        app.update();
+    }
+
+    fn game_entities(app: &mut App) -> Vec<Entity> {
+        let world = app.world_mut();
+        world
+            .query_filtered::<Entity, With<CleanupBeforeNewGame>>()
+            .iter(world)
+            .collect()
+    }
+
+    #[test]
+    fn menu_to_win_then_play_again_or_quit() {
+        let mut app = flow_app();
+        app.update();
+        assert_eq!(state(&app), TaipoState::MainMenu);
+
+        start_game(&mut app);
+        let first_game = game_entities(&mut app);
+        assert_eq!(first_game.len(), 2);
+
+        win_game(&mut app);
+        let best = app.world().resource::<BestStats>();
+        assert_eq!(best.get(&["words".to_string()]).unwrap().total_earned, 50);
+
+        // Playing again cleans up the last game before spawning the next one.
+        press(&mut app, |button| {
+            matches!(button, GameOverButton::PlayAgain)
+        });
+        app.update();
+        assert_eq!(state(&app), TaipoState::Spawn);
+        app.update();
+        assert_eq!(state(&app), TaipoState::Playing);
+        let second_game = game_entities(&mut app);
+        assert_eq!(second_game.len(), 2);
+        assert!(first_game
+            .iter()
+            .all(|entity| !second_game.contains(entity)));
+        assert_eq!(app.world().resource::<Currency>().total_earned, 0);
+        assert!(app
+            .world_mut()
+            .query::<&GameOverButton>()
+            .iter(app.world())
+            .next()
+            .is_none());
+        assert!(!app.world().resource::<TypingTargets>().possible.is_empty());
+
+        // Returning to the main menu cleans up too.
+        win_game(&mut app);
+        press(&mut app, |button| {
+            matches!(button, GameOverButton::MainMenu)
+        });
+        app.update();
+        assert_eq!(state(&app), TaipoState::MainMenu);
+        assert!(game_entities(&mut app).is_empty());
+        assert_eq!(app.world().resource::<Currency>().total_earned, 0);
+    }
+}
@@ -3,15 +3,20 @@ use bevy::{
     prelude::*,
 };
 
-use crate::{layer, AfterUpdate, HitPoints, TaipoState};
+use crate::{layer, loading::FontHandles, ui_color, AfterUpdate, HitPoints, TaipoState};
 
 pub struct HealthBarPlugin;
 
 impl Plugin for HealthBarPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<HealthBarSettings>();
+
+        // `update` needs the bars spawned by `spawn` for entities that were just added.
         app.add_systems(
             AfterUpdate,
-            (update, spawn).run_if(in_state(TaipoState::Playing)),
+            (spawn, update)
+                .chain()
+                .run_if(in_state(TaipoState::Playing)),
         );
     }
 }
@@ -37,14 +42,28 @@ impl Default for HealthBar {
 struct HealthBarBar;
 #[derive(Component)]
 struct HealthBarBackground;
+/// Hit points written as numbers, just above the bar.
+#[derive(Component)]
+struct HealthBarText;
+
+#[derive(Resource, Default)]
+pub struct HealthBarSettings {
+    /// If true, hit points are also shown as text, e.g. "12/20".
+    pub show_text: bool,
+}
 
 const HEALTHBAR_BACKGROUND: Srgba = Srgba::rgb(0.2, 0.2, 0.2);
 const HEALTHBAR_HEALTHY: Srgba = LIME;
 const HEALTHBAR_INJURED: Srgba = YELLOW;
 const HEALTHBAR_CRITICAL: Srgba = RED;
 const HEALTHBAR_INVISIBLE: Srgba = Srgba::NONE;
+const FONT_SIZE_HEALTHBAR: f32 = 8.0;
 
-pub fn spawn(mut commands: Commands, query: Query<(Entity, &HealthBar), Added<HealthBar>>) {
+pub fn spawn(
+    mut commands: Commands,
+    query: Query<(Entity, &HealthBar), Added<HealthBar>>,
+    font_handles: Res<FontHandles>,
+) {
     for (entity, healthbar) in &query {
         let bar = commands
             .spawn((
@@ -76,16 +95,45 @@ pub fn spawn(mut commands: Commands, query: Query<(Entity, &HealthBar), Added<He
             ))
             .id();
 
-        commands.entity(entity).add_children(&[bar, background]);
+        // `update` fills this in and shows it.
+        let text = commands
+            .spawn((
+                Text2d::new(""),
+                TextFont {
+                    font: font_handles.jptext.clone(),
+                    font_size: FONT_SIZE_HEALTHBAR,
+                    ..default()
+                },
+                TextColor(ui_color::NORMAL_TEXT.into()),
+                Transform::from_translation(
+                    (healthbar.offset
+                        + Vec2::new(0.0, (healthbar.size.y + FONT_SIZE_HEALTHBAR) / 2.0 + 1.0))
+                    .extend(layer::HEALTHBAR),
+                ),
+                Visibility::Hidden,
+                HealthBarText,
+            ))
+            .id();
+
+        commands
+            .entity(entity)
+            .add_children(&[bar, background, text]);
     }
 }
 
 fn update(
     mut bar_query: Query<(&mut Transform, &mut Sprite), With<HealthBarBar>>,
     mut bg_query: Query<&mut Sprite, (With<HealthBarBackground>, Without<HealthBarBar>)>,
-    health_query: Query<(&HealthBar, &HitPoints, &Children), Changed<HitPoints>>,
+    mut text_query: Query<(&mut Text2d, &mut Visibility), With<HealthBarText>>,
+    health_query: Query<(&HealthBar, Ref<HitPoints>, &Children)>,
+    settings: Res<HealthBarSettings>,
 ) {
     for (healthbar, hp, children) in health_query.iter() {
+        // Bars that were just spawned have changed hit points too.
+        if !hp.is_changed() && !settings.is_changed() {
+            continue;
+        }
+
         let frac = (hp.current as f32 / hp.max as f32).clamp(0.0, 1.0);
 
         let invisible = (!healthbar.show_full && hp.current >= hp.max)
@@ -120,6 +168,18 @@ fn update(
                     HEALTHBAR_BACKGROUND.into()
                 }
             }
+
+            // Update the text
+
+            if let Ok((mut text, mut visibility)) = text_query.get_mut(*child) {
+                text.0 = format!("{}/{}", hp.current, hp.max);
+
+                *visibility = if invisible || !settings.show_text {
+                    Visibility::Hidden
+                } else {
+                    Visibility::Inherited
+                };
+            }
         }
     }
 }
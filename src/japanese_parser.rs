@@ -3,20 +3,30 @@ use chumsky::{
     error::Cheap,
     prelude::end,
     primitive::{choice, just, none_of, one_of},
-    text::whitespace,
+    text::{int, whitespace},
     Error, Parser,
 };
 
-use crate::typing::TypingTarget;
+use crate::typing::{TypingTarget, DEFAULT_DIFFICULTY, DEFAULT_WEIGHT};
 
+/// A displayed chunk, its typed chunk, any other accepted ways of typing it, and its
+/// kana reading if it was written with one.
 #[derive(Debug, Clone)]
-struct DisplayedTypedPair(String, String);
+struct DisplayedTypedPair(String, String, Vec<String>, Option<String>);
 
 static HIRAGANA: &str = "あいうえおかがきぎくぐけげこごさざしじすずせぜそぞただちぢつづてでとどなにぬねのはばぱひびぴふぶぷへべぺほぼぽまみむめもやゆよらりるれろわゐゑをんー";
-static KATAKANA: &str = "アイウエオカガキギクグケゲコゴサザシジスズセゼソゾタダチヂツヅテデトドナニヌネノハバパヒビピフブプヘベペホボポマミムメモヤユヨラリルレロワヰヱヲンー";
+static KATAKANA: &str = "アイウエオカガキギクグケゲコゴサザシジスズセゼソゾタダチヂツヅテデトドナニヌネノハバパヒビピフブプヘベペホボポマミムメモヤユヨラリルレロワヰヱヲンヴー";
 static SUTEGANA: &str = "ァィゥェォャュョぁぃぅぇぉゃゅょ";
 static SOKUON: &str = "っッ";
 
+/// Options that change how word lists are parsed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParserOptions {
+    /// If true, "ー" is typed by repeating the vowel that the chunk before it ends
+    /// with, e.g. "tookyoo" for "トーキョー", rather than with "-".
+    pub long_vowels: bool,
+}
+
 fn kana_to_typed_chunk(kana: &str) -> Option<&'static str> {
     #![allow(clippy::match_same_arms)]
     match kana {
@@ -202,6 +212,7 @@ fn kana_to_typed_chunk(kana: &str) -> Option<&'static str> {
         "ヱ" => Some("we"),
         "ヲ" => Some("wo"),
         "ン" => Some("nn"),
+        "ヴ" => Some("vu"),
         "ー" => Some("-"),
         // you-on
         "キャ" => Some("kya"),
@@ -244,33 +255,174 @@ fn kana_to_typed_chunk(kana: &str) -> Option<&'static str> {
         "フェ" => Some("fe"),
         "フィ" => Some("fi"),
         "ティ" => Some("texi"),
+        "ヴァ" => Some("va"),
+        "ヴィ" => Some("vi"),
+        "ヴェ" => Some("ve"),
+        "ヴォ" => Some("vo"),
         _ => None,
     }
 }
 
-fn line() -> impl Parser<char, Vec<DisplayedTypedPair>, Error = Cheap<char>> {
-    kana()
-        .or(parenthetical())
+/// Returns other accepted ways of typing a chunk returned by `kana_to_typed_chunk`,
+/// for players who learned a different romanization.
+fn typed_chunk_alternatives(typed: &str) -> &'static [&'static str] {
+    match typed {
+        "shi" => &["si"],
+        "chi" => &["ti"],
+        "tsu" => &["tu"],
+        "fu" => &["hu"],
+        "ji" => &["zi"],
+        "sha" => &["sya"],
+        "shu" => &["syu"],
+        "sho" => &["syo"],
+        "cha" => &["tya"],
+        "chu" => &["tyu"],
+        "cho" => &["tyo"],
+        "ja" => &["zya", "jya"],
+        "ju" => &["zyu", "jyu"],
+        "jo" => &["zyo", "jyo"],
+        _ => &[],
+    }
+}
+
+/// Returns the typed chunk for a sokuon together with the chunk after it, typed as
+/// `typed` or one of its `alternatives`, and any alternatives for the pair.
+///
+/// A sokuon is typed by repeating the first character of whichever spelling is used
+/// for the chunk after it, e.g. "tchi" or "tti" for "っち". Before "ch", "t" is also
+/// accepted, e.g. "matcha" as well as "maccha" for "まっちゃ".
+fn sokuon_typed_chunks(typed: &str, alternatives: &[String]) -> (String, Vec<String>) {
+    let with_sokuon = |spelling: &str| {
+        let first = spelling.chars().next().unwrap();
+
+        std::iter::once(format!("{}{}", first, spelling))
+            .chain(spelling.starts_with("ch").then(|| format!("t{}", spelling)))
+            .collect::<Vec<_>>()
+    };
+
+    let canonical = with_sokuon(typed).remove(0);
+
+    let mut sokuon_alternatives = vec![];
+
+    let spellings = std::iter::once(typed)
+        .chain(alternatives.iter().map(String::as_str))
+        .flat_map(with_sokuon);
+
+    for alt in spellings {
+        if alt != canonical && !sokuon_alternatives.contains(&alt) {
+            sokuon_alternatives.push(alt);
+        }
+    }
+
+    (canonical, sokuon_alternatives)
+}
+
+/// Returns every way of typing a sequence of pairs, except for the canonical one.
+fn alternative_spellings(pairs: &[DisplayedTypedPair]) -> Vec<String> {
+    let canonical = pairs.iter().map(|p| p.1.as_str()).collect::<String>();
+
+    pairs
+        .iter()
+        .fold(vec![String::new()], |spellings, pair| {
+            spellings
+                .iter()
+                .flat_map(|spelling| {
+                    std::iter::once(&pair.1)
+                        .chain(pair.2.iter())
+                        .map(move |typed| format!("{}{}", spelling, typed))
+                })
+                .collect()
+        })
+        .into_iter()
+        .filter(|spelling| *spelling != canonical)
+        .collect()
+}
+
+/// Allows "ん" to be typed as a single "n" when the chunk after it begins with a
+/// consonant that can't combine with the "n", e.g. "kanji" for "かんじ".
+fn allow_single_n(mut pairs: Vec<DisplayedTypedPair>) -> Vec<DisplayedTypedPair> {
+    for i in 0..pairs.len().saturating_sub(1) {
+        if pairs[i].1 != "nn" || pairs[i].2.iter().any(|alt| alt == "n") {
+            continue;
+        }
+
+        let single_n = pairs[i + 1]
+            .1
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() && !"aiueoyn".contains(c));
+
+        if single_n {
+            pairs[i].2.push("n".to_string());
+        }
+    }
+
+    pairs
+}
+
+/// Makes "ー" typed with the vowel that the chunk before it ends with, still allowing
+/// "-" as an alternative.
+fn lengthen_vowels(mut pairs: Vec<DisplayedTypedPair>) -> Vec<DisplayedTypedPair> {
+    for i in 1..pairs.len() {
+        if pairs[i].1 != "-" {
+            continue;
+        }
+
+        let vowel = pairs[i - 1]
+            .1
+            .chars()
+            .last()
+            .filter(|c| "aiueo".contains(*c));
+
+        if let Some(vowel) = vowel {
+            pairs[i].1 = vowel.to_string();
+            pairs[i].2.push("-".to_string());
+        }
+    }
+
+    pairs
+}
+
+fn line(options: ParserOptions) -> impl Parser<char, Vec<DisplayedTypedPair>, Error = Cheap<char>> {
+    kana(options)
+        .or(parenthetical(options))
         .repeated()
         .at_least(1)
         .collect::<Vec<_>>()
         .flatten()
+        .map(allow_single_n)
+        .map(move |pairs| {
+            if options.long_vowels {
+                lengthen_vowels(pairs)
+            } else {
+                pairs
+            }
+        })
         .labelled("line")
 }
 
-fn parenthetical() -> impl Parser<char, Vec<DisplayedTypedPair>, Error = Cheap<char>> {
+fn parenthetical(
+    options: ParserOptions,
+) -> impl Parser<char, Vec<DisplayedTypedPair>, Error = Cheap<char>> {
     none_of("\n()")
         .repeated()
         .at_least(1)
         .collect::<String>()
-        .then(kana().delimited_by(just('('), just(')')))
+        .then(kana(options).delimited_by(just('('), just(')')))
         .map(|(outside, inside)| {
             let inside_string = inside.iter().cloned().map(|i| i.1).collect::<String>();
-            vec![DisplayedTypedPair(outside, inside_string)]
+            let reading = inside.iter().cloned().map(|i| i.0).collect::<String>();
+            let alternatives = alternative_spellings(&inside);
+            vec![DisplayedTypedPair(
+                outside,
+                inside_string,
+                alternatives,
+                Some(reading),
+            )]
         })
 }
 
-fn kana() -> impl Parser<char, Vec<DisplayedTypedPair>, Error = Cheap<char>> {
+fn kana(options: ParserOptions) -> impl Parser<char, Vec<DisplayedTypedPair>, Error = Cheap<char>> {
     one_of(SOKUON)
         .or_not()
         .then(choice((one_of(HIRAGANA), one_of(KATAKANA))).labelled("kana"))
@@ -284,43 +436,95 @@ fn kana() -> impl Parser<char, Vec<DisplayedTypedPair>, Error = Cheap<char>> {
             let typed = kana_to_typed_chunk(&combined)
                 .ok_or_else(|| Cheap::<char>::expected_input_found(span, [], None))?;
 
-            let mut pairs = vec![];
+            let alternatives = typed_chunk_alternatives(typed)
+                .iter()
+                .map(|alt| alt.to_string())
+                .collect::<Vec<_>>();
 
+            // A sokuon is typed along with the chunk after it, so that the repeated
+            // character always agrees with how that chunk is spelled.
             if let Some(sokuon) = sokuon {
-                // TODO does this work in all cases?
-                // If there's a sokuon, repeat the first character of the typed output
-                pairs.push(DisplayedTypedPair(
-                    sokuon.into(),
-                    typed.chars().next().unwrap().into(),
-                ));
-            }
+                let (typed, alternatives) = sokuon_typed_chunks(typed, &alternatives);
 
-            pairs.push(DisplayedTypedPair(combined, typed.to_owned()));
+                return Ok(vec![DisplayedTypedPair(
+                    format!("{}{}", sokuon, combined),
+                    typed,
+                    alternatives,
+                    None,
+                )]);
+            }
 
-            Ok(pairs)
+            Ok(vec![DisplayedTypedPair(
+                combined,
+                typed.to_owned(),
+                alternatives,
+                None,
+            )])
         })
         .repeated()
         .at_least(1)
         .collect::<Vec<_>>()
         .flatten()
+        .map(allow_single_n)
+        .map(move |pairs| {
+            if options.long_vowels {
+                lengthen_vowels(pairs)
+            } else {
+                pairs
+            }
+        })
 }
 
-pub fn parser() -> impl Parser<char, Vec<TypingTarget>, Error = Cheap<char>> {
+fn difficulty() -> impl Parser<char, u32, Error = Cheap<char>> {
+    int(10)
+        .delimited_by(just('['), just(']'))
+        .try_map(|digits: String, span| {
+            digits
+                .parse::<u32>()
+                .map_err(|_| Cheap::<char>::expected_input_found(span, [], None))
+        })
+        .labelled("difficulty")
+}
+
+fn weight() -> impl Parser<char, u32, Error = Cheap<char>> {
+    just('*')
+        .ignore_then(int(10))
+        .try_map(|digits: String, span| {
+            digits
+                .parse::<u32>()
+                .ok()
+                .filter(|weight| *weight > 0)
+                .ok_or_else(|| Cheap::<char>::expected_input_found(span, [], None))
+        })
+        .labelled("weight")
+}
+
+pub fn parser(options: ParserOptions) -> impl Parser<char, Vec<TypingTarget>, Error = Cheap<char>> {
     whitespace()
         .ignore_then(
-            line()
-                .map(|l| {
+            line(options)
+                .then(difficulty().or_not())
+                .then(weight().or_not())
+                .map(|((l, difficulty), weight)| {
                     let mut typed_chunks = vec![];
+                    let mut alt_typed_chunks = vec![];
                     let mut displayed_chunks = vec![];
+                    let mut readings = vec![];
 
                     for f in l.iter().cloned() {
                         displayed_chunks.push(f.0);
                         typed_chunks.push(f.1);
+                        alt_typed_chunks.push(f.2);
+                        readings.push(f.3);
                     }
 
                     TypingTarget {
                         typed_chunks,
+                        alt_typed_chunks,
                         displayed_chunks,
+                        readings,
+                        difficulty: difficulty.unwrap_or(DEFAULT_DIFFICULTY),
+                        weight: weight.unwrap_or(DEFAULT_WEIGHT),
                     }
                 })
                 .separated_by(whitespace()),
@@ -330,15 +534,50 @@ pub fn parser() -> impl Parser<char, Vec<TypingTarget>, Error = Cheap<char>> {
 }
 
 pub fn parse(input: &str) -> anyhow::Result<Vec<TypingTarget>> {
-    parser().parse(input).map_err(|errs| {
+    parse_with_options(input, ParserOptions::default())
+}
+
+pub fn parse_with_options(
+    input: &str,
+    options: ParserOptions,
+) -> anyhow::Result<Vec<TypingTarget>> {
+    // Comments are blanked rather than removed, so that errors point at the right line.
+    let input = input
+        .lines()
+        .map(|l| if is_comment(l) { "" } else { l })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let input = input.as_str();
+
+    parser(options).parse(input).map_err(|errs| {
         let err = &errs[0];
         let (line, col) = get_line_and_column(err.span().start, input);
-        anyhow!(format!("Parsing failed at line {}, column {}", line, col))
+
+        let found = match input.chars().nth(err.span().start) {
+            Some('\n') => "line break".to_string(),
+            Some(c) => format!("\"{}\"", c),
+            None => "end of file".to_string(),
+        };
+
+        anyhow!(
+            "Parsing failed at line {}, column {}: unexpected {}\n{}",
+            line,
+            col,
+            found,
+            snippet(input, line, col)
+        )
     })
 }
 
+/// Returns true if `line` is a comment, which starts with `#`, ignoring leading
+/// whitespace, e.g. "# JR Yamanote stations".
+pub fn is_comment(line: &str) -> bool {
+    line.trim_start().starts_with('#')
+}
+
+/// Returns the line and column, both starting at 1, of the character at `char_index`.
 fn get_line_and_column(char_index: usize, input: &str) -> (usize, usize) {
-    let mut last: usize = 0;
+    let mut line_start: usize = 0;
     let mut count: usize = 0;
 
     input
@@ -348,8 +587,82 @@ fn get_line_and_column(char_index: usize, input: &str) -> (usize, usize) {
         .filter(|(_, c)| *c == '\n')
         .for_each(|(i, _)| {
             count += 1;
-            last = i;
+            line_start = i + 1;
         });
 
-    (count + 1, char_index - last)
+    (count + 1, char_index - line_start + 1)
+}
+
+/// Characters shown on either side of an error.
+const SNIPPET_CONTEXT: usize = 20;
+
+/// Returns the part of `line` around `col`, with a caret under `col` on the next line.
+fn snippet(input: &str, line: usize, col: usize) -> String {
+    let chars = input
+        .lines()
+        .nth(line - 1)
+        .unwrap_or_default()
+        .chars()
+        .collect::<Vec<_>>();
+
+    let start = col.saturating_sub(SNIPPET_CONTEXT + 1);
+    let end = (col + SNIPPET_CONTEXT).min(chars.len());
+    let shown = chars[start..end.max(start)].iter().collect::<String>();
+
+    // Kana and kanji are usually twice as wide as ASCII in a monospace font.
+    let offset = chars[start..(col - 1).min(chars.len())]
+        .iter()
+        .map(|c| if c.is_ascii() { 1 } else { 2 })
+        .sum::<usize>();
+
+    format!("  {}\n  {}^", shown, " ".repeat(offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spellings(input: &str) -> Vec<String> {
+        let mut targets = parse(input).unwrap();
+        assert_eq!(targets.len(), 1);
+        targets.remove(0).spellings()
+    }
+
+    #[test]
+    fn sokuon_spellings() {
+        assert_eq!(spellings("っち"), vec!["cchi", "tchi", "tti"]);
+        assert_eq!(spellings("った"), vec!["tta"]);
+        assert_eq!(spellings("っか"), vec!["kka"]);
+        assert_eq!(spellings("っぱ"), vec!["ppa"]);
+        assert_eq!(spellings("っつ"), vec!["ttsu", "ttu"]);
+    }
+
+    #[test]
+    fn sokuon_agrees_with_next_chunk() {
+        let target = parse("まっちゃ").unwrap().remove(0);
+
+        assert!(target.matches("maccha"));
+        assert!(target.matches("matcha"));
+        assert!(target.matches("mattya"));
+        assert!(!target.matches("mactya"));
+        assert!(!target.matches("maxtucha"));
+    }
+
+    #[test]
+    fn vu_row() {
+        let target = parse("ヴァヴィヴ").unwrap().remove(0);
+        assert_eq!(target.displayed_chunks, vec!["ヴァ", "ヴィ", "ヴ"]);
+        assert_eq!(target.typed_chunks, vec!["va", "vi", "vu"]);
+    }
+
+    #[test]
+    fn long_vowel_and_small_kana() {
+        let target = parse("チョコレート").unwrap().remove(0);
+        assert_eq!(
+            target.displayed_chunks,
+            vec!["チョ", "コ", "レ", "ー", "ト"]
+        );
+        assert_eq!(target.typed_chunks, vec!["cho", "ko", "re", "-", "to"]);
+        assert!(target.matches("tyokore-to"));
+    }
 }
@@ -1,11 +1,13 @@
-// Enemies currently just "below" towers in z axis. This is okay for now
-// because the current map never allows an enemy to be in front of a tower.
+// Enemies are just "below" towers in z axis by default. This is okay for maps
+// that never allow an enemy to be in front of a tower.
 //
-// We could likely get away with towers and enemies using LAYER + y / 100.0
-// or something for their z values if it becomes necessary.
+// Maps that do can set the "y_sort" property, which moves towers and enemies
+// into Y_SORTED and orders them within it by their y position.
 //
 // See bevy issue #1275
 
+// Image layers are drawn behind everything else, in their Tiled map order
+pub const BACKGROUND: f32 = -10.0;
 // Tile Layers begin at 1.0 and correspond to their Layer ID in the Tiled map
 pub const TOWER_SLOT: f32 = 7.0;
 pub const RANGE_INDICATOR: f32 = 8.0;
@@ -13,10 +15,19 @@ pub const RETICLE: f32 = 8.1;
 pub const CORPSE: f32 = 9.0;
 pub const ENEMY: f32 = 9.1;
 pub const TOWER: f32 = 10.0;
+// Flying enemies pass over towers, and aren't y-sorted.
+pub const FLYING: f32 = 10.5;
 pub const BULLET: f32 = 11.0;
+pub const Y_SORTED: f32 = 9.5;
 // Relative to their parent. So in practice, this is ENEMY + 90
 pub const HEALTHBAR_BG: f32 = 90.0;
 pub const HEALTHBAR: f32 = 90.1;
 pub const TOWER_SLOT_LABEL_BG: f32 = 199.0;
 #[allow(dead_code)]
 pub const IN_FRONT_OF_CAMERA: f32 = 1000.1;
+
+/// Offsets `layer` by a y position so that entities lower on the screen are drawn
+/// in front, while staying within the layer for maps up to a few thousand pixels tall.
+pub fn y_sorted(layer: f32, y: f32) -> f32 {
+    layer - y / 10000.0
+}
@@ -58,23 +58,35 @@ pub struct TextureHandles {
     pub status_down: Handle<Image>,
     #[asset(path = "textures/tower_slot.png")]
     pub tower_slot: Handle<Image>,
-    #[asset(path = "textures/towers/shuriken.png")]
-    pub tower: Handle<Image>,
-    #[asset(path = "textures/towers/shuriken2.png")]
-    pub tower_two: Handle<Image>,
-    #[asset(path = "textures/towers/pupper.png")]
-    pub support_tower: Handle<Image>,
-    #[asset(path = "textures/towers/pupper2.png")]
-    pub support_tower_two: Handle<Image>,
-    #[asset(path = "textures/towers/boss.png")]
-    pub debuff_tower: Handle<Image>,
-    #[asset(path = "textures/towers/boss2.png")]
-    pub debuff_tower_two: Handle<Image>,
+    /// Tower sprites by level, starting at level 1.
+    #[asset(
+        paths("textures/towers/shuriken.png", "textures/towers/shuriken2.png"),
+        collection(typed)
+    )]
+    pub tower: Vec<Handle<Image>>,
+    #[asset(
+        paths("textures/towers/pupper.png", "textures/towers/pupper2.png"),
+        collection(typed)
+    )]
+    pub support_tower: Vec<Handle<Image>>,
+    #[asset(
+        paths("textures/towers/boss.png", "textures/towers/boss2.png"),
+        collection(typed)
+    )]
+    pub debuff_tower: Vec<Handle<Image>>,
 }
 #[derive(AssetCollection, Resource)]
 pub struct LevelHandles {
-    #[asset(path = "textures/level1.tmx")]
-    pub one: Handle<TiledMap>,
+    /// The playable maps, in the order that they are offered on the main menu.
+    #[asset(
+        paths(
+            "textures/level1.tmx",
+            "textures/horizontal-promo.tmx",
+            "textures/vertical-promo.tmx"
+        ),
+        collection(typed)
+    )]
+    pub levels: Vec<Handle<TiledMap>>,
 }
 
 #[derive(AssetCollection, Resource)]
@@ -92,13 +104,17 @@ pub struct EnemyAtlasHandles {
 }
 impl EnemyAtlasHandles {
     pub fn by_key(&self, key: &str) -> Handle<AtlasImage> {
+        self.get(key).expect("enemy atlas does not exist")
+    }
+
+    pub fn get(&self, key: &str) -> Option<Handle<AtlasImage>> {
         match key {
-            "crab" => self.crab.clone(),
-            "deathknight" => self.deathknight.clone(),
-            "skeleton" => self.skeleton.clone(),
-            "skeleton2" => self.skeleton2.clone(),
-            "snake" => self.snake.clone(),
-            _ => panic!("enemy atlas does not exist"),
+            "crab" => Some(self.crab.clone()),
+            "deathknight" => Some(self.deathknight.clone()),
+            "skeleton" => Some(self.skeleton.clone()),
+            "skeleton2" => Some(self.skeleton2.clone()),
+            "snake" => Some(self.snake.clone()),
+            _ => None,
         }
     }
 }
@@ -145,4 +161,16 @@ pub struct FontHandles {
 pub struct AudioHandles {
     #[asset(path = "sounds/wrong_character.ogg")]
     pub wrong_character: Handle<AudioSource>,
+    #[asset(path = "sounds/complete.ogg")]
+    pub complete: Handle<AudioSource>,
+    #[asset(path = "sounds/build.ogg")]
+    pub build: Handle<AudioSource>,
+    #[asset(path = "sounds/upgrade.ogg")]
+    pub upgrade: Handle<AudioSource>,
+    #[asset(path = "sounds/sell.ogg")]
+    pub sell: Handle<AudioSource>,
+    #[asset(path = "sounds/goal_damage.ogg")]
+    pub goal_damage: Handle<AudioSource>,
+    #[asset(path = "sounds/music.ogg")]
+    pub music: Handle<AudioSource>,
 }
@@ -2,45 +2,66 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use action_panel::{ActionPanel, ActionPanelItemImage, ActionPanelPlugin};
-use atlas_loader::{AtlasImage, AtlasImageLoader};
+use atlas_loader::{validate_atlas_images, AtlasImage, AtlasImageLoader};
 use bevy::{
     app::MainScheduleOrder,
     asset::AssetMetaCheck,
-    ecs::schedule::ScheduleLabel,
+    audio::Volume,
+    ecs::{entity::Entities, schedule::ScheduleLabel},
     prelude::*,
+    sprite::Anchor,
     text::{update_text2d_layout, TextLayoutInfo},
     utils::HashMap,
 };
 
 use bevy_ecs_tilemap::TilemapPlugin;
+use rand::{rngs::StdRng, SeedableRng};
 use tiled::{ObjectShape, PropertyValue};
 
 use crate::{
+    boss::BossPlugin,
     bullet::BulletPlugin,
     data::{AnimationData, GameData, GameDataPlugin},
-    enemy::EnemyPlugin,
-    game_over::GameOverPlugin,
+    enemy::{EnemyKind, EnemyPlugin},
+    game_over::{GameOverPlugin, RunStats},
     healthbar::{HealthBar, HealthBarPlugin},
-    loading::{FontHandles, LevelHandles, LoadingPlugin, TextureHandles, UiTextureHandles},
-    main_menu::MainMenuPlugin,
-    map::{find_objects, get_int_property, map_to_world, TiledMap, TiledMapPlugin},
+    loading::{
+        AudioHandles, FontHandles, GameDataHandles, LoadingPlugin, TextureHandles, UiTextureHandles,
+    },
+    main_menu::{MainMenuPlugin, SelectedLevel},
+    map::{
+        find_objects, get_int_property, get_string_property, map_to_world, MapSettings, TiledMap,
+        TiledMapPlugin, YSort,
+    },
+    map_validation::MapValidationPlugin,
+    menu_navigation::MenuNavigationPlugin,
+    minimap::{MinimapPlugin, MinimapSettings},
+    music::MusicPlugin,
+    pause::PausePlugin,
     reticle::ReticlePlugin,
+    sfx::{Sfx, SfxEvent, SfxPlugin},
+    slow_motion::SlowMotionPlugin,
+    status_effect::{StatusEffectPlugin, StatusEffectSprite},
     tower::{
-        TowerBundle, TowerChangedEvent, TowerKind, TowerPlugin, TowerSprite, TowerStats,
-        TOWER_PRICE,
+        LockedTowers, TargetingMode, TowerBundle, TowerChangedEvent, TowerConfig, TowerKind,
+        TowerPlugin, TowerSprite, TowerStats,
     },
     typing::{
-        AsciiModeEvent, TypingPlugin, TypingTarget, TypingTargetBundle, TypingTargetFinishedEvent,
+        AsciiModeEvent, Combo, FuriganaText, NumberKey, NumberKeyText, TypingPlugin,
+        TypingSettings, TypingTarget, TypingTargetBundle, TypingTargetFinishedEvent,
         TypingTargetSettings, TypingTargetText, TypingTargets,
     },
-    wave::{Wave, WavePlugin, WaveState, Waves},
+    wave::{EnemyPaths, Wave, WavePlugin, WaveSettings, WaveState, Waves},
+    wave_banner::WaveBannerPlugin,
 };
 
 extern crate anyhow;
 
 mod action_panel;
 mod atlas_loader;
+mod boss;
 mod bullet;
+mod counters;
 mod data;
 mod enemy;
 mod game_over;
@@ -50,27 +71,63 @@ mod layer;
 mod loading;
 mod main_menu;
 mod map;
+mod map_validation;
+mod menu_navigation;
+mod minimap;
+mod music;
+mod pause;
 mod reticle;
+mod sfx;
+mod slow_motion;
+mod status_effect;
 mod tower;
 mod typing;
 mod ui_color;
 mod wave;
+mod wave_banner;
 
 pub static FONT_SIZE: f32 = 22.0;
 pub static FONT_SIZE_INPUT: f32 = 22.0;
 pub static FONT_SIZE_LABEL: f32 = 16.0;
-
+pub static FONT_SIZE_FURIGANA: f32 = 10.0;
+
+/// A schedule that runs immediately after `Update`.
+///
+/// Commands issued by `Update` systems (e.g. spawning enemies or inserting a
+/// `TowerBundle` when a tower is built) have been applied by the time this schedule
+/// runs. Systems that need to react to those changes in the same frame belong here
+/// rather than in `Update`, where they would only see them a frame later.
+///
+/// Systems within this schedule that depend on each other's commands should be
+/// explicitly ordered so that Bevy applies those commands between them.
 #[derive(Debug, Hash, PartialEq, Eq, Clone, ScheduleLabel)]
 struct AfterUpdate;
 
+/// The game's states, which progress in the order `Load` → `MainMenu` → `Spawn` →
+/// `Playing` → `GameOver`. The game can be `Paused` while `Playing`, which may lead
+/// back to `MainMenu`. `GameOver` leads back to `MainMenu`, or straight to `Spawn` to
+/// play again.
+///
+/// Plugins that need to react to the flow of the game should use `OnEnter` / `OnExit`
+/// for these states, or read `StateTransitionEvent<TaipoState>`. The outcome of a game
+/// is available from `GameOverEvent` and the `GameResult` resource.
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
-enum TaipoState {
+pub enum TaipoState {
+    /// Assets are being loaded.
     #[default]
     Load,
+    /// The map and UI for a new game are being spawned.
     Spawn,
+    /// The player is choosing word lists.
     MainMenu,
     Playing,
+    /// The game is paused, and the pause menu is shown.
+    Paused,
+    /// The game has been won or lost. `GameResult` is available.
     GameOver,
+    /// The selected map has problems that prevent a game from starting, which are
+    /// listed in `MapErrors`.
+    MapError,
 }
 
 #[derive(Resource)]
@@ -87,6 +144,28 @@ impl Default for Currency {
     }
 }
 
+/// The source of randomness for gameplay.
+///
+/// Seeded from the `TAIPO_SEED` environment variable when it is set, so that a run
+/// can be reproduced.
+#[derive(Resource)]
+pub struct GameRng(pub StdRng);
+impl Default for GameRng {
+    fn default() -> Self {
+        let seed = std::env::var("TAIPO_SEED")
+            .ok()
+            .and_then(|seed| seed.parse::<u64>().ok());
+
+        match seed {
+            Some(seed) => {
+                info!("Using seed: {}", seed);
+                Self(StdRng::seed_from_u64(seed))
+            }
+            None => Self(StdRng::from_entropy()),
+        }
+    }
+}
+
 #[derive(Resource, Default)]
 pub struct TowerSelection {
     selected: Option<Entity>,
@@ -102,17 +181,93 @@ pub enum Action {
     BuildTower(TowerKind),
     UpgradeTower,
     SellTower,
+    CycleTargeting,
     SwitchLanguageMode,
     ToggleMute,
+    ToggleMinimap,
+    ToggleFurigana,
+    KillEnemy,
+    Undo,
+    StartWave,
+    CallWave,
+}
+
+/// Currency awarded for each second of delay skipped by calling a wave early.
+const CALL_WAVE_BONUS_PER_SEC: f32 = 0.5;
+
+/// How long after building or selling a tower that it can be undone.
+const UNDO_WINDOW_SECS: f32 = 5.0;
+
+/// The most recent action that can be reverted with `Action::Undo`.
+#[derive(Resource)]
+struct LastAction {
+    action: Option<UndoableAction>,
+    timer: Timer,
+}
+impl Default for LastAction {
+    fn default() -> Self {
+        Self {
+            action: None,
+            timer: Timer::from_seconds(UNDO_WINDOW_SECS, TimerMode::Once),
+        }
+    }
+}
+impl LastAction {
+    fn set(&mut self, action: UndoableAction) {
+        self.action = Some(action);
+        self.timer.reset();
+    }
+}
+enum UndoableAction {
+    BuildTower {
+        tower: Entity,
+        price: u32,
+    },
+    SellTower {
+        tower: Entity,
+        kind: TowerKind,
+        level: u32,
+        range: f32,
+        damage: u32,
+        upgrade_price: u32,
+        spent: u32,
+        refund: u32,
+    },
+}
+
+/// How long the currency display stays red after trying to spend more than the
+/// player has.
+const CURRENCY_FLASH_SECS: f32 = 0.4;
+
+/// Flashes the currency display while running.
+#[derive(Resource)]
+struct CurrencyFlash(Timer);
+impl Default for CurrencyFlash {
+    fn default() -> Self {
+        let mut timer = Timer::from_seconds(CURRENCY_FLASH_SECS, TimerMode::Once);
+        timer.tick(timer.duration());
+        Self(timer)
+    }
 }
 
 #[derive(Component)]
 struct CurrencyDisplay;
 #[derive(Component)]
 struct DelayTimerDisplay;
+#[derive(Component)]
+struct ComboDisplay;
+
+/// Something for enemies to attack. Enemies attack the goal with the same index as
+/// the wave that spawned them.
+#[derive(Component)]
+struct Goal {
+    index: i32,
+}
 
+/// Marks entities that belong to a single game. These are despawned by
+/// `cleanup_before_new_game` whenever the game returns to the main menu.
 #[derive(Component)]
-struct Goal;
+pub struct CleanupBeforeNewGame;
 
 #[derive(Component)]
 struct TowerSlot;
@@ -120,9 +275,87 @@ struct TowerSlot;
 struct TowerSlotLabel;
 #[derive(Component)]
 struct TowerSlotLabelBg;
-#[derive(Resource, Default)]
+#[derive(Resource)]
 struct AudioSettings {
     mute: bool,
+    /// If true, each correctly typed character plays a short tone that rises in pitch
+    /// as the best-matching typing target nears completion.
+    progress_tones: bool,
+    /// If true, each correctly typed character plays a quiet click.
+    correct_ticks: bool,
+    /// The volume of the background music, from 0 to 1.
+    music: f32,
+    /// The volume of sound effects, from 0 to 1.
+    sfx: f32,
+}
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            mute: false,
+            progress_tones: false,
+            correct_ticks: false,
+            music: 1.0,
+            sfx: 1.0,
+        }
+    }
+}
+impl AudioSettings {
+    fn music_volume(&self) -> f32 {
+        if self.mute {
+            0.0
+        } else {
+            self.music
+        }
+    }
+
+    fn sfx_volume(&self) -> f32 {
+        if self.mute {
+            0.0
+        } else {
+            self.sfx
+        }
+    }
+}
+#[derive(Default)]
+pub enum TimerFormat {
+    /// Seconds with one decimal place, e.g. `12.3`
+    #[default]
+    Seconds,
+    /// Minutes and whole seconds, e.g. `1:05`
+    MinutesSeconds,
+}
+impl TimerFormat {
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Seconds => Self::MinutesSeconds,
+            Self::MinutesSeconds => Self::Seconds,
+        }
+    }
+
+    pub fn format(&self, secs: f32) -> String {
+        match self {
+            Self::Seconds => format!("{:.1}", secs),
+            Self::MinutesSeconds => {
+                let secs = secs.ceil() as u32;
+                format!("{}:{:02}", secs / 60, secs % 60)
+            }
+        }
+    }
+}
+#[derive(Resource)]
+pub struct TimerDisplaySettings {
+    pub format: TimerFormat,
+    /// The wave delay timer is displayed more prominently when this many seconds or
+    /// fewer remain.
+    urgent_secs: f32,
+}
+impl Default for TimerDisplaySettings {
+    fn default() -> Self {
+        Self {
+            format: TimerFormat::default(),
+            urgent_secs: 3.0,
+        }
+    }
 }
 #[derive(Component)]
 pub struct HitPoints {
@@ -150,66 +383,56 @@ impl Default for Speed {
     }
 }
 
-#[derive(Component, Default)]
-pub struct StatusEffects(Vec<StatusEffect>);
-impl StatusEffects {
-    pub fn get_max_sub_armor(&self) -> u32 {
-        self.0
-            .iter()
-            .filter_map(|e| match e.kind {
-                StatusEffectKind::SubArmor(amt) => Some(amt),
-                _ => None,
-            })
-            .max()
-            .unwrap_or(0)
-    }
-
-    pub fn get_total_add_damage(&self) -> u32 {
-        self.0
-            .iter()
-            .filter_map(|e| match e.kind {
-                StatusEffectKind::AddDamage(amt) => Some(amt),
-                _ => None,
-            })
-            .sum::<u32>()
-    }
-}
-
-#[derive(Clone, Debug)]
-pub struct StatusEffect {
-    pub kind: StatusEffectKind,
-    pub timer: Option<Timer>,
-}
-#[derive(Clone, Debug)]
-pub enum StatusEffectKind {
-    SubArmor(u32),
-    AddDamage(u32),
-}
-#[derive(Component)]
-pub struct StatusUpSprite;
-#[derive(Component)]
-pub struct StatusDownSprite;
-
 #[derive(Component, Default)]
 pub struct Armor(u32);
 
 fn typing_target_finished_event(
     mut commands: Commands,
-    mut tower_state_query: Query<&mut TowerStats, With<TowerKind>>,
+    mut tower_state_query: Query<(&mut TowerStats, &TowerKind)>,
+    mut targeting_query: Query<&mut TargetingMode>,
     tower_children_query: Query<&Children, With<TowerSlot>>,
-    tower_sprite_query: Query<Entity, With<TowerSprite>>,
+    mut tower_sprite_query: Query<(&mut Sprite, &mut Transform), With<TowerSprite>>,
+    status_sprite_query: Query<(), With<StatusEffectSprite>>,
     action_query: Query<&Action>,
+    mut label_query: Query<(&Action, &mut TypingTarget)>,
+    mut enemy_hit_points_query: Query<&mut HitPoints, With<EnemyKind>>,
     texture_handles: Res<TextureHandles>,
-    (mut reader, mut toggle_events, mut tower_changed_events): (
+    (tower_config, locked_towers, combo, mut run_stats, audio_handles, mut currency_flash): (
+        Res<TowerConfig>,
+        Res<LockedTowers>,
+        Res<Combo>,
+        ResMut<RunStats>,
+        Res<AudioHandles>,
+        ResMut<CurrencyFlash>,
+    ),
+    (mut reader, mut toggle_events, mut tower_changed_events, mut sfx_events): (
         EventReader<TypingTargetFinishedEvent>,
         EventWriter<AsciiModeEvent>,
         EventWriter<TowerChangedEvent>,
+        EventWriter<SfxEvent>,
     ),
-    (mut currency, mut selection, mut action_panel, mut sound_settings): (
+    (
+        mut currency,
+        mut selection,
+        mut action_panel,
+        mut sound_settings,
+        mut minimap_settings,
+        mut typing_settings,
+        mut last_action,
+        mut typing_targets,
+        mut wave_state,
+        wave_settings,
+    ): (
         ResMut<Currency>,
         ResMut<TowerSelection>,
         ResMut<ActionPanel>,
         ResMut<AudioSettings>,
+        ResMut<MinimapSettings>,
+        ResMut<TypingSettings>,
+        ResMut<LastAction>,
+        ResMut<TypingTargets>,
+        ResMut<WaveState>,
+        Res<WaveSettings>,
     ),
 ) {
     for event in reader.read() {
@@ -220,9 +443,13 @@ fn typing_target_finished_event(
         if let Ok(action) = action_query.get(event.entity) {
             info!("Processing action: {:?}", action);
 
+            // More specific sounds for the action take precedence.
+            sfx_events.send(SfxEvent(Sfx::Complete));
+
             if let Action::GenerateMoney = *action {
-                currency.current = currency.current.saturating_add(1);
-                currency.total_earned = currency.total_earned.saturating_add(1);
+                let reward = 1 + combo.bonus();
+                currency.current = currency.current.saturating_add(reward);
+                currency.total_earned = currency.total_earned.saturating_add(reward);
             } else if let Action::SelectTower(tower) = *action {
                 selection.selected = Some(tower);
                 action_panel.set_changed();
@@ -235,67 +462,203 @@ fn typing_target_finished_event(
                 action_panel.set_changed();
             } else if let Action::ToggleMute = *action {
                 sound_settings.mute = !sound_settings.mute;
+            } else if let Action::ToggleMinimap = *action {
+                minimap_settings.enabled = !minimap_settings.enabled;
+            } else if let Action::ToggleFurigana = *action {
+                typing_settings.show_furigana = !typing_settings.show_furigana;
+            } else if let Action::StartWave = *action {
+                if wave_settings.wait_for_ready && !wave_state.delay_timer.finished() {
+                    wave_state.start();
+                }
+            } else if let Action::CallWave = *action {
+                // Once the delay has finished, there is nothing left to skip, so this
+                // can only pay out once per wave.
+                if !wave_state.delay_timer.finished() {
+                    let bonus = (wave_state.delay_timer.remaining_secs() * CALL_WAVE_BONUS_PER_SEC)
+                        .floor() as u32;
+
+                    wave_state.start();
+
+                    currency.current = currency.current.saturating_add(bonus);
+                    currency.total_earned = currency.total_earned.saturating_add(bonus);
+                }
+            } else if let Action::KillEnemy = *action {
+                // `enemy::death` takes care of the reward
+                if let Ok(mut hit_points) = enemy_hit_points_query.get_mut(event.entity) {
+                    hit_points.current = 0;
+                }
             } else if let Action::UpgradeTower = *action {
-                // TODO tower config from game.ron
                 if let Some(tower) = selection.selected {
-                    if let Ok(mut tower_state) = tower_state_query.get_mut(tower) {
-                        // XXX
-                        if tower_state.level < 2 && currency.current >= tower_state.upgrade_price {
-                            tower_state.level += 1;
-                            tower_state.range += 32.0;
-
+                    if let Ok((mut tower_state, tower_kind)) = tower_state_query.get_mut(tower) {
+                        let config = tower_config.get(*tower_kind);
+
+                        if tower_state.level < config.max_level
+                            && currency.current < tower_state.upgrade_price
+                        {
+                            cant_afford(
+                                &mut commands,
+                                &mut currency_flash,
+                                &audio_handles,
+                                &sound_settings,
+                            );
+                        } else if tower_state.level < config.max_level {
                             currency.current -= tower_state.upgrade_price;
+                            tower_state.spent += tower_state.upgrade_price;
+
+                            tower_state.upgrade(&config);
+                            run_stats.towers_upgraded += 1;
+
+                            // Undoing a build or sale from before the upgrade would
+                            // discard it.
+                            last_action.action = None;
+
+                            if typing_settings.longer_upgraded_targets {
+                                for (_, mut target) in label_query.iter_mut().filter(
+                                    |(action, _)| matches!(action, Action::SelectTower(t) if *t == tower),
+                                ) {
+                                    if let Some(longer) = typing_targets.replace_with_longer(&target)
+                                    {
+                                        *target = longer;
+                                    }
+                                }
+                            }
 
                             tower_changed_events.send(TowerChangedEvent);
+                            sfx_events.send(SfxEvent(Sfx::Upgrade));
                         }
                     }
                 }
 
                 action_panel.set_changed();
             } else if let Action::BuildTower(tower_kind) = *action {
-                if currency.current < TOWER_PRICE {
+                let config = tower_config.get(tower_kind);
+                let price = config.price;
+                if locked_towers.is_locked(tower_kind) {
                     continue;
                 }
-                currency.current -= TOWER_PRICE;
+                if !tower_config.can_afford(tower_kind, &currency) {
+                    cant_afford(
+                        &mut commands,
+                        &mut currency_flash,
+                        &audio_handles,
+                        &sound_settings,
+                    );
+                    continue;
+                }
+                currency.current -= price;
 
                 if let Some(tower) = selection.selected {
-                    commands.entity(tower).insert(TowerBundle::new(tower_kind));
+                    commands
+                        .entity(tower)
+                        .insert(TowerBundle::new(tower_kind, &config));
+
+                    last_action.set(UndoableAction::BuildTower { tower, price });
+                    run_stats.towers_built += 1;
 
                     tower_changed_events.send(TowerChangedEvent);
+                    sfx_events.send(SfxEvent(Sfx::Build));
                 }
             } else if let Action::SellTower = *action {
                 if let Some(tower) = selection.selected {
-                    commands.entity(tower).remove::<TowerBundle>();
-
-                    if let Ok(children) = tower_children_query.get(tower) {
-                        for child in children.iter() {
-                            if let Ok(ent) = tower_sprite_query.get(*child) {
-                                commands.entity(ent).despawn();
-
-                                let new_child = commands
-                                    .spawn((
-                                        Sprite {
-                                            image: texture_handles.tower_slot.clone(),
-                                            ..default()
-                                        },
-                                        Transform::from_translation(Vec3::new(
-                                            0.0,
-                                            0.0,
-                                            layer::TOWER_SLOT,
-                                        )),
-                                        TowerSprite,
-                                    ))
-                                    .id();
-
-                                commands.entity(tower).add_child(new_child);
-                            }
-                        }
+                    clear_tower_slot(
+                        &mut commands,
+                        tower,
+                        &tower_children_query,
+                        &mut tower_sprite_query,
+                        &status_sprite_query,
+                        &texture_handles,
+                    );
+
+                    // Half of everything spent on the tower, including its upgrades, is
+                    // refunded.
+                    if let Ok((stats, tower_kind)) = tower_state_query.get(tower) {
+                        let refund = (tower_config.get(*tower_kind).price + stats.spent) / 2;
+                        currency.current = currency.current.saturating_add(refund);
+
+                        last_action.set(UndoableAction::SellTower {
+                            tower,
+                            kind: *tower_kind,
+                            level: stats.level,
+                            range: stats.range,
+                            damage: stats.damage,
+                            upgrade_price: stats.upgrade_price,
+                            spent: stats.spent,
+                            refund,
+                        });
                     }
 
-                    // TODO refund upgrade price too
-                    currency.current = currency.current.saturating_add(TOWER_PRICE / 2);
+                    run_stats.towers_sold += 1;
 
                     tower_changed_events.send(TowerChangedEvent);
+                    sfx_events.send(SfxEvent(Sfx::Sell));
+                }
+            } else if let Action::CycleTargeting = *action {
+                if let Some(tower) = selection.selected {
+                    if let Ok(mut targeting) = targeting_query.get_mut(tower) {
+                        *targeting = targeting.next();
+                    }
+                }
+
+                action_panel.set_changed();
+            } else if let Action::Undo = *action {
+                if last_action.timer.finished() {
+                    continue;
+                }
+
+                // The refund for a sold tower must be paid back.
+                if let Some(UndoableAction::SellTower { refund, .. }) = last_action.action {
+                    if currency.current < refund {
+                        cant_afford(
+                            &mut commands,
+                            &mut currency_flash,
+                            &audio_handles,
+                            &sound_settings,
+                        );
+                        continue;
+                    }
+                }
+
+                match last_action.action.take() {
+                    Some(UndoableAction::BuildTower { tower, price }) => {
+                        clear_tower_slot(
+                            &mut commands,
+                            tower,
+                            &tower_children_query,
+                            &mut tower_sprite_query,
+                            &status_sprite_query,
+                            &texture_handles,
+                        );
+
+                        currency.current = currency.current.saturating_add(price);
+                        run_stats.towers_built = run_stats.towers_built.saturating_sub(1);
+
+                        tower_changed_events.send(TowerChangedEvent);
+                    }
+                    Some(UndoableAction::SellTower {
+                        tower,
+                        kind,
+                        level,
+                        range,
+                        damage,
+                        upgrade_price,
+                        spent,
+                        refund,
+                    }) => {
+                        currency.current -= refund;
+                        run_stats.towers_sold = run_stats.towers_sold.saturating_sub(1);
+
+                        commands.entity(tower).insert(
+                            TowerBundle::new(kind, &tower_config.get(kind))
+                                .with_level(level)
+                                .with_range(range)
+                                .with_damage(damage)
+                                .with_upgrade_price(upgrade_price)
+                                .with_spent(spent),
+                        );
+
+                        tower_changed_events.send(TowerChangedEvent);
+                    }
+                    None => {}
                 }
             }
 
@@ -309,16 +672,104 @@ fn typing_target_finished_event(
     }
 }
 
+/// Lets the player know that an action costs more than they have, by flashing the
+/// currency display and playing the wrong character sound.
+fn cant_afford(
+    commands: &mut Commands,
+    currency_flash: &mut CurrencyFlash,
+    audio_handles: &AudioHandles,
+    audio_settings: &AudioSettings,
+) {
+    currency_flash.0.reset();
+
+    if !audio_settings.mute {
+        commands.spawn((
+            AudioPlayer(audio_handles.wrong_character.clone()),
+            PlaybackSettings::DESPAWN.with_volume(Volume::new(audio_settings.sfx_volume())),
+        ));
+    }
+}
+
+/// Removes the tower from a tower slot and restores the empty slot's appearance.
+fn clear_tower_slot(
+    commands: &mut Commands,
+    tower: Entity,
+    tower_children_query: &Query<&Children, With<TowerSlot>>,
+    tower_sprite_query: &mut Query<(&mut Sprite, &mut Transform), With<TowerSprite>>,
+    status_sprite_query: &Query<(), With<StatusEffectSprite>>,
+    texture_handles: &TextureHandles,
+) {
+    commands.entity(tower).remove::<TowerBundle>();
+
+    let Ok(children) = tower_children_query.get(tower) else {
+        return;
+    };
+
+    for child in children.iter() {
+        if let Ok((mut sprite, mut transform)) = tower_sprite_query.get_mut(*child) {
+            sprite.image = texture_handles.tower_slot.clone();
+            sprite.color = Color::WHITE;
+            transform.translation = Vec3::new(0.0, 0.0, layer::TOWER_SLOT);
+            transform.scale = Vec3::ONE;
+        }
+
+        if status_sprite_query.get(*child).is_ok() {
+            commands.entity(*child).despawn_recursive();
+        }
+    }
+}
+
+fn tick_last_action(mut last_action: ResMut<LastAction>, time: Res<Time>) {
+    if last_action.action.is_none() {
+        return;
+    }
+
+    last_action.timer.tick(time.delta());
+    if last_action.timer.finished() {
+        last_action.action = None;
+    }
+}
+
+/// Clears the tower selection if the selected entity no longer exists, so that the
+/// action panel and range indicator don't keep referring to it.
+fn clear_stale_selection(
+    mut selection: ResMut<TowerSelection>,
+    mut action_panel: ResMut<ActionPanel>,
+    entities: &Entities,
+) {
+    let Some(selected) = selection.selected else {
+        return;
+    };
+
+    if !entities.contains(selected) {
+        selection.selected = None;
+        action_panel.set_changed();
+    }
+}
+
 fn update_timer_display(
-    mut query: Query<&mut Text, With<DelayTimerDisplay>>,
+    mut query: Query<(&mut Text, &mut TextColor, &mut TextFont), With<DelayTimerDisplay>>,
     wave_state: Res<WaveState>,
+    settings: Res<TimerDisplaySettings>,
 ) {
-    if !wave_state.is_changed() {
+    if !wave_state.is_changed() && !settings.is_changed() {
         return;
     }
 
-    for mut text in query.iter_mut() {
-        text.0 = format!("{:.1}", wave_state.delay_timer.remaining_secs());
+    let remaining = wave_state.delay_timer.remaining_secs();
+    let urgent = remaining > 0.0 && remaining <= settings.urgent_secs;
+
+    for (mut text, mut color, mut font) in query.iter_mut() {
+        text.0 = settings.format.format(remaining);
+
+        let (new_color, new_size) = if urgent {
+            (ui_color::BAD_TEXT, FONT_SIZE * 1.25)
+        } else {
+            (ui_color::NORMAL_TEXT, FONT_SIZE)
+        };
+
+        color.0 = new_color.into();
+        font.font_size = new_size;
     }
 }
 
@@ -335,14 +786,79 @@ fn update_currency_text(
     }
 }
 
+fn update_currency_flash(
+    mut currency_flash: ResMut<CurrencyFlash>,
+    mut query: Query<&mut TextColor, With<CurrencyDisplay>>,
+    time: Res<Time>,
+) {
+    if currency_flash.0.finished() {
+        return;
+    }
+
+    currency_flash.0.tick(time.delta());
+
+    for mut color in query.iter_mut() {
+        color.0 = if currency_flash.0.finished() {
+            ui_color::NORMAL_TEXT.into()
+        } else {
+            ui_color::BAD_TEXT.into()
+        };
+    }
+}
+
+fn update_combo_text(combo: Res<Combo>, mut query: Query<&mut Text, With<ComboDisplay>>) {
+    if !combo.is_changed() {
+        return;
+    }
+
+    for mut text in query.iter_mut() {
+        text.0 = if combo.0 > 0 {
+            format!("{} combo", combo.0)
+        } else {
+            "".to_string()
+        };
+    }
+}
+
+/// Despawns everything left over from a previous game and resets the resources that
+/// track its progress, so that a fresh game can be started from the main menu or by
+/// playing again.
+fn cleanup_before_new_game(
+    mut commands: Commands,
+    query: Query<Entity, With<CleanupBeforeNewGame>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    commands.insert_resource(Waves::default());
+    commands.insert_resource(EnemyPaths::default());
+    commands.remove_resource::<WaveState>();
+    // `TypingTargets` is left alone, because playing again prepares the next game's
+    // words before this runs. `prepare_typing_targets` replaces them either way.
+    commands.insert_resource(Currency::default());
+    commands.insert_resource(TowerSelection::default());
+    commands.insert_resource(ActionPanel::default());
+    commands.insert_resource(LastAction::default());
+    commands.insert_resource(RunStats::default());
+    commands.insert_resource(CurrencyFlash::default());
+}
+
 fn startup_system(
     mut commands: Commands,
     ui_texture_handles: ResMut<UiTextureHandles>,
     font_handles: Res<FontHandles>,
     currency: Res<Currency>,
+    game_data_handles: Res<GameDataHandles>,
+    game_data_assets: Res<Assets<GameData>>,
 ) {
     info!("startup");
 
+    let fixed_targets = &game_data_assets
+        .get(&game_data_handles.game)
+        .unwrap()
+        .fixed_targets;
+
     commands
         .spawn((
             Node {
@@ -355,6 +871,7 @@ fn startup_system(
                 ..default()
             },
             BackgroundColor(ui_color::TRANSPARENT_BACKGROUND.into()),
+            CleanupBeforeNewGame,
         ))
         .with_children(|parent| {
             parent.spawn((
@@ -389,6 +906,23 @@ fn startup_system(
                 TextColor(ui_color::NORMAL_TEXT.into()),
                 CurrencyDisplay,
             ));
+            parent.spawn((
+                Text::default(),
+                Node {
+                    margin: UiRect {
+                        right: Val::Px(10.0),
+                        ..default()
+                    },
+                    ..default()
+                },
+                TextFont {
+                    font: font_handles.jptext.clone(),
+                    font_size: FONT_SIZE_LABEL,
+                    ..default()
+                },
+                TextColor(ui_color::GOOD_TEXT.into()),
+                ComboDisplay,
+            ));
             parent.spawn((
                 ImageNode {
                     image: ui_texture_handles.timer_ui.clone(),
@@ -423,53 +957,69 @@ fn startup_system(
             ));
         });
 
-    commands.spawn(TypingTargetBundle {
-        target: TypingTarget::new("help"),
-        settings: TypingTargetSettings {
-            fixed: true,
-            disabled: false,
-        },
-        action: Action::SwitchLanguageMode,
-    });
-
-    commands.spawn(TypingTargetBundle {
-        target: TypingTarget::new("mute"),
-        settings: TypingTargetSettings {
-            fixed: true,
-            disabled: false,
-        },
-        action: Action::ToggleMute,
-    });
+    for (target, action) in fixed_targets.0.iter() {
+        commands.spawn((
+            TypingTargetBundle {
+                target: target.clone(),
+                settings: TypingTargetSettings {
+                    fixed: true,
+                    disabled: false,
+                },
+                action: action.clone(),
+            },
+            CleanupBeforeNewGame,
+        ));
+    }
 }
 
 fn update_tower_slot_labels(
-    mut bg_query: Query<&mut Sprite, With<TowerSlotLabelBg>>,
+    mut bg_query: Query<(&mut Sprite, &Children), With<TowerSlotLabelBg>>,
     query: Query<(&TextLayoutInfo, &Parent), (With<TowerSlotLabel>, Changed<TextLayoutInfo>)>,
+    mut number_key_query: Query<&mut Transform, With<NumberKeyText>>,
 ) {
     for (info, parent) in query.iter() {
-        if let Ok(mut bg_sprite) = bg_query.get_mut(**parent) {
+        if let Ok((mut bg_sprite, children)) = bg_query.get_mut(**parent) {
             if let Some(bg_sprite_size) = bg_sprite.custom_size {
-                bg_sprite.custom_size = Some(Vec2::new(info.size.x + 8.0, bg_sprite_size.y));
+                let width = info.size.x + 8.0;
+                bg_sprite.custom_size = Some(Vec2::new(width, bg_sprite_size.y));
+
+                for child in children.iter() {
+                    if let Ok(mut transform) = number_key_query.get_mut(*child) {
+                        transform.translation.x = -width / 2.0 - 4.0;
+                    }
+                }
             }
         }
     }
 }
 
+/// Removes consecutive points of a path that are in the same place, returning the
+/// number removed. Zero-length segments would make enemies arrive at the same waypoint
+/// repeatedly.
+fn skip_duplicate_points(points: &mut Vec<Vec2>) -> usize {
+    let len = points.len();
+    points.dedup_by(|a, b| a.distance_squared(*b) < f32::EPSILON);
+    len - points.len()
+}
+
 fn spawn_map_objects(
     mut commands: Commands,
     mut typing_targets: ResMut<TypingTargets>,
     mut waves: ResMut<Waves>,
-    level_handles: Res<LevelHandles>,
+    selected_level: Res<SelectedLevel>,
     font_handles: Res<FontHandles>,
     texture_handles: Res<TextureHandles>,
     maps: Res<Assets<TiledMap>>,
+    tower_config: Res<TowerConfig>,
 ) {
-    let Some(tiled_map) = maps.get(&level_handles.one) else {
+    let Some(tiled_map) = maps.get(&selected_level.0) else {
         panic!("Queried map not in assets?");
     };
 
     info!("spawn_map_objects");
 
+    let map_settings = MapSettings::from_map(tiled_map);
+
     // paths
 
     let paths: HashMap<i32, Vec<Vec2>> = find_objects(tiled_map, "enemy_path")
@@ -483,19 +1033,25 @@ fn spawn_map_objects(
                 return None;
             };
 
-            let transformed: Vec<Vec2> = points
+            let mut transformed: Vec<Vec2> = points
                 .iter()
                 .map(|(x, y)| {
-                    let transform = map_to_world(
-                        tiled_map,
-                        Vec2::new(*x, *y) + Vec2::new(o.x, o.y),
-                        Vec2::ZERO,
-                        0.0,
-                    );
+                    // Points are relative to the object, and rotate with it.
+                    let point = Vec2::from_angle(o.rotation.to_radians()).rotate(Vec2::new(*x, *y));
+                    let transform =
+                        map_to_world(tiled_map, point + Vec2::new(o.x, o.y), Vec2::ZERO, 0.0, 0.0);
                     transform.translation.truncate()
                 })
                 .collect();
 
+            let skipped = skip_duplicate_points(&mut transformed);
+            if skipped > 0 {
+                warn!(
+                    "enemy_path {}: skipped {} duplicate point(s)",
+                    index, skipped
+                );
+            }
+
             Some((*index, transformed))
         })
         .collect();
@@ -516,6 +1072,7 @@ fn spawn_map_objects(
     }
 
     commands.insert_resource(WaveState::from(waves.current().unwrap()));
+    commands.insert_resource(EnemyPaths(paths));
 
     // goal
 
@@ -528,6 +1085,8 @@ fn spawn_map_objects(
             }
         };
 
+        let index = get_int_property(&o, "index").unwrap_or(0);
+
         let pos = Vec2::new(o.x, o.y);
         let size = match o.shape {
             ObjectShape::Rect { width, height } => Vec2::new(width, height),
@@ -537,10 +1096,13 @@ fn spawn_map_objects(
             }
         };
 
-        let transform = map_to_world(tiled_map, pos, size, layer::ENEMY);
+        let mut transform = map_to_world(tiled_map, pos, size, o.rotation, layer::ENEMY);
+        // Only the goal's position depends on its rotation, so that its health bar stays
+        // level.
+        transform.rotation = Quat::IDENTITY;
 
         commands.spawn((
-            Goal,
+            Goal { index },
             transform,
             Visibility::default(),
             HitPoints::full(hp),
@@ -550,6 +1112,7 @@ fn spawn_map_objects(
                 show_empty: true,
                 ..default()
             },
+            CleanupBeforeNewGame,
         ));
     });
 
@@ -565,23 +1128,29 @@ fn spawn_map_objects(
         })
         .collect::<Vec<_>>();
 
-    tower_slots.sort_by(|a, b| a.1.cmp(&b.1));
+    tower_slots.sort_by_key(|a| a.1);
 
-    for (obj, _index) in tower_slots {
+    for (i, (obj, _index)) in tower_slots.into_iter().enumerate() {
         let pos = Vec2::new(obj.x, obj.y);
         let size = match obj.shape {
             ObjectShape::Rect { width, height } => Vec2::new(width, height),
             _ => continue,
         };
 
-        let transform = map_to_world(tiled_map, pos, size, 0.0);
+        let transform = map_to_world(tiled_map, pos, size, obj.rotation, 0.0);
 
-        let mut label_bg_transform = transform;
+        // The label stays level below the slot, even if the slot is rotated.
+        let mut label_bg_transform = transform.with_rotation(Quat::IDENTITY);
         label_bg_transform.translation.y -= 32.0;
         label_bg_transform.translation.z = layer::TOWER_SLOT_LABEL_BG;
 
         let tower = commands
-            .spawn((TowerSlot, transform, Visibility::default()))
+            .spawn((
+                TowerSlot,
+                transform,
+                Visibility::default(),
+                CleanupBeforeNewGame,
+            ))
             .with_children(|parent| {
                 parent.spawn((
                     Sprite {
@@ -594,9 +1163,41 @@ fn spawn_map_objects(
             })
             .id();
 
-        let target = typing_targets.pop_front();
+        if map_settings.y_sort {
+            // The tower sprite is a child of the slot at layer::TOWER, so offset the slot
+            // such that the tower itself ends up in layer::Y_SORTED.
+            commands
+                .entity(tower)
+                .insert(YSort(layer::Y_SORTED - layer::TOWER));
+        }
+
+        // Slots may start with a tower already built, for scripted scenarios.
+        if obj.properties.contains_key("tower_kind") {
+            match get_string_property(&obj, "tower_kind").and_then(|kind| kind.parse()) {
+                Ok(tower_kind) => {
+                    let config = tower_config.get(tower_kind);
+                    let level = match get_int_property(&obj, "level") {
+                        Ok(level) => (level.max(1) as u32).min(config.max_level),
+                        Err(_) => 1,
+                    };
+
+                    let mut bundle = TowerBundle::new(tower_kind, &config);
+                    for _ in 1..level {
+                        bundle.stats.upgrade(&config);
+                    }
 
-        commands
+                    commands.entity(tower).insert(bundle);
+                }
+                Err(err) => warn!("tower_slot: {}", err),
+            }
+        }
+
+        let Some(target) = typing_targets.pop_front() else {
+            warn!("tower_slot: no unambiguous words left for its label");
+            continue;
+        };
+
+        let label = commands
             .spawn((
                 Sprite {
                     color: ui_color::TRANSPARENT_BACKGROUND.into(),
@@ -605,6 +1206,7 @@ fn spawn_map_objects(
                 },
                 label_bg_transform,
                 TowerSlotLabelBg,
+                CleanupBeforeNewGame,
                 TypingTargetBundle {
                     target: target.clone(),
                     action: Action::SelectTower(tower),
@@ -625,17 +1227,72 @@ fn spawn_map_objects(
                         TypingTargetText,
                         TowerSlotLabel,
                     ))
-                    .with_child((
-                        TextSpan::new(target.displayed_chunks.join("")),
+                    .with_children(|parent| {
+                        parent.spawn((
+                            TextSpan::default(),
+                            TextFont {
+                                font: font_handles.jptext.clone(),
+                                font_size: FONT_SIZE_LABEL,
+                                ..default()
+                            },
+                            TextColor(ui_color::PARTIAL_TEXT.into()),
+                        ));
+                        parent.spawn((
+                            TextSpan::new(target.displayed_chunks.join("")),
+                            TextFont {
+                                font: font_handles.jptext.clone(),
+                                font_size: FONT_SIZE_LABEL,
+                                ..default()
+                            },
+                            TextColor(ui_color::NORMAL_TEXT.into()),
+                        ));
+                    });
+                // Just above the label's background
+                parent.spawn((
+                    Text2d::new(""),
+                    TextFont {
+                        font: font_handles.jptext.clone(),
+                        font_size: FONT_SIZE_FURIGANA,
+                        ..default()
+                    },
+                    TextColor(ui_color::NORMAL_TEXT.into()),
+                    Transform::from_xyz(
+                        0.0,
+                        (FONT_SIZE_LABEL + FONT_SIZE_FURIGANA) / 2.0 + 4.0,
+                        0.1,
+                    ),
+                    Visibility::Hidden,
+                    FuriganaText,
+                ));
+            })
+            .id();
+
+        // The first nine slots can also be selected with the number keys.
+        let number = i as u32 + 1;
+        if number <= 9 {
+            commands
+                .entity(label)
+                .insert(NumberKey(number))
+                .with_children(|parent| {
+                    // Just left of the label's background, moved by `update_tower_slot_labels`
+                    parent.spawn((
+                        Text2d::new(number.to_string()),
                         TextFont {
                             font: font_handles.jptext.clone(),
                             font_size: FONT_SIZE_LABEL,
                             ..default()
                         },
                         TextColor(ui_color::NORMAL_TEXT.into()),
+                        Anchor::CenterRight,
+                        Transform::from_xyz(-58.0, 0.0, 0.1),
+                        Visibility::Hidden,
+                        NumberKeyText,
                     ));
-            });
+                });
+        }
     }
+
+    commands.insert_resource(map_settings);
 }
 
 fn check_spawn(
@@ -697,7 +1354,8 @@ fn main() {
     app.init_state::<TaipoState>();
 
     app.init_asset::<AtlasImage>()
-        .register_asset_loader(AtlasImageLoader);
+        .register_asset_loader(AtlasImageLoader)
+        .add_systems(Update, validate_atlas_images);
 
     app.add_plugins(TilemapPlugin)
         .add_plugins(TiledMapPlugin)
@@ -712,14 +1370,30 @@ fn main() {
         .add_plugins(WavePlugin)
         .add_plugins(ReticlePlugin)
         .add_plugins(GameOverPlugin)
-        .add_plugins(ActionPanelPlugin);
+        .add_plugins(ActionPanelPlugin)
+        .add_plugins(MinimapPlugin)
+        .add_plugins(SlowMotionPlugin)
+        .add_plugins(WaveBannerPlugin)
+        .add_plugins(PausePlugin)
+        .add_plugins(BossPlugin)
+        .add_plugins(MusicPlugin)
+        .add_plugins(SfxPlugin)
+        .add_plugins(MenuNavigationPlugin)
+        .add_plugins(MapValidationPlugin)
+        .add_plugins(StatusEffectPlugin);
 
     app.init_resource::<Currency>()
+        .init_resource::<GameRng>()
         .init_resource::<TowerSelection>()
-        .init_resource::<AudioSettings>();
+        .init_resource::<AudioSettings>()
+        .init_resource::<TimerDisplaySettings>()
+        .init_resource::<LastAction>()
+        .init_resource::<CurrencyFlash>();
 
     app.add_event::<TowerChangedEvent>();
 
+    app.add_systems(OnEnter(TaipoState::MainMenu), cleanup_before_new_game);
+
     app.add_systems(
         OnEnter(TaipoState::Spawn),
         (spawn_map_objects, startup_system),
@@ -731,8 +1405,12 @@ fn main() {
         Update,
         (
             update_timer_display,
+            clear_stale_selection,
+            tick_last_action,
             typing_target_finished_event,
             update_currency_text.after(typing_target_finished_event),
+            update_currency_flash.after(typing_target_finished_event),
+            update_combo_text,
         )
             .run_if(in_state(TaipoState::Playing)),
     );
@@ -750,3 +1428,103 @@ fn main() {
 
     app.run();
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::{ecs::system::RunSystemOnce, state::app::StatesPlugin};
+
+    use super::*;
+
+    #[test]
+    fn despawned_selected_tower_is_unselected() {
+        let mut world = World::new();
+        world.init_resource::<ActionPanel>();
+
+        let config = TowerConfig::default().get(TowerKind::Basic);
+        let tower = world
+            .spawn(TowerBundle::new(TowerKind::Basic, &config).with_level(2))
+            .id();
+        let other = world
+            .spawn(TowerBundle::new(TowerKind::Basic, &config))
+            .id();
+
+        world.insert_resource(TowerSelection {
+            selected: Some(other),
+        });
+        world.clear_trackers();
+        world.run_system_once(clear_stale_selection).unwrap();
+        assert_eq!(world.resource::<TowerSelection>().selected, Some(other));
+        assert!(!world.resource_ref::<ActionPanel>().is_changed());
+
+        world.resource_mut::<TowerSelection>().selected = Some(tower);
+        world.despawn(tower);
+        world.clear_trackers();
+        world.run_system_once(clear_stale_selection).unwrap();
+        assert_eq!(world.resource::<TowerSelection>().selected, None);
+        assert!(world.resource_ref::<ActionPanel>().is_changed());
+    }
+
+    #[test]
+    fn duplicate_path_points_are_skipped() {
+        let mut points = vec![
+            Vec2::ZERO,
+            Vec2::ZERO,
+            Vec2::new(32.0, 0.0),
+            Vec2::new(32.0, 0.0),
+            Vec2::new(32.0, 0.0),
+            Vec2::new(32.0, 32.0),
+            Vec2::ZERO,
+        ];
+
+        assert_eq!(skip_duplicate_points(&mut points), 3);
+        assert_eq!(
+            points,
+            vec![
+                Vec2::ZERO,
+                Vec2::new(32.0, 0.0),
+                Vec2::new(32.0, 32.0),
+                Vec2::ZERO
+            ]
+        );
+    }
+
+    #[test]
+    fn quitting_to_main_menu_cleans_up_the_game() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin))
+            .insert_state(TaipoState::Paused)
+            .add_systems(OnEnter(TaipoState::MainMenu), cleanup_before_new_game);
+
+        let game = app
+            .world_mut()
+            .spawn(CleanupBeforeNewGame)
+            .with_child(Goal { index: 0 })
+            .id();
+        let child = app.world().entity(game).get::<Children>().unwrap()[0];
+        let kept = app.world_mut().spawn_empty().id();
+
+        app.insert_resource(Currency {
+            current: 200,
+            total_earned: 300,
+        });
+        app.insert_resource(Waves {
+            waves: vec![Wave::default()],
+            current: 1,
+        });
+        app.insert_resource(WaveState::default());
+
+        app.world_mut()
+            .resource_mut::<NextState<TaipoState>>()
+            .set(TaipoState::MainMenu);
+        app.update();
+
+        assert!(app.world().get_entity(game).is_err());
+        assert!(app.world().get_entity(child).is_err());
+        assert!(app.world().get_entity(kept).is_ok());
+
+        let currency = app.world().resource::<Currency>();
+        assert_eq!((currency.current, currency.total_earned), (10, 0));
+        assert!(app.world().resource::<Waves>().waves.is_empty());
+        assert!(!app.world().contains_resource::<WaveState>());
+    }
+}
@@ -1,43 +1,392 @@
-use bevy::prelude::*;
+use bevy::{
+    audio::Volume,
+    prelude::*,
+    utils::{HashSet, Instant},
+};
 
-use rand::{prelude::SliceRandom, thread_rng};
+use rand::prelude::SliceRandom;
 
 use crate::{
+    action_panel::{ActionPanelSettings, NUM_ACTION_PANEL_ITEMS},
     data::{WordList, WordListMenuItem},
-    loading::{FontHandles, GameDataHandles, LevelHandles},
-    map::{TiledMapBundle, TiledMapHandle},
-    typing::TypingTargets,
-    ui_color, GameData, TaipoState, TypingTarget, FONT_SIZE_LABEL,
+    enemy::EnemySettings,
+    game_over::BestStats,
+    healthbar::HealthBarSettings,
+    loading::{AudioHandles, EnemyAtlasHandles, FontHandles, GameDataHandles, LevelHandles},
+    map::{find_objects, TiledMap, TiledMapBundle, TiledMapHandle},
+    map_validation::{validate_map, MapErrors},
+    menu_navigation::{key_label, MenuAction, MenuNavigationBindings, RebindingAction},
+    typing::{TypingSettings, TypingTargets},
+    ui_color,
+    wave::WaveSettings,
+    AudioSettings, GameData, GameRng, TaipoState, TimerDisplaySettings, TimerFormat, TypingTarget,
+    FONT_SIZE_LABEL,
 };
 
 pub struct MainMenuPlugin;
 
+const FONT_SIZE_BEST: f32 = 12.0;
+
 impl Plugin for MainMenuPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedWordLists>()
+            .init_resource::<GameSpeed>();
+
         app.add_systems(OnEnter(TaipoState::MainMenu), main_menu_startup);
 
         app.add_systems(
             Update,
-            (main_menu, button_system).run_if(in_state(TaipoState::MainMenu)),
+            (
+                main_menu,
+                button_system,
+                endless_button_system,
+                level_button_system,
+                game_speed_button_system,
+                volume_button_system,
+                ticks_button_system,
+                longer_words_button_system,
+                wait_for_start_button_system,
+                tones_button_system,
+                display_button_system,
+                progress_button_system,
+                hints_button_system,
+                enemy_words_button_system,
+                hit_points_button_system,
+                number_keys_button_system,
+                reduce_motion_button_system,
+                timer_format_button_system,
+            )
+                .run_if(in_state(TaipoState::MainMenu)),
+        );
+        // A key that starts rebinding must not also be captured as the new binding, so
+        // keys are captured before buttons are handled.
+        app.add_systems(
+            Update,
+            (
+                capture_binding,
+                controls_button_system,
+                binding_button_system,
+                reset_controls_button_system,
+                update_binding_labels,
+            )
+                .chain()
+                .run_if(in_state(TaipoState::MainMenu)),
         );
+        app.add_systems(OnExit(TaipoState::MainMenu), cancel_rebinding);
+    }
+}
+
+#[derive(Component)]
+struct EndlessButton;
+#[derive(Component)]
+struct EndlessButtonText;
+
+fn endless_label(endless: bool) -> &'static str {
+    if endless {
+        "Endless: On"
+    } else {
+        "Endless: Off"
+    }
+}
+
+/// A multiplier for the speed of everything driven by virtual time, for players who
+/// find the default pace overwhelming.
+#[derive(Resource)]
+pub struct GameSpeed(pub f32);
+impl Default for GameSpeed {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+const GAME_SPEEDS: [f32; 4] = [0.5, 1.0, 1.5, 2.0];
+
+#[derive(Component)]
+struct GameSpeedButton;
+#[derive(Component)]
+struct GameSpeedButtonText;
+
+fn game_speed_label(speed: f32) -> String {
+    format!("Speed: {}x", speed)
+}
+
+const VOLUMES: [f32; 5] = [0.0, 0.25, 0.5, 0.75, 1.0];
+
+#[derive(Component, Clone, Copy, PartialEq)]
+enum VolumeButton {
+    Music,
+    Sfx,
+}
+impl VolumeButton {
+    fn volume(self, audio_settings: &mut AudioSettings) -> &mut f32 {
+        match self {
+            Self::Music => &mut audio_settings.music,
+            Self::Sfx => &mut audio_settings.sfx,
+        }
+    }
+
+    fn label(self, volume: f32) -> String {
+        let name = match self {
+            Self::Music => "Music",
+            Self::Sfx => "Sound",
+        };
+
+        format!("{}: {:.0}%", name, volume * 100.0)
+    }
+}
+#[derive(Component)]
+struct VolumeButtonText(VolumeButton);
+
+#[derive(Component)]
+struct TicksButton;
+#[derive(Component)]
+struct TicksButtonText;
+
+fn ticks_label(ticks: bool) -> &'static str {
+    if ticks {
+        "Key Clicks: On"
+    } else {
+        "Key Clicks: Off"
+    }
+}
+
+#[derive(Component)]
+struct LongerWordsButton;
+#[derive(Component)]
+struct LongerWordsButtonText;
+
+fn longer_words_label(longer_words: bool) -> &'static str {
+    if longer_words {
+        "Longer Words: On"
+    } else {
+        "Longer Words: Off"
+    }
+}
+
+#[derive(Component)]
+struct WaitForStartButton;
+#[derive(Component)]
+struct WaitForStartButtonText;
+
+fn wait_for_start_label(wait_for_start: bool) -> &'static str {
+    if wait_for_start {
+        "Wait For Start: On"
+    } else {
+        "Wait For Start: Off"
+    }
+}
+
+#[derive(Component)]
+struct TonesButton;
+#[derive(Component)]
+struct TonesButtonText;
+
+fn tones_label(tones: bool) -> &'static str {
+    if tones {
+        "Typing Tones: On"
+    } else {
+        "Typing Tones: Off"
+    }
+}
+
+#[derive(Component)]
+struct ProgressButton;
+#[derive(Component)]
+struct ProgressButtonText;
+
+fn progress_label(progress: bool) -> &'static str {
+    if progress {
+        "Progress: On"
+    } else {
+        "Progress: Off"
+    }
+}
+
+#[derive(Component)]
+struct HintsButton;
+#[derive(Component)]
+struct HintsButtonText;
+
+fn hints_label(hints: bool) -> &'static str {
+    if hints {
+        "Hints: On"
+    } else {
+        "Hints: Off"
+    }
+}
+
+#[derive(Component)]
+struct EnemyWordsButton;
+#[derive(Component)]
+struct EnemyWordsButtonText;
+
+fn enemy_words_label(enemy_words: bool) -> &'static str {
+    if enemy_words {
+        "Enemy Words: On"
+    } else {
+        "Enemy Words: Off"
+    }
+}
+
+#[derive(Component)]
+struct HitPointsButton;
+#[derive(Component)]
+struct HitPointsButtonText;
+
+fn hit_points_label(hit_points: bool) -> &'static str {
+    if hit_points {
+        "Hit Points: On"
+    } else {
+        "Hit Points: Off"
+    }
+}
+
+#[derive(Component)]
+struct NumberKeysButton;
+#[derive(Component)]
+struct NumberKeysButtonText;
+
+fn number_keys_label(number_keys: bool) -> &'static str {
+    if number_keys {
+        "Number Keys: On"
+    } else {
+        "Number Keys: Off"
+    }
+}
+
+#[derive(Component)]
+struct ReduceMotionButton;
+#[derive(Component)]
+struct ReduceMotionButtonText;
+
+fn reduce_motion_label(reduce_motion: bool) -> &'static str {
+    if reduce_motion {
+        "Reduce Motion: On"
+    } else {
+        "Reduce Motion: Off"
     }
 }
 
+#[derive(Component)]
+struct TimerFormatButton;
+#[derive(Component)]
+struct TimerFormatButtonText;
+
+fn timer_format_label(format: &TimerFormat) -> String {
+    format!("Timer: {}", format.format(65.0))
+}
+
+/// The options for the game, which are swapped out for `ControlsColumn` when the
+/// player wants to change controls, or for `DisplayColumn` when the player wants to
+/// change what is shown during a game.
+#[derive(Component)]
+struct OptionsColumn;
+#[derive(Component)]
+struct ControlsColumn;
+#[derive(Component)]
+struct DisplayColumn;
+#[derive(Component)]
+struct DisplayButton;
+#[derive(Component)]
+struct DisplayBackButton;
+#[derive(Component)]
+struct ControlsButton;
+#[derive(Component)]
+struct ControlsBackButton;
+#[derive(Component)]
+struct ResetControlsButton;
+#[derive(Component)]
+struct BindingButton(MenuAction);
+#[derive(Component)]
+struct BindingButtonText(MenuAction);
+
+fn binding_label(action: MenuAction, key: KeyCode) -> String {
+    format!("{}: {}", action.label(), key_label(key))
+}
+
+/// Explains why a game couldn't be started.
+#[derive(Component)]
+struct StartErrorText;
+
+#[derive(Component)]
+struct LevelButton;
+#[derive(Component)]
+struct LevelButtonText;
+
+/// The map that the next game will be played on, chosen from `LevelHandles`.
+#[derive(Resource)]
+pub struct SelectedLevel(pub Handle<TiledMap>);
+
+fn level_label(level_handles: &LevelHandles, selected: &Handle<TiledMap>) -> String {
+    let index = level_handles
+        .levels
+        .iter()
+        .position(|level| level == selected)
+        .unwrap_or(0);
+
+    format!("Level: {}", index + 1)
+}
+
+/// The word lists chosen for the current game.
+#[derive(Resource, Default)]
+pub struct SelectedWordLists(pub Vec<String>);
+
 fn main_menu_startup(
     mut commands: Commands,
     font_handles: Res<FontHandles>,
     game_data_handles: Res<GameDataHandles>,
     game_data_assets: Res<Assets<GameData>>,
+    word_list_assets: Res<Assets<WordList>>,
     level_handles: Res<LevelHandles>,
+    asset_server: Res<AssetServer>,
+    best_stats: Res<BestStats>,
+    (
+        wave_settings,
+        game_speed,
+        audio_settings,
+        typing_settings,
+        action_panel_settings,
+        enemy_settings,
+        healthbar_settings,
+        timer_settings,
+    ): (
+        Res<WaveSettings>,
+        Res<GameSpeed>,
+        Res<AudioSettings>,
+        Res<TypingSettings>,
+        Res<ActionPanelSettings>,
+        Res<EnemySettings>,
+        Res<HealthBarSettings>,
+        Res<TimerDisplaySettings>,
+    ),
+    bindings: Res<MenuNavigationBindings>,
+    selected_level: Option<Res<SelectedLevel>>,
+    camera_query: Query<(), With<Camera2d>>,
+    map_query: Query<(), With<TiledMapHandle>>,
 ) {
     info!("main_menu_startup");
 
-    commands.spawn(Camera2d);
+    let selected_level = match selected_level {
+        Some(selected_level) => selected_level.0.clone(),
+        None => {
+            let first = level_handles.levels[0].clone();
+            commands.insert_resource(SelectedLevel(first.clone()));
+            first
+        }
+    };
+
+    // The camera and map outlive a game, so they are only spawned the first time
+    // that the menu is shown.
+    if camera_query.is_empty() {
+        commands.spawn(Camera2d);
+    }
 
-    commands.spawn(TiledMapBundle {
-        tiled_map: TiledMapHandle(level_handles.one.clone()),
-        ..default()
-    });
+    if map_query.is_empty() {
+        commands.spawn(TiledMapBundle {
+            tiled_map: TiledMapHandle(selected_level.clone()),
+            ..default()
+        });
+    }
 
     let game_data = game_data_assets.get(&game_data_handles.game).unwrap();
 
@@ -55,12 +404,27 @@ fn main_menu_startup(
             StateScoped(TaipoState::MainMenu),
         ))
         .with_children(|parent| {
+            parent.spawn((
+                Text::new(""),
+                TextFont {
+                    font: font_handles.jptext.clone(),
+                    font_size: FONT_SIZE_LABEL,
+                    ..default()
+                },
+                TextColor(ui_color::BAD_TEXT.into()),
+                Node {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(10.0),
+                    ..default()
+                },
+                StartErrorText,
+            ));
+
             parent
                 .spawn((
                     Node {
-                        flex_direction: FlexDirection::Column,
                         justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
+                        align_items: AlignItems::FlexStart,
                         align_self: AlignSelf::Center,
                         padding: UiRect::all(Val::Px(20.)),
                         ..default()
@@ -68,69 +432,864 @@ fn main_menu_startup(
                     BackgroundColor(ui_color::DIALOG_BACKGROUND.into()),
                 ))
                 .with_children(|parent| {
-                    for selection in game_data.word_list_menu.iter() {
-                        parent
-                            .spawn((
-                                Button,
-                                Node {
-                                    width: Val::Px(200.0),
-                                    height: Val::Px(48.0),
-                                    margin: UiRect::all(Val::Px(5.0)),
-                                    justify_content: JustifyContent::Center,
-                                    align_items: AlignItems::Center,
-                                    ..default()
-                                },
-                                BackgroundColor(ui_color::NORMAL_BUTTON.into()),
-                                selection.clone(),
-                            ))
-                            .with_children(|parent| {
-                                parent.spawn((
-                                    Text::new(&selection.label),
-                                    TextFont {
-                                        font: font_handles.jptext.clone(),
-                                        font_size: FONT_SIZE_LABEL,
-                                        ..default()
-                                    },
-                                    TextColor(ui_color::BUTTON_TEXT.into()),
+                    // Word lists on the left, and options for the game on the right
+                    parent
+                        .spawn(Node {
+                            flex_direction: FlexDirection::Column,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            for selection in game_data.word_list_menu.iter() {
+                                // Word lists that failed to load would otherwise only be noticed
+                                // when the player picks them.
+                                let missing =
+                                    missing_word_lists(selection, game_data, &word_list_assets);
+
+                                for path in missing.iter() {
+                                    let state = game_data
+                                        .word_lists
+                                        .get(*path)
+                                        .and_then(|handle| asset_server.get_load_state(handle));
+                                    error!("Word list \"{}\" failed to load: {:?}", path, state);
+                                }
+
+                                let mut button = parent.spawn((
+                                    Button,
+                                    Node {
+                                        flex_direction: FlexDirection::Column,
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(48.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
                                 ));
+
+                                // Without a `WordListMenuItem`, `button_system` ignores the button.
+                                if missing.is_empty() {
+                                    button.insert(selection.clone());
+                                }
+
+                                button.with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new(if missing.is_empty() {
+                                            selection.label.clone()
+                                        } else {
+                                            format!("{} (missing)", selection.label)
+                                        }),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(if missing.is_empty() {
+                                            ui_color::BUTTON_TEXT.into()
+                                        } else {
+                                            ui_color::BAD_TEXT.into()
+                                        }),
+                                    ));
+
+                                    if let Some(best) = best_stats.get(&selection.word_lists) {
+                                        parent.spawn((
+                                            Text::new(format!(
+                                                "Best: {}円 / {:.0} WPM",
+                                                best.total_earned, best.wpm
+                                            )),
+                                            TextFont {
+                                                font: font_handles.jptext.clone(),
+                                                font_size: FONT_SIZE_BEST,
+                                                ..default()
+                                            },
+                                            TextColor(ui_color::BUTTON_TEXT.into()),
+                                        ));
+                                    }
+                                });
+                            }
+                        });
+
+                    parent
+                        .spawn((
+                            Node {
+                                flex_direction: FlexDirection::Column,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            OptionsColumn,
+                        ))
+                        .with_children(|parent| {
+                            parent
+                                .spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(32.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                    EndlessButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new(endless_label(wave_settings.endless)),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(ui_color::BUTTON_TEXT.into()),
+                                        EndlessButtonText,
+                                    ));
+                                });
+
+                            parent
+                                .spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(32.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                    GameSpeedButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new(game_speed_label(game_speed.0)),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(ui_color::BUTTON_TEXT.into()),
+                                        GameSpeedButtonText,
+                                    ));
+                                });
+
+                            for (button, volume) in [
+                                (VolumeButton::Music, audio_settings.music),
+                                (VolumeButton::Sfx, audio_settings.sfx),
+                            ] {
+                                parent
+                                    .spawn((
+                                        Button,
+                                        Node {
+                                            width: Val::Px(200.0),
+                                            height: Val::Px(32.0),
+                                            margin: UiRect::all(Val::Px(5.0)),
+                                            justify_content: JustifyContent::Center,
+                                            align_items: AlignItems::Center,
+                                            ..default()
+                                        },
+                                        BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                        button,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn((
+                                            Text::new(button.label(volume)),
+                                            TextFont {
+                                                font: font_handles.jptext.clone(),
+                                                font_size: FONT_SIZE_LABEL,
+                                                ..default()
+                                            },
+                                            TextColor(ui_color::BUTTON_TEXT.into()),
+                                            VolumeButtonText(button),
+                                        ));
+                                    });
+                            }
+
+                            parent
+                                .spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(32.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                    TicksButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new(ticks_label(audio_settings.correct_ticks)),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(ui_color::BUTTON_TEXT.into()),
+                                        TicksButtonText,
+                                    ));
+                                });
+
+                            parent
+                                .spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(32.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                    LongerWordsButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new(longer_words_label(
+                                            typing_settings.longer_upgraded_targets,
+                                        )),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(ui_color::BUTTON_TEXT.into()),
+                                        LongerWordsButtonText,
+                                    ));
+                                });
+
+                            parent
+                                .spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(32.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                    WaitForStartButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new(wait_for_start_label(
+                                            wave_settings.wait_for_ready,
+                                        )),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(ui_color::BUTTON_TEXT.into()),
+                                        WaitForStartButtonText,
+                                    ));
+                                });
+
+                            parent
+                                .spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(32.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                    TonesButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new(tones_label(audio_settings.progress_tones)),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(ui_color::BUTTON_TEXT.into()),
+                                        TonesButtonText,
+                                    ));
+                                });
+
+                            // Only worth showing when there is actually a choice to make.
+                            if level_handles.levels.len() > 1 {
+                                parent
+                                    .spawn((
+                                        Button,
+                                        Node {
+                                            width: Val::Px(200.0),
+                                            height: Val::Px(32.0),
+                                            margin: UiRect::all(Val::Px(5.0)),
+                                            justify_content: JustifyContent::Center,
+                                            align_items: AlignItems::Center,
+                                            ..default()
+                                        },
+                                        BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                        LevelButton,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn((
+                                            Text::new(level_label(&level_handles, &selected_level)),
+                                            TextFont {
+                                                font: font_handles.jptext.clone(),
+                                                font_size: FONT_SIZE_LABEL,
+                                                ..default()
+                                            },
+                                            TextColor(ui_color::BUTTON_TEXT.into()),
+                                            LevelButtonText,
+                                        ));
+                                    });
+                            }
+
+                            // Side by side, as the column is already as tall as the window
+                            // allows.
+                            parent.spawn(Node::default()).with_children(|parent| {
+                                parent
+                                    .spawn((
+                                        Button,
+                                        Node {
+                                            width: Val::Px(95.0),
+                                            height: Val::Px(32.0),
+                                            margin: UiRect::all(Val::Px(5.0)),
+                                            justify_content: JustifyContent::Center,
+                                            align_items: AlignItems::Center,
+                                            ..default()
+                                        },
+                                        BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                        DisplayButton,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn((
+                                            Text::new("Display"),
+                                            TextFont {
+                                                font: font_handles.jptext.clone(),
+                                                font_size: FONT_SIZE_LABEL,
+                                                ..default()
+                                            },
+                                            TextColor(ui_color::BUTTON_TEXT.into()),
+                                        ));
+                                    });
+
+                                parent
+                                    .spawn((
+                                        Button,
+                                        Node {
+                                            width: Val::Px(95.0),
+                                            height: Val::Px(32.0),
+                                            margin: UiRect::all(Val::Px(5.0)),
+                                            justify_content: JustifyContent::Center,
+                                            align_items: AlignItems::Center,
+                                            ..default()
+                                        },
+                                        BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                        ControlsButton,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn((
+                                            Text::new("Controls"),
+                                            TextFont {
+                                                font: font_handles.jptext.clone(),
+                                                font_size: FONT_SIZE_LABEL,
+                                                ..default()
+                                            },
+                                            TextColor(ui_color::BUTTON_TEXT.into()),
+                                        ));
+                                    });
                             });
-                    }
+                        });
+
+                    parent
+                        .spawn((
+                            Node {
+                                flex_direction: FlexDirection::Column,
+                                align_items: AlignItems::Center,
+                                display: Display::None,
+                                ..default()
+                            },
+                            ControlsColumn,
+                        ))
+                        .with_children(|parent| {
+                            for action in MenuAction::ALL {
+                                parent
+                                    .spawn((
+                                        Button,
+                                        Node {
+                                            width: Val::Px(200.0),
+                                            height: Val::Px(32.0),
+                                            margin: UiRect::all(Val::Px(5.0)),
+                                            justify_content: JustifyContent::Center,
+                                            align_items: AlignItems::Center,
+                                            ..default()
+                                        },
+                                        BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                        BindingButton(action),
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn((
+                                            Text::new(binding_label(action, bindings.key(action))),
+                                            TextFont {
+                                                font: font_handles.jptext.clone(),
+                                                font_size: FONT_SIZE_LABEL,
+                                                ..default()
+                                            },
+                                            TextColor(ui_color::BUTTON_TEXT.into()),
+                                            BindingButtonText(action),
+                                        ));
+                                    });
+                            }
+
+                            parent
+                                .spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(32.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                    ResetControlsButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new("Reset to Defaults"),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(ui_color::BUTTON_TEXT.into()),
+                                    ));
+                                });
+
+                            parent
+                                .spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(32.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                    ControlsBackButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new("Back"),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(ui_color::BUTTON_TEXT.into()),
+                                    ));
+                                });
+                        });
+
+                    parent
+                        .spawn((
+                            Node {
+                                flex_direction: FlexDirection::Column,
+                                align_items: AlignItems::Center,
+                                display: Display::None,
+                                ..default()
+                            },
+                            DisplayColumn,
+                        ))
+                        .with_children(|parent| {
+                            parent
+                                .spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(32.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                    ProgressButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new(progress_label(
+                                            typing_settings.show_partial_chunk,
+                                        )),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(ui_color::BUTTON_TEXT.into()),
+                                        ProgressButtonText,
+                                    ));
+                                });
+
+                            parent
+                                .spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(32.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                    HintsButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new(hints_label(action_panel_settings.show_hints)),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(ui_color::BUTTON_TEXT.into()),
+                                        HintsButtonText,
+                                    ));
+                                });
+
+                            parent
+                                .spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(32.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                    EnemyWordsButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new(enemy_words_label(enemy_settings.typing_targets)),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(ui_color::BUTTON_TEXT.into()),
+                                        EnemyWordsButtonText,
+                                    ));
+                                });
+
+                            parent
+                                .spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(32.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                    HitPointsButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new(hit_points_label(healthbar_settings.show_text)),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(ui_color::BUTTON_TEXT.into()),
+                                        HitPointsButtonText,
+                                    ));
+                                });
+
+                            parent
+                                .spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(32.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                    NumberKeysButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new(number_keys_label(typing_settings.number_keys)),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(ui_color::BUTTON_TEXT.into()),
+                                        NumberKeysButtonText,
+                                    ));
+                                });
+
+                            parent
+                                .spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(32.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                    ReduceMotionButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new(reduce_motion_label(
+                                            action_panel_settings.reduce_motion,
+                                        )),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(ui_color::BUTTON_TEXT.into()),
+                                        ReduceMotionButtonText,
+                                    ));
+                                });
+
+                            parent
+                                .spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(32.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                    TimerFormatButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new(timer_format_label(&timer_settings.format)),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(ui_color::BUTTON_TEXT.into()),
+                                        TimerFormatButtonText,
+                                    ));
+                                });
+
+                            parent
+                                .spawn((
+                                    Button,
+                                    Node {
+                                        width: Val::Px(200.0),
+                                        height: Val::Px(32.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                    DisplayBackButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        Text::new("Back"),
+                                        TextFont {
+                                            font: font_handles.jptext.clone(),
+                                            font_size: FONT_SIZE_LABEL,
+                                            ..default()
+                                        },
+                                        TextColor(ui_color::BUTTON_TEXT.into()),
+                                    ));
+                                });
+                        });
                 });
         });
 }
 
 fn main_menu() {}
 
-fn button_system(
+fn level_button_system(
     mut interaction_query: Query<
-        (&Interaction, &mut BackgroundColor, &WordListMenuItem),
-        (Changed<Interaction>, With<Button>),
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<LevelButton>),
     >,
-    mut next_state: ResMut<NextState<TaipoState>>,
-    game_data_handles: Res<GameDataHandles>,
-    game_data_assets: Res<Assets<GameData>>,
-    word_list_assets: Res<Assets<WordList>>,
-    mut typing_targets: ResMut<TypingTargets>,
+    mut text_query: Query<&mut Text, With<LevelButtonText>>,
+    mut map_query: Query<&mut TiledMapHandle>,
+    level_handles: Res<LevelHandles>,
+    mut selected_level: ResMut<SelectedLevel>,
 ) {
-    for (interaction, mut background_color, menu_item) in interaction_query.iter_mut() {
+    for (interaction, mut background_color) in interaction_query.iter_mut() {
         match *interaction {
             Interaction::Pressed => {
                 *background_color = ui_color::PRESSED_BUTTON.into();
 
-                let game_data = game_data_assets.get(&game_data_handles.game).unwrap();
+                let next = level_handles
+                    .levels
+                    .iter()
+                    .position(|level| *level == selected_level.0)
+                    .map_or(0, |index| (index + 1) % level_handles.levels.len());
 
-                let mut rng = thread_rng();
+                selected_level.0 = level_handles.levels[next].clone();
+
+                // Show the chosen level behind the menu.
+                for mut map_handle in map_query.iter_mut() {
+                    map_handle.0 = selected_level.0.clone();
+                }
 
-                let mut possible_typing_targets: Vec<TypingTarget> = vec![];
-                for list in &menu_item.word_lists {
-                    let word_list = word_list_assets.get(&game_data.word_lists[list]).unwrap();
-                    possible_typing_targets.extend(word_list.words.clone());
+                for mut text in text_query.iter_mut() {
+                    text.0 = level_label(&level_handles, &selected_level.0);
                 }
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn endless_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<EndlessButton>),
+    >,
+    mut text_query: Query<&mut Text, With<EndlessButtonText>>,
+    mut wave_settings: ResMut<WaveSettings>,
+) {
+    for (interaction, mut background_color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
 
-                possible_typing_targets.shuffle(&mut rng);
-                typing_targets.possible = possible_typing_targets.into();
+                wave_settings.endless = !wave_settings.endless;
 
-                next_state.set(TaipoState::Spawn);
+                for mut text in text_query.iter_mut() {
+                    text.0 = endless_label(wave_settings.endless).to_string();
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+// The speed is applied to virtual time by `slow_motion::update_time_scale`. Only
+// virtual time is scaled, so the typing cursor and menu animations, which use real
+// time, and audio playback are unaffected.
+fn game_speed_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<GameSpeedButton>),
+    >,
+    mut text_query: Query<&mut Text, With<GameSpeedButtonText>>,
+    mut game_speed: ResMut<GameSpeed>,
+) {
+    for (interaction, mut background_color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                let next = GAME_SPEEDS
+                    .iter()
+                    .position(|speed| *speed == game_speed.0)
+                    .map_or(1, |index| (index + 1) % GAME_SPEEDS.len());
+
+                game_speed.0 = GAME_SPEEDS[next];
+
+                for mut text in text_query.iter_mut() {
+                    text.0 = game_speed_label(game_speed.0);
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn ticks_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<TicksButton>),
+    >,
+    mut text_query: Query<&mut Text, With<TicksButtonText>>,
+    mut audio_settings: ResMut<AudioSettings>,
+) {
+    for (interaction, mut background_color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                audio_settings.correct_ticks = !audio_settings.correct_ticks;
+
+                for mut text in text_query.iter_mut() {
+                    text.0 = ticks_label(audio_settings.correct_ticks).to_string();
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn longer_words_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<LongerWordsButton>),
+    >,
+    mut text_query: Query<&mut Text, With<LongerWordsButtonText>>,
+    mut typing_settings: ResMut<TypingSettings>,
+) {
+    for (interaction, mut background_color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                typing_settings.longer_upgraded_targets = !typing_settings.longer_upgraded_targets;
+
+                for mut text in text_query.iter_mut() {
+                    text.0 =
+                        longer_words_label(typing_settings.longer_upgraded_targets).to_string();
+                }
             }
             Interaction::Hovered => {
                 *background_color = ui_color::HOVERED_BUTTON.into();
@@ -141,3 +1300,729 @@ fn button_system(
         }
     }
 }
+
+fn wait_for_start_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<WaitForStartButton>),
+    >,
+    mut text_query: Query<&mut Text, With<WaitForStartButtonText>>,
+    mut wave_settings: ResMut<WaveSettings>,
+) {
+    for (interaction, mut background_color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                wave_settings.wait_for_ready = !wave_settings.wait_for_ready;
+
+                for mut text in text_query.iter_mut() {
+                    text.0 = wait_for_start_label(wave_settings.wait_for_ready).to_string();
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn tones_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<TonesButton>),
+    >,
+    mut text_query: Query<&mut Text, With<TonesButtonText>>,
+    mut audio_settings: ResMut<AudioSettings>,
+) {
+    for (interaction, mut background_color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                audio_settings.progress_tones = !audio_settings.progress_tones;
+
+                for mut text in text_query.iter_mut() {
+                    text.0 = tones_label(audio_settings.progress_tones).to_string();
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+/// Swaps between the game options and the display options.
+fn display_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, Has<DisplayBackButton>),
+        (
+            Changed<Interaction>,
+            Or<(With<DisplayButton>, With<DisplayBackButton>)>,
+        ),
+    >,
+    mut options_query: Query<&mut Node, (With<OptionsColumn>, Without<DisplayColumn>)>,
+    mut display_query: Query<&mut Node, (With<DisplayColumn>, Without<OptionsColumn>)>,
+) {
+    for (interaction, mut background_color, back) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                let (options, display) = if back {
+                    (Display::Flex, Display::None)
+                } else {
+                    (Display::None, Display::Flex)
+                };
+
+                for mut node in options_query.iter_mut() {
+                    node.display = options;
+                }
+                for mut node in display_query.iter_mut() {
+                    node.display = display;
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn progress_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<ProgressButton>),
+    >,
+    mut text_query: Query<&mut Text, With<ProgressButtonText>>,
+    mut typing_settings: ResMut<TypingSettings>,
+) {
+    for (interaction, mut background_color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                typing_settings.show_partial_chunk = !typing_settings.show_partial_chunk;
+
+                for mut text in text_query.iter_mut() {
+                    text.0 = progress_label(typing_settings.show_partial_chunk).to_string();
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn hints_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<HintsButton>),
+    >,
+    mut text_query: Query<&mut Text, With<HintsButtonText>>,
+    mut action_panel_settings: ResMut<ActionPanelSettings>,
+) {
+    for (interaction, mut background_color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                action_panel_settings.show_hints = !action_panel_settings.show_hints;
+
+                for mut text in text_query.iter_mut() {
+                    text.0 = hints_label(action_panel_settings.show_hints).to_string();
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn enemy_words_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<EnemyWordsButton>),
+    >,
+    mut text_query: Query<&mut Text, With<EnemyWordsButtonText>>,
+    mut enemy_settings: ResMut<EnemySettings>,
+) {
+    for (interaction, mut background_color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                enemy_settings.typing_targets = !enemy_settings.typing_targets;
+
+                for mut text in text_query.iter_mut() {
+                    text.0 = enemy_words_label(enemy_settings.typing_targets).to_string();
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn hit_points_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<HitPointsButton>),
+    >,
+    mut text_query: Query<&mut Text, With<HitPointsButtonText>>,
+    mut healthbar_settings: ResMut<HealthBarSettings>,
+) {
+    for (interaction, mut background_color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                healthbar_settings.show_text = !healthbar_settings.show_text;
+
+                for mut text in text_query.iter_mut() {
+                    text.0 = hit_points_label(healthbar_settings.show_text).to_string();
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn number_keys_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<NumberKeysButton>),
+    >,
+    mut text_query: Query<&mut Text, With<NumberKeysButtonText>>,
+    mut typing_settings: ResMut<TypingSettings>,
+) {
+    for (interaction, mut background_color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                typing_settings.number_keys = !typing_settings.number_keys;
+
+                for mut text in text_query.iter_mut() {
+                    text.0 = number_keys_label(typing_settings.number_keys).to_string();
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn reduce_motion_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<ReduceMotionButton>),
+    >,
+    mut text_query: Query<&mut Text, With<ReduceMotionButtonText>>,
+    mut action_panel_settings: ResMut<ActionPanelSettings>,
+) {
+    for (interaction, mut background_color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                action_panel_settings.reduce_motion = !action_panel_settings.reduce_motion;
+
+                for mut text in text_query.iter_mut() {
+                    text.0 = reduce_motion_label(action_panel_settings.reduce_motion).to_string();
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn timer_format_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<TimerFormatButton>),
+    >,
+    mut text_query: Query<&mut Text, With<TimerFormatButtonText>>,
+    mut timer_settings: ResMut<TimerDisplaySettings>,
+) {
+    for (interaction, mut background_color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                timer_settings.format = timer_settings.format.next();
+
+                for mut text in text_query.iter_mut() {
+                    text.0 = timer_format_label(&timer_settings.format);
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+/// Swaps between the game options and the controls.
+fn controls_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, Has<ControlsBackButton>),
+        (
+            Changed<Interaction>,
+            Or<(With<ControlsButton>, With<ControlsBackButton>)>,
+        ),
+    >,
+    mut options_query: Query<&mut Node, (With<OptionsColumn>, Without<ControlsColumn>)>,
+    mut controls_query: Query<&mut Node, (With<ControlsColumn>, Without<OptionsColumn>)>,
+    mut rebinding: ResMut<RebindingAction>,
+) {
+    for (interaction, mut background_color, back) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                let (options, controls) = if back {
+                    (Display::Flex, Display::None)
+                } else {
+                    (Display::None, Display::Flex)
+                };
+
+                for mut node in options_query.iter_mut() {
+                    node.display = options;
+                }
+                for mut node in controls_query.iter_mut() {
+                    node.display = controls;
+                }
+
+                rebinding.0 = None;
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn binding_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &BindingButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut rebinding: ResMut<RebindingAction>,
+) {
+    for (interaction, mut background_color, button) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                rebinding.0 = Some(button.0);
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn reset_controls_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<ResetControlsButton>),
+    >,
+    mut bindings: ResMut<MenuNavigationBindings>,
+    mut rebinding: ResMut<RebindingAction>,
+) {
+    for (interaction, mut background_color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                *bindings = MenuNavigationBindings::default();
+                rebinding.0 = None;
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+/// Binds the next key pressed to the action being rebound. Escape cancels rebinding,
+/// and keys that are already used by another action are refused.
+fn capture_binding(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut bindings: ResMut<MenuNavigationBindings>,
+    mut rebinding: ResMut<RebindingAction>,
+    mut text_query: Query<(&mut Text, &BindingButtonText)>,
+) {
+    let Some(action) = rebinding.0 else {
+        return;
+    };
+
+    let Some(key) = keyboard.get_just_pressed().next().copied() else {
+        return;
+    };
+
+    if key == KeyCode::Escape {
+        rebinding.0 = None;
+        return;
+    }
+
+    // Only trigger change detection when the binding actually changes, so that the
+    // message below isn't immediately replaced.
+    match bindings.bypass_change_detection().bind(action, key) {
+        Ok(()) => {
+            bindings.set_changed();
+            rebinding.0 = None;
+        }
+        Err(other) => {
+            for (mut text, text_action) in text_query.iter_mut() {
+                if text_action.0 == action {
+                    text.0 = format!("{} is used by {}", key_label(key), other.label());
+                }
+            }
+        }
+    }
+}
+
+fn update_binding_labels(
+    bindings: Res<MenuNavigationBindings>,
+    rebinding: Res<RebindingAction>,
+    mut text_query: Query<(&mut Text, &BindingButtonText)>,
+) {
+    if !bindings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+
+    for (mut text, text_action) in text_query.iter_mut() {
+        text.0 = if rebinding.0 == Some(text_action.0) {
+            format!("{}: Press a key", text_action.0.label())
+        } else {
+            binding_label(text_action.0, bindings.key(text_action.0))
+        };
+    }
+}
+
+fn cancel_rebinding(mut rebinding: ResMut<RebindingAction>) {
+    rebinding.0 = None;
+}
+
+fn volume_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &VolumeButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut text_query: Query<(&mut Text, &VolumeButtonText)>,
+    mut audio_settings: ResMut<AudioSettings>,
+) {
+    for (interaction, mut background_color, button) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                let volume = button.volume(&mut audio_settings);
+                let next = VOLUMES
+                    .iter()
+                    .position(|v| *v == *volume)
+                    .map_or(VOLUMES.len() - 1, |index| (index + 1) % VOLUMES.len());
+                *volume = VOLUMES[next];
+                let volume = *volume;
+
+                for (mut text, text_button) in text_query.iter_mut() {
+                    if text_button.0 == *button {
+                        text.0 = button.label(volume);
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+/// There aren't enough words for every tower slot and action panel item to have
+/// its own.
+pub struct NotEnoughWords {
+    pub available: usize,
+    pub required: usize,
+}
+
+/// Returns the paths in `selection` whose word lists failed to load.
+fn missing_word_lists<'a>(
+    selection: &'a WordListMenuItem,
+    game_data: &GameData,
+    word_list_assets: &Assets<WordList>,
+) -> Vec<&'a String> {
+    selection
+        .word_lists
+        .iter()
+        .filter(|path| {
+            game_data
+                .word_lists
+                .get(*path)
+                .is_none_or(|handle| word_list_assets.get(handle).is_none())
+        })
+        .collect()
+}
+
+/// Fills `typing_targets` with the words from `word_lists`, in the order that they
+/// should come up, for a game on `tiled_map`.
+///
+/// If there aren't enough words, `typing_targets` is left empty.
+pub fn prepare_typing_targets(
+    typing_targets: &mut TypingTargets,
+    word_lists: &[String],
+    tiled_map: &TiledMap,
+    game_data: &GameData,
+    word_list_assets: &Assets<WordList>,
+    rng: &mut GameRng,
+) -> Result<(), NotEnoughWords> {
+    *typing_targets = TypingTargets::default();
+
+    let start = Instant::now();
+
+    // Lists may share words, e.g. kana that also appear in a vocabulary list.
+    // Skipping duplicates up front also keeps the weighted shuffle below small.
+    let mut seen: HashSet<String> = HashSet::default();
+
+    let mut possible_typing_targets: Vec<TypingTarget> = vec![];
+    for list in word_lists {
+        let word_list = word_list_assets.get(&game_data.word_lists[list]).unwrap();
+        possible_typing_targets.extend(
+            word_list
+                .words
+                .iter()
+                .filter(|word| seen.insert(word.typed_chunks.concat()))
+                .cloned(),
+        );
+    }
+
+    // Higher weight words tend to come first. Weights are always at least 1, so this
+    // can't fail.
+    let len = possible_typing_targets.len();
+    possible_typing_targets = possible_typing_targets
+        .choose_multiple_weighted(&mut rng.0, len, |target| target.weight as f64)
+        .unwrap()
+        .cloned()
+        .collect();
+    // Easier words, with a lower difficulty, first, so that the difficulty ramps up
+    // as the game goes on.
+    // This is a stable sort, so words with the same difficulty stay shuffled.
+    // Most lists don't use difficulty tags, so avoid sorting when it wouldn't
+    // change anything.
+    let first_difficulty = possible_typing_targets.first().map(|t| t.difficulty);
+    if possible_typing_targets
+        .iter()
+        .any(|target| Some(target.difficulty) != first_difficulty)
+    {
+        possible_typing_targets.sort_by_key(|target| target.difficulty);
+    }
+    typing_targets.possible = possible_typing_targets.into();
+
+    info!(
+        "Prepared {} words in {:?}",
+        typing_targets.possible.len(),
+        start.elapsed()
+    );
+
+    for target in game_data.fixed_targets.iter() {
+        typing_targets.reserve(target);
+    }
+
+    // Every tower slot and action panel item needs its own word.
+    let required = find_objects(tiled_map, "tower_slot").count() + NUM_ACTION_PANEL_ITEMS;
+    let available = typing_targets.available(required);
+    if available < required {
+        *typing_targets = TypingTargets::default();
+
+        return Err(NotEnoughWords {
+            available,
+            required,
+        });
+    }
+
+    Ok(())
+}
+
+fn button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &WordListMenuItem),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut next_state: ResMut<NextState<TaipoState>>,
+    game_data_handles: Res<GameDataHandles>,
+    game_data_assets: Res<Assets<GameData>>,
+    word_list_assets: Res<Assets<WordList>>,
+    mut typing_targets: ResMut<TypingTargets>,
+    mut selected_word_lists: ResMut<SelectedWordLists>,
+    mut rng: ResMut<GameRng>,
+    (selected_level, maps, enemy_atlas_handles, mut map_errors): (
+        Res<SelectedLevel>,
+        Res<Assets<TiledMap>>,
+        Res<EnemyAtlasHandles>,
+        ResMut<MapErrors>,
+    ),
+    (mut commands, mut error_text_query, audio_handles, audio_settings): (
+        Commands,
+        Query<&mut Text, With<StartErrorText>>,
+        Res<AudioHandles>,
+        Res<AudioSettings>,
+    ),
+) {
+    for (interaction, mut background_color, menu_item) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                // Problems with the map are reported all at once, rather than causing
+                // a panic or a broken game partway through spawning it.
+                map_errors.0 = match maps.get(&selected_level.0) {
+                    Some(tiled_map) => validate_map(tiled_map, &enemy_atlas_handles),
+                    None => vec!["map: failed to load.".to_string()],
+                };
+                if !map_errors.0.is_empty() {
+                    for error in map_errors.0.iter() {
+                        error!("{}", error);
+                    }
+
+                    next_state.set(TaipoState::MapError);
+                    continue;
+                }
+
+                let game_data = game_data_assets.get(&game_data_handles.game).unwrap();
+                let tiled_map = maps.get(&selected_level.0).unwrap();
+
+                if let Err(err) = prepare_typing_targets(
+                    &mut typing_targets,
+                    &menu_item.word_lists,
+                    tiled_map,
+                    game_data,
+                    &word_list_assets,
+                    &mut rng,
+                ) {
+                    for mut text in error_text_query.iter_mut() {
+                        text.0 = format!(
+                            "{} has {} usable words, but this map needs {}.",
+                            menu_item.label, err.available, err.required
+                        );
+                    }
+
+                    if !audio_settings.mute {
+                        commands.spawn((
+                            AudioPlayer(audio_handles.wrong_character.clone()),
+                            PlaybackSettings::DESPAWN
+                                .with_volume(Volume::new(audio_settings.sfx_volume())),
+                        ));
+                    }
+
+                    continue;
+                }
+
+                selected_word_lists.0.clone_from(&menu_item.word_lists);
+
+                next_state.set(TaipoState::Spawn);
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nonexistent_word_list_is_reported_missing() {
+        let mut word_list_assets = Assets::<WordList>::default();
+        let loaded = word_list_assets.add(WordList { words: vec![] });
+
+        // `GameDataLoader` creates a handle for every referenced path, even when the
+        // file doesn't exist. That handle just never gets an asset.
+        let game_data = GameData {
+            word_lists: [
+                ("data/word_list/kana.txt".to_string(), loaded),
+                (
+                    "data/word_list/nonexistent.txt".to_string(),
+                    Handle::weak_from_u128(990),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            ..default()
+        };
+
+        let selection = WordListMenuItem {
+            label: "Kana".to_string(),
+            word_lists: vec![
+                "data/word_list/kana.txt".to_string(),
+                "data/word_list/nonexistent.txt".to_string(),
+                "data/word_list/unreferenced.txt".to_string(),
+            ],
+        };
+
+        assert_eq!(
+            missing_word_lists(&selection, &game_data, &word_list_assets),
+            vec![
+                "data/word_list/nonexistent.txt",
+                "data/word_list/unreferenced.txt"
+            ]
+        );
+    }
+}
@@ -1,14 +1,22 @@
 use bevy::{
-    asset::{io::Reader, AssetLoader},
+    asset::{io::Reader, AssetLoader, LoadContext},
     prelude::*,
     reflect::TypePath,
+    sprite::Anchor,
 };
 
 use anyhow::anyhow;
 use bevy_ecs_tilemap::prelude::*;
 use tiled::{Object, PropertyValue};
 
-use std::{collections::HashMap, io::Cursor, path::Path, sync::Arc};
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{layer, AfterUpdate};
 
 #[derive(Default)]
 pub struct TiledMapPlugin;
@@ -20,7 +28,9 @@ impl Plugin for TiledMapPlugin {
         app.init_asset::<TiledMap>()
             .add_event::<TiledMapLoadedEvent>()
             .register_asset_loader(TiledLoader)
-            .add_systems(Update, process_loaded_maps);
+            .init_resource::<MapSettings>()
+            .add_systems(Update, (process_loaded_maps, update_parallax))
+            .add_systems(AfterUpdate, y_sort);
     }
 }
 
@@ -28,12 +38,56 @@ impl Plugin for TiledMapPlugin {
 pub struct TiledMap {
     pub map: tiled::Map,
     pub tilemap_textures: HashMap<usize, TilemapTexture>,
+    /// Images for each tile of each image collection tileset, keyed by tileset index
+    /// and then tile id.
+    pub collection_textures: HashMap<usize, HashMap<tiled::TileId, Handle<Image>>>,
+    /// Images for each image layer, keyed by layer index.
+    pub image_layer_textures: HashMap<usize, Handle<Image>>,
+}
+
+#[derive(Resource, Default)]
+pub struct MapSettings {
+    /// If true, towers, enemies, and corpses are drawn in order of their y position.
+    /// Enabled by the map's `y_sort` property.
+    pub y_sort: bool,
+}
+impl MapSettings {
+    pub fn from_map(map: &TiledMap) -> Self {
+        Self {
+            y_sort: matches!(
+                map.map.properties.get("y_sort"),
+                Some(PropertyValue::BoolValue(true))
+            ),
+        }
+    }
+}
+
+/// Keeps an entity's z within a layer, ordered by its y position. The value is the
+/// z that the entity would have at `y = 0`.
+#[derive(Component)]
+pub struct YSort(pub f32);
+
+/// A background image spawned from a Tiled image layer.
+#[derive(Component)]
+pub struct TiledImageLayer;
+
+/// Offsets an entity from its origin by a fraction of the camera's position, so
+/// that it appears to scroll at a different rate than the map.
+///
+/// A factor of `1.0` scrolls with the map, and `0.0` stays fixed on the screen.
+#[derive(Component)]
+pub struct Parallax {
+    pub factor: Vec2,
+    pub origin: Vec3,
 }
 
 // Stores a list of tiled layers.
 #[derive(Component, Default)]
 pub struct TiledLayersStorage {
     pub storage: HashMap<u32, Entity>,
+    /// Tiles from image collection tilesets, which are drawn as sprites, with one
+    /// entity per tileset and layer.
+    pub collection_layers: Vec<Entity>,
 }
 
 #[derive(Component, Default)]
@@ -47,24 +101,43 @@ pub struct TiledMapBundle {
     pub global_transform: GlobalTransform,
 }
 
-struct BytesResourceReader {
+struct BytesResourceReader<'a> {
     bytes: Arc<[u8]>,
+    tilesets: &'a HashMap<PathBuf, Arc<[u8]>>,
+    /// External tilesets that the map needed, but that haven't been read yet.
+    missing: Vec<PathBuf>,
 }
 
-impl BytesResourceReader {
-    fn new(bytes: &[u8]) -> Self {
+impl<'a> BytesResourceReader<'a> {
+    fn new(bytes: &[u8], tilesets: &'a HashMap<PathBuf, Arc<[u8]>>) -> Self {
         Self {
             bytes: Arc::from(bytes),
+            tilesets,
+            missing: vec![],
         }
     }
 }
 
-impl tiled::ResourceReader for BytesResourceReader {
+impl tiled::ResourceReader for BytesResourceReader<'_> {
     type Resource = Cursor<Arc<[u8]>>;
     type Error = std::io::Error;
 
-    fn read_from(&mut self, _path: &Path) -> std::result::Result<Self::Resource, Self::Error> {
-        // In this case, the path is ignored because the byte data is already provided.
+    fn read_from(&mut self, path: &Path) -> std::result::Result<Self::Resource, Self::Error> {
+        // External tilesets can only be read asynchronously, so they are read ahead of
+        // time by `TiledLoader`, which tries again with any that were missing.
+        if path.extension().is_some_and(|ext| ext == "tsx") {
+            let Some(bytes) = self.tilesets.get(path) else {
+                self.missing.push(path.to_path_buf());
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "tileset hasn't been read yet",
+                ));
+            };
+
+            return Ok(Cursor::new(bytes.clone()));
+        }
+
+        // Otherwise, this is the map itself, and the byte data is already provided.
         Ok(Cursor::new(self.bytes.clone()))
     }
 }
@@ -80,23 +153,55 @@ impl AssetLoader for TiledLoader {
         &self,
         reader: &mut dyn Reader,
         _settings: &Self::Settings,
-        load_context: &mut bevy::asset::LoadContext<'_>,
+        load_context: &mut LoadContext<'_>,
     ) -> Result<Self::Asset, Self::Error> {
         let mut bytes = Vec::new();
         reader.read_to_end(&mut bytes).await?;
 
-        let mut loader = tiled::Loader::with_cache_and_reader(
-            tiled::DefaultResourceCache::new(),
-            BytesResourceReader::new(&bytes),
-        );
-        let map = loader.load_tmx_map(load_context.path())?;
+        let path = load_context.path().to_path_buf();
+
+        // External tilesets are read through the asset server, which also lets Bevy
+        // know that the map depends on them.
+        let mut tilesets = HashMap::default();
+        let map = loop {
+            let (result, missing) = {
+                let mut loader = tiled::Loader::with_cache_and_reader(
+                    tiled::DefaultResourceCache::new(),
+                    BytesResourceReader::new(&bytes, &tilesets),
+                );
+                let result = loader.load_tmx_map(&path);
+                (result, loader.reader().missing.clone())
+            };
+
+            match result {
+                Ok(map) => break map,
+                Err(err) if missing.is_empty() => return Err(err.into()),
+                Err(_) => {}
+            }
+
+            for tileset_path in missing {
+                let tileset_bytes = load_context.read_asset_bytes(tileset_path.clone()).await?;
+                tilesets.insert(tileset_path, Arc::from(tileset_bytes));
+            }
+        };
 
         let mut tilemap_textures = HashMap::default();
+        let mut collection_textures = HashMap::default();
 
         for (tileset_index, tileset) in map.tilesets().iter().enumerate() {
             let tilemap_texture = match &tileset.image {
+                // Each tile has its own image, and is drawn by `spawn_collection_layers`.
                 None => {
-                    info!("Skipping image collection tileset '{}' which is incompatible with atlas feature", tileset.name);
+                    let images = tileset
+                        .tiles()
+                        .filter_map(|(id, tile)| {
+                            let image: Handle<Image> =
+                                load_context.load(tile.image.as_ref()?.source.clone());
+                            Some((id, image))
+                        })
+                        .collect::<HashMap<_, _>>();
+
+                    collection_textures.insert(tileset_index, images);
                     continue;
                 }
                 Some(img) => {
@@ -109,9 +214,27 @@ impl AssetLoader for TiledLoader {
             tilemap_textures.insert(tileset_index, tilemap_texture);
         }
 
+        let mut image_layer_textures = HashMap::default();
+
+        for (layer_index, layer) in map.layers().enumerate() {
+            let tiled::LayerType::Image(image_layer) = layer.layer_type() else {
+                continue;
+            };
+
+            let Some(img) = &image_layer.image else {
+                continue;
+            };
+
+            let texture: Handle<Image> = load_context.load(img.source.clone());
+
+            image_layer_textures.insert(layer_index, texture);
+        }
+
         let asset_map = TiledMap {
             map,
             tilemap_textures,
+            collection_textures,
+            image_layer_textures,
         };
 
         info!("Loaded map: {}", load_context.path().display());
@@ -129,7 +252,8 @@ fn process_loaded_maps(
     maps: Res<Assets<TiledMap>>,
     tile_storage_query: Query<(Entity, &TileStorage)>,
     mut map_query: Query<(&TiledMapHandle, &mut TiledLayersStorage)>,
-    new_maps: Query<&TiledMapHandle, Added<TiledMapHandle>>,
+    // Also includes maps whose handle was swapped, e.g. by choosing another level.
+    new_maps: Query<&TiledMapHandle, Changed<TiledMapHandle>>,
 ) {
     let mut changed_maps = Vec::<AssetId<TiledMap>>::default();
     for event in map_events.read() {
@@ -168,14 +292,64 @@ fn process_loaded_maps(
                 continue;
             };
 
-            // TODO: Create a RemoveMap component..
-            for layer_entity in layer_storage.storage.values() {
-                if let Ok((_, layer_tile_storage)) = tile_storage_query.get(*layer_entity) {
+            // Tiles aren't children of their tilemap, so they are despawned separately.
+            for (_, layer_entity) in layer_storage.storage.drain() {
+                if let Ok((_, layer_tile_storage)) = tile_storage_query.get(layer_entity) {
                     for tile in layer_tile_storage.iter().flatten() {
                         commands.entity(*tile).despawn_recursive();
                     }
                 }
-                // commands.entity(*layer_entity).despawn_recursive();
+                commands.entity(layer_entity).despawn_recursive();
+            }
+            for layer_entity in layer_storage.collection_layers.drain(..) {
+                commands.entity(layer_entity).despawn_recursive();
+            }
+
+            let map_bounds = map_world_bounds(tiled_map);
+
+            // Image layers are drawn as sprites behind all of the tile layers, preserving
+            // their order relative to each other.
+            for (layer_index, layer) in tiled_map.map.layers().enumerate() {
+                let tiled::LayerType::Image(image_layer) = layer.layer_type() else {
+                    continue;
+                };
+
+                let (Some(img), Some(texture)) = (
+                    &image_layer.image,
+                    tiled_map.image_layer_textures.get(&layer_index),
+                ) else {
+                    continue;
+                };
+
+                let size = Vec2::new(img.width as f32, img.height as f32);
+
+                // Y axis in bevy/tiled are reversed, and the layer offset is the position
+                // of the top-left corner of the image.
+                let origin = Vec3::new(
+                    map_bounds.min.x + layer.offset_x + size.x / 2.0,
+                    map_bounds.max.y - layer.offset_y - size.y / 2.0,
+                    layer::BACKGROUND + layer_index as f32 * 0.01,
+                );
+
+                let layer_entity = commands
+                    .spawn((
+                        Sprite {
+                            image: texture.clone(),
+                            color: Color::WHITE.with_alpha(layer.opacity),
+                            ..default()
+                        },
+                        Transform::from_translation(origin),
+                        Parallax {
+                            factor: Vec2::new(layer.parallax_x, layer.parallax_y),
+                            origin,
+                        },
+                        TiledImageLayer,
+                    ))
+                    .id();
+
+                layer_storage
+                    .storage
+                    .insert(layer_index as u32, layer_entity);
             }
 
             // The TilemapBundle requires that all tile images come exclusively from a single
@@ -184,6 +358,17 @@ fn process_loaded_maps(
             // tilesets on each layer and allows differently-sized tile images in each tileset,
             // this means we need to load each combination of tileset and layer separately.
             for (tileset_index, tileset) in tiled_map.map.tilesets().iter().enumerate() {
+                if let Some(images) = tiled_map.collection_textures.get(&tileset_index) {
+                    spawn_collection_layers(
+                        &mut commands,
+                        tiled_map,
+                        tileset_index,
+                        images,
+                        &mut layer_storage,
+                    );
+                    continue;
+                }
+
                 let Some(tilemap_texture) = tiled_map.tilemap_textures.get(&tileset_index) else {
                     warn!("Skipped creating layer with missing tilemap textures.");
                     continue;
@@ -199,16 +384,33 @@ fn process_loaded_maps(
                     y: tileset.spacing as f32,
                 };
 
+                let animations = tileset
+                    .tiles()
+                    .filter_map(|(id, tile)| {
+                        let frames = tile.animation.as_ref()?;
+                        let animated = animated_tile(frames);
+                        if animated.is_none() {
+                            warn!(
+                                "Tile {} in tileset {} is not animated, because only animations of consecutive tiles are supported.",
+                                id, tileset.name
+                            );
+                        }
+                        Some((id, animated?))
+                    })
+                    .collect::<HashMap<_, _>>();
+
                 // Once materials have been created/added we need to then create the layers.
                 for (layer_index, layer) in tiled_map.map.layers().enumerate() {
                     let offset_x = layer.offset_x;
                     let offset_y = layer.offset_y;
 
                     let tiled::LayerType::Tiles(tile_layer) = layer.layer_type() else {
-                        warn!(
-                            "Skipping layer {} because only tile layers are supported.",
-                            layer.id()
-                        );
+                        if !matches!(layer.layer_type(), tiled::LayerType::Image(_)) {
+                            warn!(
+                                "Skipping layer {} because only tile layers are supported.",
+                                layer.id()
+                            );
+                        }
                         continue;
                     };
 
@@ -284,6 +486,11 @@ fn process_loaded_maps(
                                     ..Default::default()
                                 })
                                 .id();
+
+                            if let Some(animated) = animations.get(&layer_tile.id()) {
+                                commands.entity(tile_entity).insert(*animated);
+                            }
+
                             storage.set(&position, tile_entity);
                         }
                     }
@@ -314,6 +521,139 @@ fn process_loaded_maps(
     }
 }
 
+/// Spawns the tiles from an image collection tileset as sprites.
+///
+/// `bevy_ecs_tilemap` can only draw these without its `atlas` feature, which is needed
+/// for WebGL2. Sprites also allow the images to have different sizes. Like in Tiled,
+/// each image is drawn from the bottom-left corner of its cell.
+fn spawn_collection_layers(
+    commands: &mut Commands,
+    tiled_map: &TiledMap,
+    tileset_index: usize,
+    images: &HashMap<tiled::TileId, Handle<Image>>,
+    layer_storage: &mut TiledLayersStorage,
+) {
+    if !matches!(tiled_map.map.orientation, tiled::Orientation::Orthogonal) {
+        warn!("Skipping image collection tileset because only orthogonal maps are supported.");
+        return;
+    }
+
+    let map_bounds = map_world_bounds(tiled_map);
+    let tile_width = tiled_map.map.tile_width as f32;
+    let tile_height = tiled_map.map.tile_height as f32;
+
+    for (layer_index, layer) in tiled_map.map.layers().enumerate() {
+        let tiled::LayerType::Tiles(tiled::TileLayer::Finite(layer_data)) = layer.layer_type()
+        else {
+            continue;
+        };
+
+        let mut sprites = vec![];
+
+        for x in 0..layer_data.width() as i32 {
+            for y in 0..layer_data.height() as i32 {
+                let Some(layer_tile) = layer_data.get_tile(x, y) else {
+                    continue;
+                };
+
+                if tileset_index != layer_tile.tileset_index() {
+                    continue;
+                }
+
+                let Some(image) = images.get(&layer_tile.id()) else {
+                    continue;
+                };
+
+                // Y axis in bevy/tiled are reversed.
+                let pos = Vec2::new(
+                    map_bounds.min.x + layer.offset_x + x as f32 * tile_width,
+                    map_bounds.max.y - layer.offset_y - (y + 1) as f32 * tile_height,
+                );
+
+                sprites.push((
+                    Sprite {
+                        image: image.clone(),
+                        flip_x: layer_tile.flip_h,
+                        flip_y: layer_tile.flip_v,
+                        anchor: Anchor::BottomLeft,
+                        ..default()
+                    },
+                    Transform::from_translation(pos.extend(0.0)),
+                ));
+            }
+        }
+
+        if sprites.is_empty() {
+            continue;
+        }
+
+        // The same depth as a tile layer would have.
+        let layer_entity = commands
+            .spawn((
+                Transform::from_xyz(0.0, 0.0, layer_index as f32),
+                Visibility::default(),
+            ))
+            .with_children(|parent| {
+                for sprite in sprites {
+                    parent.spawn(sprite);
+                }
+            })
+            .id();
+
+        layer_storage.collection_layers.push(layer_entity);
+    }
+}
+
+/// Converts a Tiled tile animation to an `AnimatedTile`, which can only play a range of
+/// consecutive tiles, each for the same amount of time. The frames play at the average
+/// of their durations.
+fn animated_tile(frames: &[tiled::Frame]) -> Option<AnimatedTile> {
+    let first = frames.first()?.tile_id;
+
+    let consecutive = frames
+        .iter()
+        .enumerate()
+        .all(|(i, frame)| frame.tile_id == first + i as u32);
+    let total_ms = frames.iter().map(|frame| frame.duration).sum::<u32>();
+    if !consecutive || total_ms == 0 {
+        return None;
+    }
+
+    Some(AnimatedTile {
+        start: first,
+        end: first + frames.len() as u32,
+        // Frames per second
+        speed: frames.len() as f32 * 1000.0 / total_ms as f32,
+    })
+}
+
+fn update_parallax(
+    camera_query: Query<&Transform, (With<Camera2d>, Without<Parallax>)>,
+    mut parallax_query: Query<(&mut Transform, &Parallax)>,
+) {
+    let Ok(camera_transform) = camera_query.get_single() else {
+        return;
+    };
+
+    let camera_pos = camera_transform.translation.truncate();
+
+    for (mut transform, parallax) in parallax_query.iter_mut() {
+        let offset = camera_pos * (Vec2::ONE - parallax.factor);
+        transform.translation = parallax.origin + offset.extend(0.0);
+    }
+}
+
+fn y_sort(mut query: Query<(&mut Transform, &YSort), Or<(Changed<Transform>, Changed<YSort>)>>) {
+    for (mut transform, y_sort) in query.iter_mut() {
+        let z = layer::y_sorted(y_sort.0, transform.translation.y);
+
+        // Avoid triggering change detection when nothing moved
+        if transform.translation.z != z {
+            transform.translation.z = z;
+        }
+    }
+}
+
 pub fn get_float_property(object: &Object, name: &str) -> anyhow::Result<f32> {
     let val = object
         .properties
@@ -338,6 +678,18 @@ pub fn get_int_property(object: &Object, name: &str) -> anyhow::Result<i32> {
     val
 }
 
+pub fn get_bool_property(object: &Object, name: &str) -> anyhow::Result<bool> {
+    let val = object
+        .properties
+        .get(name)
+        .ok_or_else(|| anyhow!("property \"{}\" not found.", name))
+        .and_then(|v| match v {
+            PropertyValue::BoolValue(v) => Ok(*v),
+            _ => Err(anyhow!("property \"{}\" type mismatch.", name)),
+        });
+    val
+}
+
 pub fn get_string_property(object: &Object, name: &str) -> anyhow::Result<String> {
     let val = object
         .properties
@@ -350,6 +702,18 @@ pub fn get_string_property(object: &Object, name: &str) -> anyhow::Result<String
     val
 }
 
+pub fn get_color_property(object: &Object, name: &str) -> anyhow::Result<Color> {
+    let val = object
+        .properties
+        .get(name)
+        .ok_or_else(|| anyhow!("property \"{}\" not found.", name))
+        .and_then(|v| match v {
+            PropertyValue::ColorValue(c) => Ok(Color::srgba_u8(c.red, c.green, c.blue, c.alpha)),
+            _ => Err(anyhow!("property \"{}\" type mismatch.", name)),
+        });
+    val
+}
+
 pub fn find_objects<'a>(
     map: &'a TiledMap,
     user_type: &'a str,
@@ -364,14 +728,50 @@ pub fn find_objects<'a>(
         .filter(move |o| o.user_type == user_type)
 }
 
-pub fn map_to_world(map: &TiledMap, pos: Vec2, size: Vec2, z: f32) -> Transform {
-    let map_height = map.map.height * map.map.tile_height;
-    let map_width = map.map.width * map.map.tile_width;
+/// Returns the world-space area covered by the map's tiles. Maps are centered on
+/// the origin.
+pub fn map_world_bounds(map: &TiledMap) -> Rect {
+    let size = Vec2::new(
+        (map.map.width * map.map.tile_width) as f32,
+        (map.map.height * map.map.tile_height) as f32,
+    );
+
+    Rect::from_center_size(Vec2::ZERO, size)
+}
+
+/// Returns the transform of the center of an object, where `rotation` is the object's
+/// rotation in degrees clockwise around `pos`, as in Tiled.
+pub fn map_to_world(map: &TiledMap, pos: Vec2, size: Vec2, rotation: f32, z: f32) -> Transform {
+    let bounds = map_world_bounds(map);
+
+    // Y axis in bevy/tiled are reversed, so clockwise is a negative rotation.
+    let rotation = Quat::from_rotation_z(-rotation.to_radians());
+    let origin = Vec2::new(bounds.min.x + pos.x, bounds.max.y - pos.y);
+    let center = origin + (rotation * (size / 2.0).extend(0.0)).truncate();
+
+    Transform::from_translation(center.extend(z)).with_rotation(rotation)
+}
 
-    Transform::from_xyz(
-        map_width as f32 / -2.0 + pos.x + size.x / 2.0,
-        // Y axis in bevy/tiled are reversed.
-        map_height as f32 / 2.0 - pos.y + size.y / 2.0,
-        z,
-    )
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_bounds_match_map_size() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/textures/level1.tmx");
+        let map = tiled::Loader::new().load_tmx_map(path).unwrap();
+
+        let tiled_map = TiledMap {
+            map,
+            tilemap_textures: default(),
+            collection_textures: default(),
+            image_layer_textures: default(),
+        };
+
+        // level1 is 32x24 tiles of 32x32 pixels.
+        assert_eq!(
+            map_world_bounds(&tiled_map),
+            Rect::new(-512.0, -384.0, 512.0, 384.0)
+        );
+    }
 }
@@ -0,0 +1,308 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use tiled::ObjectShape;
+
+use crate::{
+    loading::{EnemyAtlasHandles, FontHandles},
+    map::{find_objects, get_float_property, get_int_property, get_string_property, TiledMap},
+    tower::TowerKind,
+    ui_color,
+    wave::{path_indices, Wave},
+    TaipoState, FONT_SIZE, FONT_SIZE_LABEL,
+};
+
+pub struct MapValidationPlugin;
+
+impl Plugin for MapValidationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MapErrors>();
+
+        app.add_systems(OnEnter(TaipoState::MapError), spawn_map_errors);
+
+        app.add_systems(
+            Update,
+            back_button_system.run_if(in_state(TaipoState::MapError)),
+        );
+    }
+}
+
+const FONT_SIZE_ERROR: f32 = 14.0;
+
+/// Problems found with the selected map, which prevented a game from starting.
+#[derive(Resource, Default)]
+pub struct MapErrors(pub Vec<String>);
+
+#[derive(Component)]
+struct BackButton;
+
+/// Checks a map for problems that would prevent a game from being played on it,
+/// returning a description of each one.
+pub fn validate_map(tiled_map: &TiledMap, enemy_atlas_handles: &EnemyAtlasHandles) -> Vec<String> {
+    let mut errors = vec![];
+
+    // paths
+
+    let mut paths = HashSet::new();
+
+    for object in find_objects(tiled_map, "enemy_path") {
+        let index = match get_int_property(&object, "index") {
+            Ok(index) => index,
+            Err(err) => {
+                errors.push(format!("enemy_path: {}", err));
+                continue;
+            }
+        };
+
+        if !matches!(
+            object.shape,
+            ObjectShape::Polyline { .. } | ObjectShape::Polygon { .. }
+        ) {
+            errors.push(format!("enemy_path {}: not a polyline.", index));
+        } else if !paths.insert(index) {
+            errors.push(format!(
+                "enemy_path {}: index is used more than once.",
+                index
+            ));
+        }
+    }
+
+    // goals
+
+    let mut goals = HashSet::new();
+
+    for object in find_objects(tiled_map, "goal") {
+        let index = get_int_property(&object, "index").unwrap_or(0);
+
+        if let ObjectShape::Rect { .. } = object.shape {
+            goals.insert(index);
+        } else {
+            errors.push(format!("goal {}: not a rectangle.", index));
+        }
+    }
+
+    if goals.is_empty() {
+        errors.push("map: no goal.".to_string());
+    }
+
+    // waves
+
+    let mut waves = find_objects(tiled_map, "wave").collect::<Vec<_>>();
+
+    if waves.is_empty() {
+        errors.push("map: no waves.".to_string());
+    }
+
+    // Waves are numbered in the order that they are played.
+    waves.sort_by(|a, b| a.x.total_cmp(&b.x));
+
+    for (i, object) in waves.iter().enumerate() {
+        let name = format!("wave {}", i + 1);
+        let before = errors.len();
+
+        for property in ["num", "hp", "armor"] {
+            if let Err(err) = get_int_property(object, property) {
+                errors.push(format!("{}: {}", name, err));
+            }
+        }
+
+        for property in ["delay", "interval", "speed"] {
+            if let Err(err) = get_float_property(object, property) {
+                errors.push(format!("{}: {}", name, err));
+            }
+        }
+
+        match path_indices(object) {
+            Ok(indices) => {
+                for index in indices.iter().filter(|index| !paths.contains(*index)) {
+                    errors.push(format!("{}: no enemy_path with index {}.", name, index));
+                }
+            }
+            Err(err) => errors.push(format!("{}: {}", name, err)),
+        }
+
+        let goal = get_int_property(object, "goal_index").unwrap_or(0);
+        if !goals.is_empty() && !goals.contains(&goal) {
+            errors.push(format!("{}: no goal with index {}.", name, goal));
+        }
+
+        // Anything else that would make the wave invalid
+        if errors.len() > before {
+            continue;
+        }
+
+        let wave_paths: HashMap<i32, Vec<Vec2>> =
+            paths.iter().map(|index| (*index, vec![])).collect();
+
+        match Wave::new(object, &wave_paths) {
+            Ok(wave) => {
+                let kinds = std::iter::once(&wave.enemy)
+                    .chain(wave.enemy_weights.iter().map(|(kind, _)| kind))
+                    .chain(wave.splits_into.iter().map(|splits_into| &splits_into.kind));
+
+                for kind in kinds {
+                    if enemy_atlas_handles.get(kind).is_none() {
+                        errors.push(format!("{}: unknown enemy \"{}\".", name, kind));
+                    }
+                }
+            }
+            Err(err) => errors.push(format!("{}: {}", name, err)),
+        }
+    }
+
+    // tower slots
+
+    let mut tower_slots = HashSet::new();
+    let mut num_tower_slots = 0;
+
+    for object in find_objects(tiled_map, "tower_slot") {
+        num_tower_slots += 1;
+
+        let index = match get_int_property(&object, "index") {
+            Ok(index) => index,
+            Err(err) => {
+                errors.push(format!("tower_slot: {}", err));
+                continue;
+            }
+        };
+
+        if !tower_slots.insert(index) {
+            errors.push(format!(
+                "tower_slot {}: index is used more than once.",
+                index
+            ));
+        }
+
+        if !matches!(object.shape, ObjectShape::Rect { .. }) {
+            errors.push(format!("tower_slot {}: not a rectangle.", index));
+        }
+
+        if object.properties.contains_key("tower_kind") {
+            if let Err(err) = get_string_property(&object, "tower_kind")
+                .and_then(|kind| kind.parse::<TowerKind>())
+            {
+                errors.push(format!("tower_slot {}: {}", index, err));
+            }
+        }
+    }
+
+    if num_tower_slots == 0 {
+        errors.push("map: no tower slots.".to_string());
+    }
+
+    errors
+}
+
+fn spawn_map_errors(
+    mut commands: Commands,
+    font_handles: Res<FontHandles>,
+    map_errors: Res<MapErrors>,
+) {
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                justify_content: JustifyContent::Center,
+                align_self: AlignSelf::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(ui_color::OVERLAY.into()),
+            StateScoped(TaipoState::MapError),
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    Node {
+                        flex_direction: FlexDirection::Column,
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        align_self: AlignSelf::Center,
+                        max_width: Val::Percent(90.),
+                        padding: UiRect::all(Val::Px(20.)),
+                        ..default()
+                    },
+                    BackgroundColor(ui_color::DIALOG_BACKGROUND.into()),
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new("This map can't be played"),
+                        TextFont {
+                            font: font_handles.jptext.clone(),
+                            font_size: FONT_SIZE,
+                            ..default()
+                        },
+                        TextColor(ui_color::BAD_TEXT.into()),
+                        Node {
+                            margin: UiRect::bottom(Val::Px(10.0)),
+                            ..default()
+                        },
+                    ));
+
+                    for error in map_errors.0.iter() {
+                        parent.spawn((
+                            Text::new(error.clone()),
+                            TextFont {
+                                font: font_handles.jptext.clone(),
+                                font_size: FONT_SIZE_ERROR,
+                                ..default()
+                            },
+                            TextColor(ui_color::NORMAL_TEXT.into()),
+                        ));
+                    }
+
+                    parent
+                        .spawn((
+                            Button,
+                            Node {
+                                width: Val::Px(200.0),
+                                height: Val::Px(48.0),
+                                margin: UiRect::top(Val::Px(10.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                            BackButton,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                Text::new("Return to Main Menu"),
+                                TextFont {
+                                    font: font_handles.jptext.clone(),
+                                    font_size: FONT_SIZE_LABEL,
+                                    ..default()
+                                },
+                                TextColor(ui_color::BUTTON_TEXT.into()),
+                            ));
+                        });
+                });
+        });
+}
+
+fn back_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<BackButton>),
+    >,
+    mut next_state: ResMut<NextState<TaipoState>>,
+) {
+    for (interaction, mut background_color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                next_state.set(TaipoState::MainMenu);
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
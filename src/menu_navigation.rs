@@ -0,0 +1,279 @@
+use bevy::{prelude::*, ui::UiSystem, utils::HashSet};
+
+use crate::{ui_color, TaipoState};
+
+pub struct MenuNavigationPlugin;
+
+impl Plugin for MenuNavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MenuNavigationBindings>()
+            .init_resource::<RebindingAction>();
+
+        app.add_systems(
+            PreUpdate,
+            navigate_menu.after(UiSystem::Focus).run_if(
+                in_state(TaipoState::MainMenu)
+                    .or(in_state(TaipoState::Paused))
+                    .or(in_state(TaipoState::GameOver))
+                    .or(in_state(TaipoState::MapError)),
+            ),
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuAction {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+}
+impl MenuAction {
+    pub const ALL: [MenuAction; 5] = [
+        MenuAction::Up,
+        MenuAction::Down,
+        MenuAction::Left,
+        MenuAction::Right,
+        MenuAction::Select,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Up => "Up",
+            Self::Down => "Down",
+            Self::Left => "Left",
+            Self::Right => "Right",
+            Self::Select => "Select",
+        }
+    }
+
+    /// The direction that this action moves focus in, in UI coordinates, which have
+    /// y pointing down.
+    fn direction(self) -> Vec2 {
+        match self {
+            Self::Up => Vec2::NEG_Y,
+            Self::Down => Vec2::Y,
+            Self::Left => Vec2::NEG_X,
+            Self::Right => Vec2::X,
+            Self::Select => Vec2::ZERO,
+        }
+    }
+}
+
+/// The keys used to navigate menus.
+#[derive(Resource, Clone, PartialEq)]
+pub struct MenuNavigationBindings {
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub select: KeyCode,
+}
+impl Default for MenuNavigationBindings {
+    fn default() -> Self {
+        Self {
+            up: KeyCode::ArrowUp,
+            down: KeyCode::ArrowDown,
+            left: KeyCode::ArrowLeft,
+            right: KeyCode::ArrowRight,
+            select: KeyCode::Enter,
+        }
+    }
+}
+impl MenuNavigationBindings {
+    pub fn key(&self, action: MenuAction) -> KeyCode {
+        match action {
+            MenuAction::Up => self.up,
+            MenuAction::Down => self.down,
+            MenuAction::Left => self.left,
+            MenuAction::Right => self.right,
+            MenuAction::Select => self.select,
+        }
+    }
+
+    /// Binds `key` to `action`, unless it is already bound to a different action, in
+    /// which case that action is returned as the error.
+    pub fn bind(&mut self, action: MenuAction, key: KeyCode) -> Result<(), MenuAction> {
+        if let Some(other) = MenuAction::ALL
+            .into_iter()
+            .find(|other| *other != action && self.key(*other) == key)
+        {
+            return Err(other);
+        }
+
+        let binding = match action {
+            MenuAction::Up => &mut self.up,
+            MenuAction::Down => &mut self.down,
+            MenuAction::Left => &mut self.left,
+            MenuAction::Right => &mut self.right,
+            MenuAction::Select => &mut self.select,
+        };
+        *binding = key;
+
+        Ok(())
+    }
+}
+
+/// Returns a short name for a key, e.g. "W" rather than `KeyCode::KeyW`.
+pub fn key_label(key: KeyCode) -> String {
+    let name = format!("{:?}", key);
+
+    ["Key", "Digit"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .map_or(name.clone(), str::to_string)
+}
+
+/// The action that is waiting for a key to be pressed so that it can be rebound.
+/// Menu navigation is disabled while this is set, so that the key can be captured.
+#[derive(Resource, Default)]
+pub struct RebindingAction(pub Option<MenuAction>);
+
+/// The button that keyboard and gamepad navigation acts on.
+#[derive(Component)]
+struct Focused;
+
+/// Stick movement smaller than this is ignored.
+const STICK_DEADZONE: f32 = 0.5;
+/// After moving focus, the stick must return closer to the center than this before
+/// it can move focus again, so that one flick moves focus one step.
+const STICK_RELEASE: f32 = 0.3;
+
+/// Moves focus between visible buttons with the keys in `MenuNavigationBindings`, a
+/// gamepad's D-pad, or its left stick, and presses the focused button with the select
+/// key, Space, or the gamepad's south face button.
+///
+/// Buttons are pressed by setting their `Interaction`, so the systems that handle
+/// mouse clicks handle these presses too.
+fn navigate_menu(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<(Entity, &Gamepad)>,
+    bindings: Res<MenuNavigationBindings>,
+    rebinding: Res<RebindingAction>,
+    mut button_query: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &ViewVisibility,
+            &ComputedNode,
+            &mut Interaction,
+        ),
+        With<Button>,
+    >,
+    focused_query: Query<Entity, With<Focused>>,
+    // Gamepads whose stick has moved focus and hasn't been released yet.
+    mut stick_held: Local<HashSet<Entity>>,
+    mut pressed: Local<Option<Entity>>,
+) {
+    // Release the button pressed last frame, like releasing the mouse would.
+    if let Some(entity) = pressed.take() {
+        if let Ok((_, _, _, _, mut interaction)) = button_query.get_mut(entity) {
+            interaction.set_if_neq(Interaction::None);
+        }
+    }
+
+    if rebinding.0.is_some() {
+        return;
+    }
+
+    let mut direction = Vec2::ZERO;
+    for action in MenuAction::ALL {
+        if action != MenuAction::Select && keyboard.just_pressed(bindings.key(action)) {
+            direction = action.direction();
+        }
+    }
+
+    let mut select =
+        keyboard.just_pressed(bindings.select) || keyboard.just_pressed(KeyCode::Space);
+
+    // Forget gamepads that were disconnected.
+    stick_held.retain(|entity| gamepads.contains(*entity));
+
+    for (entity, gamepad) in gamepads.iter() {
+        for (button, action) in [
+            (GamepadButton::DPadUp, MenuAction::Up),
+            (GamepadButton::DPadDown, MenuAction::Down),
+            (GamepadButton::DPadLeft, MenuAction::Left),
+            (GamepadButton::DPadRight, MenuAction::Right),
+        ] {
+            if gamepad.just_pressed(button) {
+                direction = action.direction();
+            }
+        }
+
+        let stick = gamepad.left_stick();
+        if stick_held.contains(&entity) {
+            if stick.length() <= STICK_RELEASE {
+                stick_held.remove(&entity);
+            }
+        } else if stick.length() > STICK_DEADZONE {
+            stick_held.insert(entity);
+            // The stick's y points up, but UI coordinates have y pointing down.
+            direction = Vec2::new(stick.x, -stick.y).normalize();
+        }
+
+        select |= gamepad.just_pressed(GamepadButton::South);
+    }
+
+    let buttons = button_query
+        .iter()
+        // Buttons inside a node with `Display::None` are still visible, but have no size.
+        .filter(|(_, _, visibility, node, _)| visibility.get() && node.size() != Vec2::ZERO)
+        .map(|(entity, transform, _, _, _)| (entity, transform.translation().truncate()))
+        .collect::<Vec<_>>();
+
+    let current = focused_query
+        .iter()
+        .find_map(|entity| buttons.iter().find(|(e, _)| *e == entity).copied());
+
+    if direction != Vec2::ZERO {
+        let next = match current {
+            Some((_, from)) => buttons
+                .iter()
+                .filter_map(|(entity, pos)| {
+                    let diff = *pos - from;
+                    let along = diff.dot(direction);
+                    if along <= 1.0 {
+                        return None;
+                    }
+
+                    // Prefer buttons that are closest to being directly in line.
+                    let across = (diff - direction * along).length();
+                    Some((*entity, along + across * 2.0))
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(entity, _)| entity),
+            // Start from the top-left button.
+            None => buttons
+                .iter()
+                .min_by(|a, b| (a.1.y, a.1.x).partial_cmp(&(b.1.y, b.1.x)).unwrap())
+                .map(|(entity, _)| *entity),
+        };
+
+        if let Some(next) = next {
+            for entity in focused_query.iter() {
+                commands.entity(entity).remove::<(Focused, Outline)>();
+            }
+
+            commands.entity(next).insert((
+                Focused,
+                Outline::new(
+                    Val::Px(2.0),
+                    Val::ZERO,
+                    ui_color::FOCUSED_BUTTON_OUTLINE.into(),
+                ),
+            ));
+        }
+    }
+
+    if select {
+        if let Some((entity, _)) = current {
+            if let Ok((_, _, _, _, mut interaction)) = button_query.get_mut(entity) {
+                *interaction = Interaction::Pressed;
+                *pressed = Some(entity);
+            }
+        }
+    }
+}
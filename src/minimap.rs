@@ -0,0 +1,214 @@
+use bevy::{
+    color::palettes::css::{GRAY, LIME, RED, WHITE},
+    prelude::*,
+};
+
+use crate::{
+    enemy::AnimationState,
+    main_menu::SelectedLevel,
+    map::{map_world_bounds, TiledMap},
+    ui_color,
+    wave::EnemyPaths,
+    CleanupBeforeNewGame, Goal, TaipoState, TowerSlot,
+};
+
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MinimapSettings>()
+            .insert_resource(MinimapTimer(Timer::from_seconds(
+                0.25,
+                TimerMode::Repeating,
+            )));
+
+        app.add_systems(
+            OnTransition {
+                exited: TaipoState::Spawn,
+                entered: TaipoState::Playing,
+            },
+            spawn_minimap,
+        );
+
+        app.add_systems(
+            Update,
+            (update_minimap_visibility, update_minimap_enemies)
+                .run_if(in_state(TaipoState::Playing)),
+        );
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct MinimapSettings {
+    pub enabled: bool,
+}
+
+/// Throttles updates to the enemy markers, which are rebuilt from scratch.
+#[derive(Resource)]
+struct MinimapTimer(Timer);
+
+/// The world-space area displayed by the minimap.
+#[derive(Component)]
+struct Minimap {
+    min: Vec2,
+    size: Vec2,
+}
+impl Minimap {
+    fn marker_node(&self, pos: Vec2, size: f32) -> Node {
+        let frac = (pos - self.min) / self.size;
+
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Percent(frac.x * 100.0),
+            // UI coordinates have y pointing down
+            top: Val::Percent((1.0 - frac.y) * 100.0),
+            margin: UiRect {
+                left: Val::Px(size / -2.0),
+                top: Val::Px(size / -2.0),
+                ..default()
+            },
+            width: Val::Px(size),
+            height: Val::Px(size),
+            ..default()
+        }
+    }
+}
+#[derive(Component)]
+struct MinimapEnemy;
+
+const MINIMAP_WIDTH: f32 = 128.0;
+const MINIMAP_PATH: Srgba = GRAY;
+const MINIMAP_TOWER_SLOT: Srgba = WHITE;
+const MINIMAP_GOAL: Srgba = LIME;
+const MINIMAP_ENEMY: Srgba = RED;
+/// World-space distance between the dots used to draw enemy paths.
+const PATH_DOT_SPACING: f32 = 24.0;
+
+fn spawn_minimap(
+    mut commands: Commands,
+    selected_level: Res<SelectedLevel>,
+    maps: Res<Assets<TiledMap>>,
+    paths: Res<EnemyPaths>,
+    settings: Res<MinimapSettings>,
+    tower_slot_query: Query<&Transform, With<TowerSlot>>,
+    goal_query: Query<&Transform, With<Goal>>,
+) {
+    let Some(tiled_map) = maps.get(&selected_level.0) else {
+        return;
+    };
+
+    let bounds = map_world_bounds(tiled_map);
+    let size = bounds.size();
+
+    let minimap = Minimap {
+        min: bounds.min,
+        size,
+    };
+
+    let mut markers = vec![];
+
+    for path in paths.0.values() {
+        for segment in path.windows(2) {
+            let (start, end) = (segment[0], segment[1]);
+            let steps = (start.distance(end) / PATH_DOT_SPACING).ceil().max(1.0) as usize;
+
+            for step in 0..steps {
+                let pos = start.lerp(end, step as f32 / steps as f32);
+                markers.push((minimap.marker_node(pos, 2.0), MINIMAP_PATH));
+            }
+        }
+    }
+
+    for transform in tower_slot_query.iter() {
+        markers.push((
+            minimap.marker_node(transform.translation.truncate(), 4.0),
+            MINIMAP_TOWER_SLOT,
+        ));
+    }
+
+    for transform in goal_query.iter() {
+        markers.push((
+            minimap.marker_node(transform.translation.truncate(), 6.0),
+            MINIMAP_GOAL,
+        ));
+    }
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                right: Val::Px(0.0),
+                // Just above the typing buffer
+                bottom: Val::Px(42.0),
+                width: Val::Px(MINIMAP_WIDTH),
+                height: Val::Px(MINIMAP_WIDTH * size.y / size.x),
+                ..default()
+            },
+            BackgroundColor(ui_color::TRANSPARENT_BACKGROUND.into()),
+            if settings.enabled {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            },
+            minimap,
+            CleanupBeforeNewGame,
+        ))
+        .with_children(|parent| {
+            for (node, color) in markers {
+                parent.spawn((node, BackgroundColor(color.into())));
+            }
+        });
+}
+
+fn update_minimap_visibility(
+    settings: Res<MinimapSettings>,
+    mut query: Query<&mut Visibility, With<Minimap>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    for mut visibility in query.iter_mut() {
+        *visibility = if settings.enabled {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+fn update_minimap_enemies(
+    mut commands: Commands,
+    mut timer: ResMut<MinimapTimer>,
+    settings: Res<MinimapSettings>,
+    minimap_query: Query<(Entity, &Minimap)>,
+    marker_query: Query<Entity, With<MinimapEnemy>>,
+    enemy_query: Query<(&Transform, &AnimationState)>,
+    time: Res<Time<Real>>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() || !settings.enabled {
+        return;
+    }
+
+    let Ok((minimap_entity, minimap)) = minimap_query.get_single() else {
+        return;
+    };
+
+    for entity in marker_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    commands.entity(minimap_entity).with_children(|parent| {
+        for (transform, state) in enemy_query.iter() {
+            if matches!(state, AnimationState::Corpse) {
+                continue;
+            }
+
+            parent.spawn((
+                minimap.marker_node(transform.translation.truncate(), 3.0),
+                BackgroundColor(MINIMAP_ENEMY.into()),
+                MinimapEnemy,
+            ));
+        }
+    });
+}
@@ -0,0 +1,66 @@
+use bevy::{audio::Volume, prelude::*};
+
+use crate::{loading::AudioHandles, AudioSettings, CleanupBeforeNewGame, TaipoState};
+
+pub struct MusicPlugin;
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnTransition {
+                exited: TaipoState::Spawn,
+                entered: TaipoState::Playing,
+            },
+            start_music,
+        );
+        app.add_systems(OnEnter(TaipoState::GameOver), stop_music);
+        app.add_systems(OnEnter(TaipoState::Paused), pause_music);
+        app.add_systems(OnExit(TaipoState::Paused), resume_music);
+
+        app.add_systems(Update, update_music_volume);
+    }
+}
+
+#[derive(Component)]
+struct Music;
+
+fn start_music(
+    mut commands: Commands,
+    audio_handles: Res<AudioHandles>,
+    audio_settings: Res<AudioSettings>,
+) {
+    commands.spawn((
+        AudioPlayer(audio_handles.music.clone()),
+        PlaybackSettings::LOOP.with_volume(Volume::new(audio_settings.music_volume())),
+        Music,
+        CleanupBeforeNewGame,
+    ));
+}
+
+fn stop_music(mut commands: Commands, query: Query<Entity, With<Music>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn pause_music(query: Query<&AudioSink, With<Music>>) {
+    for sink in query.iter() {
+        sink.pause();
+    }
+}
+
+fn resume_music(query: Query<&AudioSink, With<Music>>) {
+    for sink in query.iter() {
+        sink.play();
+    }
+}
+
+fn update_music_volume(audio_settings: Res<AudioSettings>, query: Query<&AudioSink, With<Music>>) {
+    if !audio_settings.is_changed() {
+        return;
+    }
+
+    for sink in query.iter() {
+        sink.set_volume(audio_settings.music_volume());
+    }
+}
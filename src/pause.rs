@@ -0,0 +1,142 @@
+use bevy::prelude::*;
+
+use crate::{loading::FontHandles, ui_color, TaipoState, FONT_SIZE, FONT_SIZE_LABEL};
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(TaipoState::Paused), (spawn_pause_menu, pause_time));
+        app.add_systems(OnExit(TaipoState::Paused), unpause_time);
+
+        app.add_systems(
+            Update,
+            pause_button_system.run_if(in_state(TaipoState::Paused)),
+        );
+    }
+}
+
+#[derive(Component, Clone, Copy)]
+enum PauseButton {
+    Resume,
+    MainMenu,
+}
+impl PauseButton {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Resume => "Resume",
+            Self::MainMenu => "Return to Main Menu",
+        }
+    }
+}
+
+// Most gameplay systems only run while `Playing`, but pausing virtual time also stops
+// anything else that is driven by it.
+fn pause_time(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+fn unpause_time(mut time: ResMut<Time<Virtual>>) {
+    time.unpause();
+}
+
+fn spawn_pause_menu(mut commands: Commands, font_handles: Res<FontHandles>) {
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                justify_content: JustifyContent::Center,
+                align_self: AlignSelf::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(ui_color::OVERLAY.into()),
+            GlobalZIndex(1),
+            StateScoped(TaipoState::Paused),
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    Node {
+                        flex_direction: FlexDirection::Column,
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        align_self: AlignSelf::Center,
+                        padding: UiRect::all(Val::Px(20.)),
+                        ..default()
+                    },
+                    BackgroundColor(ui_color::DIALOG_BACKGROUND.into()),
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new("Paused"),
+                        TextFont {
+                            font: font_handles.jptext.clone(),
+                            font_size: FONT_SIZE,
+                            ..default()
+                        },
+                        TextColor(ui_color::NORMAL_TEXT.into()),
+                        Node {
+                            margin: UiRect::bottom(Val::Px(10.0)),
+                            ..default()
+                        },
+                    ));
+
+                    for button in [PauseButton::Resume, PauseButton::MainMenu] {
+                        parent
+                            .spawn((
+                                Button,
+                                Node {
+                                    width: Val::Px(200.0),
+                                    height: Val::Px(48.0),
+                                    margin: UiRect::all(Val::Px(5.0)),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..default()
+                                },
+                                BackgroundColor(ui_color::NORMAL_BUTTON.into()),
+                                button,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    Text::new(button.label()),
+                                    TextFont {
+                                        font: font_handles.jptext.clone(),
+                                        font_size: FONT_SIZE_LABEL,
+                                        ..default()
+                                    },
+                                    TextColor(ui_color::BUTTON_TEXT.into()),
+                                ));
+                            });
+                    }
+                });
+        });
+}
+
+fn pause_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &PauseButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut next_state: ResMut<NextState<TaipoState>>,
+) {
+    for (interaction, mut background_color, button) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *background_color = ui_color::PRESSED_BUTTON.into();
+
+                next_state.set(match button {
+                    PauseButton::Resume => TaipoState::Playing,
+                    PauseButton::MainMenu => TaipoState::MainMenu,
+                });
+            }
+            Interaction::Hovered => {
+                *background_color = ui_color::HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *background_color = ui_color::NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use bevy::{audio::Volume, prelude::*, utils::HashMap};
+
+use crate::{loading::AudioHandles, AudioSettings, TaipoState};
+
+pub struct SfxPlugin;
+
+impl Plugin for SfxPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SfxEvent>();
+
+        app.add_systems(PostUpdate, play_sfx.run_if(in_state(TaipoState::Playing)));
+    }
+}
+
+/// Sound effects, from least to most important. Only the most important sound sent
+/// in a frame is played, so that e.g. building a tower doesn't also play the sound
+/// for completing a word.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Sfx {
+    Complete,
+    Sell,
+    Upgrade,
+    Build,
+    GoalDamage,
+}
+impl Sfx {
+    fn handle(self, audio_handles: &AudioHandles) -> Handle<AudioSource> {
+        match self {
+            Self::Complete => audio_handles.complete.clone(),
+            // A falling minor third
+            Self::Sell => audio_handles.sell.clone(),
+            // A rising major third, higher than building
+            Self::Upgrade => audio_handles.upgrade.clone(),
+            // A rising major third
+            Self::Build => audio_handles.build.clone(),
+            Self::GoalDamage => audio_handles.goal_damage.clone(),
+        }
+    }
+
+    /// The sound won't be played again until this much time has passed.
+    fn cooldown(self) -> Duration {
+        match self {
+            // Many enemies may be attacking at once.
+            Self::GoalDamage => Duration::from_millis(500),
+            _ => Duration::from_millis(50),
+        }
+    }
+}
+
+#[derive(Event)]
+pub struct SfxEvent(pub Sfx);
+
+const SFX_VOLUME: f32 = 0.1;
+
+fn play_sfx(
+    mut commands: Commands,
+    mut events: EventReader<SfxEvent>,
+    mut last_played: Local<HashMap<Sfx, Duration>>,
+    audio_handles: Res<AudioHandles>,
+    audio_settings: Res<AudioSettings>,
+    time: Res<Time<Real>>,
+) {
+    let Some(sfx) = events.read().map(|event| event.0).max() else {
+        return;
+    };
+
+    if audio_settings.mute {
+        return;
+    }
+
+    let now = time.elapsed();
+    if last_played
+        .get(&sfx)
+        .is_some_and(|last| now.saturating_sub(*last) < sfx.cooldown())
+    {
+        return;
+    }
+    last_played.insert(sfx, now);
+
+    commands.spawn((
+        AudioPlayer(sfx.handle(&audio_handles)),
+        PlaybackSettings::DESPAWN
+            .with_volume(Volume::new(SFX_VOLUME * audio_settings.sfx_volume())),
+    ));
+}
@@ -0,0 +1,77 @@
+use bevy::prelude::*;
+
+use crate::{main_menu::GameSpeed, TaipoState};
+
+pub struct SlowMotionPlugin;
+
+impl Plugin for SlowMotionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SlowMotion>();
+
+        app.add_systems(
+            Update,
+            (toggle_slow_motion, update_time_scale)
+                .chain()
+                .run_if(in_state(TaipoState::Playing)),
+        );
+    }
+}
+
+/// Debug key that cycles between normal speed, slow motion, and frozen.
+const SLOW_MOTION_KEY: KeyCode = KeyCode::F2;
+const SLOW_MOTION_SPEED: f32 = 0.1;
+/// How quickly the game's speed eases towards the target speed, as a fraction of the
+/// remaining difference per real-time second.
+const EASE_RATE: f32 = 8.0;
+
+/// Scales the game's virtual time, which drives the simulation, on top of the player's
+/// chosen `GameSpeed`. UI that should stay responsive uses `Time<Real>` instead.
+#[derive(Resource)]
+pub struct SlowMotion {
+    pub target_speed: f32,
+}
+impl Default for SlowMotion {
+    fn default() -> Self {
+        Self { target_speed: 1.0 }
+    }
+}
+
+fn toggle_slow_motion(keyboard: Res<ButtonInput<KeyCode>>, mut slow_motion: ResMut<SlowMotion>) {
+    if !keyboard.just_pressed(SLOW_MOTION_KEY) {
+        return;
+    }
+
+    slow_motion.target_speed = if slow_motion.target_speed == 1.0 {
+        SLOW_MOTION_SPEED
+    } else if slow_motion.target_speed > 0.0 {
+        0.0
+    } else {
+        1.0
+    };
+
+    info!("Game speed: {}", slow_motion.target_speed);
+}
+
+fn update_time_scale(
+    slow_motion: Res<SlowMotion>,
+    game_speed: Res<GameSpeed>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    real_time: Res<Time<Real>>,
+) {
+    let current = virtual_time.relative_speed();
+    let target = slow_motion.target_speed * game_speed.0;
+
+    if current == target {
+        return;
+    }
+
+    let eased = current + (target - current) * (1.0 - (-EASE_RATE * real_time.delta_secs()).exp());
+
+    let speed = if (target - eased).abs() < 0.01 {
+        target
+    } else {
+        eased
+    };
+
+    virtual_time.set_relative_speed(speed);
+}
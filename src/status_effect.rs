@@ -0,0 +1,207 @@
+use std::mem::discriminant;
+
+use bevy::prelude::*;
+
+use crate::{layer, loading::TextureHandles, HitPoints, TaipoState};
+
+pub struct StatusEffectPlugin;
+
+impl Plugin for StatusEffectPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            tick_status_effects.run_if(in_state(TaipoState::Playing)),
+        );
+    }
+}
+
+/// Vertical distance between stacked status effect icons.
+const ICON_SPACING: f32 = 9.0;
+
+#[derive(Component, Default)]
+pub struct StatusEffects(pub Vec<StatusEffect>);
+impl StatusEffects {
+    pub fn get_max_sub_armor(&self) -> u32 {
+        self.0
+            .iter()
+            .filter_map(|e| match e.kind {
+                StatusEffectKind::SubArmor(amt) => Some(amt),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn get_total_add_damage(&self) -> u32 {
+        self.0
+            .iter()
+            .filter_map(|e| match e.kind {
+                StatusEffectKind::AddDamage(amt) => Some(amt),
+                _ => None,
+            })
+            .sum::<u32>()
+    }
+
+    /// Returns the strongest active slow, as a fraction of speed to remove.
+    pub fn get_max_slow(&self) -> f32 {
+        self.0
+            .iter()
+            .filter_map(|e| match e.kind {
+                StatusEffectKind::Slow(amt) => Some(amt),
+                _ => None,
+            })
+            .fold(0.0, f32::max)
+            .min(1.0)
+    }
+
+    /// Adds an effect. A new poison replaces any existing poison rather than stacking
+    /// with it, keeping the highest damage and restarting the duration.
+    pub fn add(&mut self, mut effect: StatusEffect) {
+        if let StatusEffectKind::Poison { dps } = &mut effect.kind {
+            for existing in self.0.iter() {
+                if let StatusEffectKind::Poison { dps: existing_dps } = existing.kind {
+                    *dps = (*dps).max(existing_dps);
+                }
+            }
+
+            self.0
+                .retain(|e| !matches!(e.kind, StatusEffectKind::Poison { .. }));
+        }
+
+        self.0.push(effect);
+    }
+
+    /// Returns one effect for each distinct kind of active effect, in the order
+    /// that they were first applied.
+    pub fn distinct_kinds(&self) -> Vec<&StatusEffectKind> {
+        let mut kinds: Vec<&StatusEffectKind> = vec![];
+
+        for effect in self.0.iter() {
+            if !kinds
+                .iter()
+                .any(|kind| discriminant(*kind) == discriminant(&effect.kind))
+            {
+                kinds.push(&effect.kind);
+            }
+        }
+
+        kinds
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StatusEffect {
+    pub kind: StatusEffectKind,
+    /// If set, the effect is removed when the timer finishes.
+    pub timer: Option<Timer>,
+}
+#[derive(Clone, Debug)]
+pub enum StatusEffectKind {
+    SubArmor(u32),
+    AddDamage(u32),
+    /// Reduces movement speed by a fraction, from 0 to 1.
+    Slow(f32),
+    /// Deals damage once per second while the effect's timer runs.
+    Poison {
+        dps: u32,
+    },
+}
+impl StatusEffectKind {
+    pub fn icon(&self, texture_handles: &TextureHandles) -> Handle<Image> {
+        match self {
+            StatusEffectKind::SubArmor(_)
+            | StatusEffectKind::Slow(_)
+            | StatusEffectKind::Poison { .. } => texture_handles.status_down.clone(),
+            StatusEffectKind::AddDamage(_) => texture_handles.status_up.clone(),
+        }
+    }
+}
+
+/// Ticks the timers of status effects, removing those that have expired, and deals
+/// damage from poison.
+fn tick_status_effects(
+    mut query: Query<(&mut StatusEffects, Option<&mut HitPoints>)>,
+    time: Res<Time>,
+) {
+    for (mut status_effects, mut hit_points) in query.iter_mut() {
+        // Only trigger change detection when an effect is removed, so that the
+        // status effect icons aren't rebuilt every frame.
+        let mut expired = false;
+        let mut poison_damage = 0;
+
+        for effect in status_effects.bypass_change_detection().0.iter_mut() {
+            if let Some(timer) = effect.timer.as_mut() {
+                let seconds_before = timer.elapsed_secs().floor();
+
+                if timer.tick(time.delta()).finished() {
+                    expired = true;
+                }
+
+                if let StatusEffectKind::Poison { dps } = effect.kind {
+                    let seconds = timer.elapsed_secs().floor() - seconds_before;
+                    poison_damage += dps * seconds as u32;
+                }
+            }
+        }
+
+        // Dead enemies stay dead. Damage is dealt through `HitPoints` so that
+        // `enemy::death` notices it.
+        if let Some(hit_points) = hit_points.as_mut() {
+            if poison_damage > 0 && hit_points.current > 0 {
+                hit_points.current = hit_points.current.saturating_sub(poison_damage);
+            }
+        }
+
+        if expired {
+            status_effects
+                .0
+                .retain(|effect| !effect.timer.as_ref().is_some_and(Timer::finished));
+        }
+    }
+}
+
+/// A small icon displayed next to an entity for each kind of status effect
+/// that is active on it.
+#[derive(Component)]
+pub struct StatusEffectSprite;
+
+/// Replaces the status effect icons that are children of `entity` with one icon
+/// per distinct active effect kind, stacked downwards from `origin`.
+pub fn update_status_effect_sprites(
+    commands: &mut Commands,
+    entity: Entity,
+    status_effects: Option<&StatusEffects>,
+    children: Option<&Children>,
+    sprite_query: &Query<(), With<StatusEffectSprite>>,
+    texture_handles: &TextureHandles,
+    origin: Vec2,
+) {
+    if let Some(children) = children {
+        for child in children.iter() {
+            if sprite_query.get(*child).is_ok() {
+                commands.entity(*child).despawn_recursive();
+            }
+        }
+    }
+
+    let Some(status_effects) = status_effects else {
+        return;
+    };
+
+    for (i, kind) in status_effects.distinct_kinds().into_iter().enumerate() {
+        let icon = commands
+            .spawn((
+                Sprite {
+                    image: kind.icon(texture_handles),
+                    ..default()
+                },
+                Transform::from_translation(
+                    (origin - Vec2::new(0.0, i as f32 * ICON_SPACING)).extend(layer::HEALTHBAR_BG),
+                ),
+                StatusEffectSprite,
+            ))
+            .id();
+
+        commands.entity(entity).add_child(icon);
+    }
+}
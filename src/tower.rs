@@ -1,86 +1,293 @@
-use bevy::prelude::*;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use serde::Deserialize;
 
 use crate::{
-    bullet::Bullet, enemy::EnemyKind, layer, typing_target_finished_event, AfterUpdate, HitPoints,
-    StatusDownSprite, StatusEffect, StatusEffectKind, StatusEffects, StatusUpSprite, TaipoState,
-    TextureHandles, TowerSelection,
+    action_panel::ActionPanel,
+    bullet::{Bullet, Piercing, Splash},
+    enemy::{EnemyKind, EnemyPath, Flying},
+    layer,
+    loading::GameDataHandles,
+    status_effect::{
+        update_status_effect_sprites, StatusEffect, StatusEffectKind, StatusEffectSprite,
+        StatusEffects,
+    },
+    wave::Waves,
+    AfterUpdate, CleanupBeforeNewGame, Currency, GameData, HitPoints, TaipoState, TextureHandles,
+    TowerSelection,
 };
 
 pub struct TowerPlugin;
 
 impl Plugin for TowerPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<EnemyGrid>();
+
         app.add_systems(
             Update,
-            (
-                shoot_enemies,
-                // ensure that we process the TowerChanged event in the frame *after*. This adds
-                // a one frame delay but prevents us from needing yet another stage.
-                // TODO see if this works if we just shove it in AfterUpdate.
-                update_tower_status_effects.before(typing_target_finished_event),
-            )
+            (update_enemy_grid, shoot_enemies)
+                .chain()
                 .run_if(in_state(TaipoState::Playing)),
         );
 
+        // These run in `AfterUpdate` so that towers built or sold by
+        // `typing_target_finished_event` are visible to them in the same frame.
         app.add_systems(
             AfterUpdate,
             update_range_indicator.run_if(in_state(TaipoState::Playing)),
         );
         app.add_systems(
             AfterUpdate,
-            update_tower_appearance.run_if(in_state(TaipoState::Playing)),
+            (
+                update_tower_appearance,
+                update_tower_status_effects,
+                // Needs the new `StatusEffects` from the system above.
+                update_tower_status_effect_appearance,
+            )
+                .chain()
+                .run_if(in_state(TaipoState::Playing)),
         );
+
+        app.add_systems(OnExit(TaipoState::Load), init_tower_config);
+        app.init_resource::<LockedTowers>();
+
         app.add_systems(
-            AfterUpdate,
-            update_tower_status_effect_appearance.run_if(in_state(TaipoState::Playing)),
+            OnEnter(TaipoState::Spawn),
+            (spawn_range_indicator, init_locked_towers),
+        );
+        app.add_systems(
+            Update,
+            update_locked_towers.run_if(in_state(TaipoState::Playing)),
+        );
+        // Towers may have been prebuilt by the map while spawning. Resuming from `Paused`
+        // also enters `Playing`, so only the transition from `Spawn` counts.
+        app.add_systems(
+            OnTransition {
+                exited: TaipoState::Spawn,
+                entered: TaipoState::Playing,
+            },
+            prebuilt_towers_changed,
         );
-
-        app.add_systems(OnEnter(TaipoState::Spawn), spawn_range_indicator);
     }
 }
 
 pub static TOWER_PRICE: u32 = 20;
 
+/// The fraction of speed that enemies lose when hit by a debuff tower.
+const DEBUFF_SLOW: f32 = 0.3;
+/// How long the slow from a debuff tower lasts.
+const DEBUFF_SLOW_SECS: f32 = 2.0;
+/// Damage dealt each second by a poison tower's poison, which ignores armor.
+const POISON_DPS: u32 = 1;
+/// How long poison lasts. Poisoning an enemy again restarts this.
+const POISON_SECS: f32 = 3.0;
+/// Poison towers reuse the debuff tower's art, tinted.
+const POISON_TINT: Srgba = Srgba::rgb(0.6, 1.0, 0.5);
+/// The distance from its target within which a splash tower's bullets also damage
+/// other enemies.
+const SPLASH_RADIUS: f32 = 48.0;
+/// Splash towers reuse the shuriken tower's art, tinted.
+const SPLASH_TINT: Srgba = Srgba::rgb(1.0, 0.6, 0.4);
+/// The number of enemies that each of a railgun tower's bullets can hit.
+const RAILGUN_PIERCE: u32 = 3;
+const RAILGUN_BULLET_SPEED: f32 = 300.0;
+/// Railgun towers reuse the shuriken tower's art, tinted.
+const RAILGUN_TINT: Srgba = Srgba::rgb(0.5, 0.7, 1.0);
+
+/// Tower settings loaded from `game.ron`, by tower kind. Kinds that aren't
+/// configured use the default settings.
+#[derive(Resource, Debug, Default, Deserialize, Clone)]
+#[serde(transparent)]
+pub struct TowerConfig(HashMap<TowerKind, TowerKindConfig>);
+impl TowerConfig {
+    pub fn get(&self, kind: TowerKind) -> TowerKindConfig {
+        self.0.get(&kind).cloned().unwrap_or_default()
+    }
+
+    /// Returns true if `currency` covers the price of building a tower of `kind`.
+    pub fn can_afford(&self, kind: TowerKind, currency: &Currency) -> bool {
+        currency.current >= self.get(kind).price
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct TowerKindConfig {
+    /// The cost to build a tower of this kind.
+    pub price: u32,
+    pub range: f32,
+    /// The damage dealt by each shot. Defaults to 1 for `TowerKind::Basic`,
+    /// `TowerKind::Splash`, and `TowerKind::Railgun`, and 0 for other kinds, which
+    /// affect enemies in other ways.
+    pub damage: Option<u32>,
+    /// Seconds between shots.
+    pub fire_interval: f32,
+    /// If true, towers of this kind can shoot flying enemies.
+    pub anti_air: bool,
+    /// The cost of the first upgrade.
+    pub upgrade_price: u32,
+    /// The highest level that a tower of this kind can be upgraded to.
+    pub max_level: u32,
+    /// Stats gained with each upgrade, unless overridden by `upgrades`.
+    pub upgrade: TowerUpgradeConfig,
+    /// Stats gained with specific upgrades, starting with the upgrade to level 2.
+    pub upgrades: Vec<TowerUpgradeConfig>,
+    /// A condition that must be met before towers of this kind can be built. Kinds
+    /// without one are available from the start.
+    pub unlock: Option<TowerUnlock>,
+}
+impl Default for TowerKindConfig {
+    fn default() -> Self {
+        Self {
+            price: TOWER_PRICE,
+            range: 128.0,
+            damage: None,
+            fire_interval: 1.0,
+            anti_air: false,
+            upgrade_price: 10,
+            max_level: 3,
+            upgrade: TowerUpgradeConfig::default(),
+            upgrades: vec![],
+            unlock: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub enum TowerUnlock {
+    /// Unlocked once this many waves have been sent.
+    Wave(usize),
+    /// Unlocked once this much currency has been earned.
+    Earned(u32),
+}
+impl TowerUnlock {
+    fn is_met(&self, waves: &Waves, currency: &Currency) -> bool {
+        match self {
+            Self::Wave(num) => waves.current >= *num,
+            Self::Earned(amount) => currency.total_earned >= *amount,
+        }
+    }
+}
+
+/// Tower kinds that can't be built yet because their `TowerUnlock` condition hasn't
+/// been met.
+#[derive(Resource, Default)]
+pub struct LockedTowers(HashSet<TowerKind>);
+impl LockedTowers {
+    pub fn is_locked(&self, kind: TowerKind) -> bool {
+        self.0.contains(&kind)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct TowerUpgradeConfig {
+    pub range: f32,
+    pub damage: u32,
+    /// Added to the cost of the next upgrade.
+    pub price: u32,
+}
+impl Default for TowerUpgradeConfig {
+    fn default() -> Self {
+        Self {
+            range: 32.0,
+            damage: 0,
+            price: 10,
+        }
+    }
+}
+
 #[derive(Bundle, Default)]
 pub struct TowerBundle {
     pub kind: TowerKind,
     pub stats: TowerStats,
     pub state: TowerState,
+    pub targeting: TargetingMode,
     pub status_effects: StatusEffects,
 }
 impl TowerBundle {
-    pub fn new(kind: TowerKind) -> Self {
-        let damage = match kind {
-            TowerKind::Basic => 1,
+    pub fn new(kind: TowerKind, config: &TowerKindConfig) -> Self {
+        let damage = config.damage.unwrap_or(match kind {
+            TowerKind::Basic | TowerKind::Splash | TowerKind::Railgun => 1,
             _ => 0,
-        };
+        });
         Self {
             stats: TowerStats {
                 level: 1,
-                range: 128.0,
+                range: config.range,
                 damage,
-                upgrade_price: 10,
+                upgrade_price: config.upgrade_price,
+                spent: 0,
+                anti_air: config.anti_air,
             },
             state: TowerState {
-                timer: Timer::from_seconds(1.0, TimerMode::Repeating),
+                timer: Timer::from_seconds(config.fire_interval, TimerMode::Repeating),
             },
             kind,
             ..default()
         }
     }
 }
+// Builder-style overrides for constructing specific towers (e.g. a level 2 debuff tower)
+// without going through the build and upgrade flow.
+impl TowerBundle {
+    pub fn with_level(mut self, level: u32) -> Self {
+        self.stats.level = level;
+        self
+    }
+
+    pub fn with_range(mut self, range: f32) -> Self {
+        self.stats.range = range;
+        self
+    }
+
+    pub fn with_damage(mut self, damage: u32) -> Self {
+        self.stats.damage = damage;
+        self
+    }
+
+    pub fn with_upgrade_price(mut self, upgrade_price: u32) -> Self {
+        self.stats.upgrade_price = upgrade_price;
+        self
+    }
+
+    pub fn with_spent(mut self, spent: u32) -> Self {
+        self.stats.spent = spent;
+        self
+    }
+}
 
 #[derive(Component)]
 pub struct TowerSprite;
-#[derive(Component, Debug, Copy, Clone)]
+#[derive(Component, Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Deserialize)]
 pub enum TowerKind {
+    #[default]
     Basic,
     Support,
     Debuff,
+    Poison,
+    Splash,
+    /// Fires bullets in a straight line that pierce through several enemies.
+    Railgun,
 }
-impl Default for TowerKind {
-    fn default() -> Self {
-        Self::Basic
+impl FromStr for TowerKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Basic" => Ok(Self::Basic),
+            "Support" => Ok(Self::Support),
+            "Debuff" => Ok(Self::Debuff),
+            "Poison" => Ok(Self::Poison),
+            "Splash" => Ok(Self::Splash),
+            "Railgun" => Ok(Self::Railgun),
+            _ => Err(anyhow!("unknown tower kind \"{}\".", s)),
+        }
     }
 }
 #[derive(Component, Default, Debug)]
@@ -89,12 +296,101 @@ pub struct TowerStats {
     pub range: f32,
     pub damage: u32,
     pub upgrade_price: u32,
+    /// The total amount of currency spent on upgrading this tower.
+    pub spent: u32,
+    /// If true, this tower can shoot flying enemies.
+    pub anti_air: bool,
+}
+impl TowerStats {
+    /// Increases the tower's level, applying the stats gained with each upgrade.
+    pub fn upgrade(&mut self, config: &TowerKindConfig) {
+        self.level += 1;
+
+        let upgrade = config
+            .upgrades
+            .get(self.level as usize - 2)
+            .unwrap_or(&config.upgrade);
+
+        self.range += upgrade.range;
+        self.damage += upgrade.damage;
+        self.upgrade_price += upgrade.price;
+    }
+
+    /// The damage dealt by each shot, including bonuses from status effects.
+    pub fn effective_damage(&self, status_effects: &StatusEffects) -> u32 {
+        self.damage
+            .saturating_add(status_effects.get_total_add_damage())
+    }
 }
 #[derive(Component, Default)]
 pub struct TowerState {
     pub timer: Timer,
 }
 
+/// Determines which of the enemies within range a tower will shoot at.
+#[derive(Component, Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TargetingMode {
+    /// The enemy furthest along its path.
+    #[default]
+    First,
+    /// The enemy least far along its path.
+    Last,
+    /// The enemy closest to the tower.
+    Closest,
+    /// The enemy with the most hit points.
+    Strongest,
+    /// The enemy with the fewest hit points.
+    Weakest,
+}
+impl TargetingMode {
+    pub fn next(self) -> Self {
+        match self {
+            Self::First => Self::Last,
+            Self::Last => Self::Closest,
+            Self::Closest => Self::Strongest,
+            Self::Strongest => Self::Weakest,
+            Self::Weakest => Self::First,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::First => "First",
+            Self::Last => "Last",
+            Self::Closest => "Closest",
+            Self::Strongest => "Strongest",
+            Self::Weakest => "Weakest",
+        }
+    }
+}
+
+/// The distance that an enemy still has to walk before reaching the end of its path.
+fn remaining_path_distance(path: &EnemyPath, pos: Vec2) -> f32 {
+    let Some(next) = path.path.get(path.path_index + 1) else {
+        return 0.0;
+    };
+
+    pos.distance(*next)
+        + path.path[path.path_index + 1..]
+            .windows(2)
+            .map(|segment| segment[0].distance(segment[1]))
+            .sum::<f32>()
+}
+
+/// The damage per second that a tower deals to a single enemy, ignoring enemy armor.
+pub fn effective_dps(
+    stats: &TowerStats,
+    state: &TowerState,
+    status_effects: &StatusEffects,
+) -> f32 {
+    let interval = state.timer.duration().as_secs_f32();
+    if interval <= 0.0 {
+        return 0.0;
+    }
+
+    stats.effective_damage(status_effects) as f32 / interval
+}
+
 /// Any tower was changed, added, or removed.
 #[derive(Event)]
 pub struct TowerChangedEvent;
@@ -102,20 +398,14 @@ pub struct TowerChangedEvent;
 #[derive(Component)]
 struct RangeIndicator;
 
-// This currently does not work properly for status effects with timers, but
-// we don't have any of those in game yet.
 fn update_tower_status_effect_appearance(
     mut commands: Commands,
     query: Query<(Entity, &StatusEffects, &Children), (With<TowerKind>, Changed<StatusEffects>)>,
-    up_query: Query<Entity, With<StatusUpSprite>>,
-    down_query: Query<Entity, With<StatusDownSprite>>,
+    sprite_query: Query<(), With<StatusEffectSprite>>,
     tower_sprite_query: Query<&Transform, With<TowerSprite>>,
     texture_handles: Res<TextureHandles>,
 ) {
     for (entity, status_effects, children) in query.iter() {
-        let down = status_effects.get_max_sub_armor() > 0;
-        let up = status_effects.get_total_add_damage() > 0;
-
         let sprite_transform = children
             .iter()
             .filter_map(|child| tower_sprite_query.get(*child).ok())
@@ -123,65 +413,31 @@ fn update_tower_status_effect_appearance(
             .expect("no sprite for tower?");
         let sprite_size = sprite_transform.scale.truncate();
 
-        for child in children.iter() {
-            match (down, down_query.get(*child)) {
-                (true, Err(_)) => {
-                    let down_ent = commands
-                        .spawn((
-                            Sprite {
-                                image: texture_handles.status_down.clone(),
-                                ..default()
-                            },
-                            Transform::from_translation(Vec3::new(
-                                sprite_size.x / 2.0 + 6.0,
-                                -12.0,
-                                layer::HEALTHBAR_BG,
-                            )),
-                            StatusDownSprite,
-                        ))
-                        .id();
-                    commands.entity(entity).add_child(down_ent);
-                }
-                (false, Ok(down_ent)) => {
-                    commands.entity(down_ent).despawn_recursive();
-                }
-                _ => {}
-            }
-            match (up, up_query.get(*child)) {
-                (true, Err(_)) => {
-                    let up_ent = commands
-                        .spawn((
-                            Sprite {
-                                image: texture_handles.status_up.clone(),
-                                ..default()
-                            },
-                            Transform::from_translation(Vec3::new(
-                                sprite_size.x / 2.0 + 6.0,
-                                -12.0,
-                                layer::HEALTHBAR_BG,
-                            )),
-                            StatusUpSprite,
-                        ))
-                        .id();
-                    commands.entity(entity).add_child(up_ent);
-                }
-                (false, Ok(up_ent)) => {
-                    commands.entity(up_ent).despawn_recursive();
-                }
-                _ => {}
-            }
-        }
+        update_status_effect_sprites(
+            &mut commands,
+            entity,
+            Some(status_effects),
+            Some(children),
+            &sprite_query,
+            &texture_handles,
+            Vec2::new(sprite_size.x / 2.0 + 6.0, -12.0),
+        );
     }
 }
 
+fn prebuilt_towers_changed(mut writer: EventWriter<TowerChangedEvent>) {
+    writer.send(TowerChangedEvent);
+}
+
 fn update_tower_status_effects(
-    reader: EventReader<TowerChangedEvent>,
+    mut reader: EventReader<TowerChangedEvent>,
     query: Query<(Entity, &TowerKind, &TowerStats, &Transform)>,
     mut status_query: Query<&mut StatusEffects, With<TowerKind>>,
 ) {
     if reader.is_empty() {
         return;
     }
+    reader.clear();
 
     let support_towers: Vec<_> = query
         .iter()
@@ -220,50 +476,60 @@ fn update_tower_status_effects(
     }
 }
 
+/// The color that a tower kind's sprites and icons are tinted with.
+pub fn tower_tint(kind: TowerKind) -> Color {
+    match kind {
+        TowerKind::Poison => POISON_TINT.into(),
+        TowerKind::Splash => SPLASH_TINT.into(),
+        TowerKind::Railgun => RAILGUN_TINT.into(),
+        _ => Color::WHITE,
+    }
+}
+
+/// How much larger a tower's sprite is drawn for each level above the highest level
+/// that has its own art.
+const EXTRA_LEVEL_SCALE: f32 = 0.15;
+
 fn update_tower_appearance(
-    mut commands: Commands,
-    sprite_query: Query<Entity, With<TowerSprite>>,
-    mut tower_query: Query<(Entity, &TowerStats, &TowerKind, &Children), Changed<TowerStats>>,
+    mut sprite_query: Query<(&mut Sprite, &mut Transform), With<TowerSprite>>,
+    tower_query: Query<(&TowerStats, &TowerKind, &Children), Changed<TowerStats>>,
     texture_handles: Res<TextureHandles>,
     textures: Res<Assets<Image>>,
 ) {
-    for (parent, stats, tower_type, children) in tower_query.iter_mut() {
+    for (stats, tower_type, children) in tower_query.iter() {
         info!("picked up a changed<TowerStats>");
-        for child in children.iter() {
-            if let Ok(ent) = sprite_query.get(*child) {
-                commands.entity(ent).despawn();
-            }
+
+        if stats.level == 0 {
+            continue;
         }
 
-        let texture_handle = match (tower_type, stats.level) {
-            (TowerKind::Basic, 1) => Some(&texture_handles.tower),
-            (TowerKind::Basic, 2) => Some(&texture_handles.tower_two),
-            (TowerKind::Support, 1) => Some(&texture_handles.support_tower),
-            (TowerKind::Support, 2) => Some(&texture_handles.support_tower_two),
-            (TowerKind::Debuff, 1) => Some(&texture_handles.debuff_tower),
-            (TowerKind::Debuff, 2) => Some(&texture_handles.debuff_tower_two),
-            _ => None,
+        let sprites = match tower_type {
+            TowerKind::Basic | TowerKind::Splash | TowerKind::Railgun => &texture_handles.tower,
+            TowerKind::Support => &texture_handles.support_tower,
+            TowerKind::Debuff | TowerKind::Poison => &texture_handles.debuff_tower,
         };
 
-        if let Some(texture_handle) = texture_handle {
-            let texture = textures.get(texture_handle).unwrap();
+        // Levels without their own art reuse the highest level that has some, drawn a
+        // little larger for each level past it.
+        let texture_handle = &sprites[(stats.level as usize).min(sprites.len()) - 1];
+        let extra_levels = (stats.level as usize).saturating_sub(sprites.len());
+        let scale = 1.0 + EXTRA_LEVEL_SCALE * extra_levels as f32;
 
-            let new_child = commands
-                .spawn((
-                    Sprite {
-                        image: texture_handle.clone(),
-                        ..default()
-                    },
-                    Transform::from_translation(Vec3::new(
-                        0.0,
-                        (texture.texture_descriptor.size.height / 2) as f32 - 16.0,
-                        layer::TOWER,
-                    )),
-                    TowerSprite,
-                ))
-                .id();
-
-            commands.entity(parent).add_child(new_child);
+        let texture = textures.get(texture_handle).unwrap();
+
+        for child in children.iter() {
+            let Ok((mut sprite, mut transform)) = sprite_query.get_mut(*child) else {
+                continue;
+            };
+
+            sprite.image = texture_handle.clone();
+            sprite.color = tower_tint(*tower_type);
+            transform.scale = Vec3::new(scale, scale, 1.0);
+            transform.translation = Vec3::new(
+                0.0,
+                (texture.texture_descriptor.size.height / 2) as f32 * scale - 16.0,
+                layer::TOWER,
+            );
         }
     }
 }
@@ -306,6 +572,60 @@ fn update_range_indicator(
     }
 }
 
+/// Above this many living enemies, `shoot_enemies` only considers enemies in nearby
+/// cells of the `EnemyGrid`. Below it, checking every enemy is cheaper than
+/// maintaining the grid.
+const ENEMY_GRID_THRESHOLD: usize = 64;
+const ENEMY_GRID_CELL_SIZE: f32 = 64.0;
+
+/// Living enemies, bucketed by their position into square cells.
+#[derive(Resource, Default)]
+struct EnemyGrid {
+    cells: HashMap<IVec2, Vec<Entity>>,
+    /// False when there are too few enemies for the grid to be worthwhile, in which
+    /// case it is left empty.
+    active: bool,
+}
+impl EnemyGrid {
+    fn cell(pos: Vec2) -> IVec2 {
+        (pos / ENEMY_GRID_CELL_SIZE).floor().as_ivec2()
+    }
+
+    /// Returns the enemies in every cell that overlaps the square around `pos` that
+    /// contains a circle of radius `range`. Some of them may be out of range.
+    fn nearby(&self, pos: Vec2, range: f32) -> impl Iterator<Item = Entity> + '_ {
+        let min = Self::cell(pos - Vec2::splat(range));
+        let max = Self::cell(pos + Vec2::splat(range));
+
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+    }
+}
+
+fn update_enemy_grid(
+    mut grid: ResMut<EnemyGrid>,
+    enemy_query: Query<(Entity, &HitPoints, &Transform), With<EnemyKind>>,
+) {
+    grid.cells.clear();
+
+    let living = || enemy_query.iter().filter(|(_, hp, _)| hp.current > 0);
+
+    grid.active = living().count() > ENEMY_GRID_THRESHOLD;
+    if !grid.active {
+        return;
+    }
+
+    for (enemy, _, transform) in living() {
+        grid.cells
+            .entry(EnemyGrid::cell(transform.translation.truncate()))
+            .or_default()
+            .push(enemy);
+    }
+}
+
 fn shoot_enemies(
     mut commands: Commands,
     mut tower_query: Query<(
@@ -313,13 +633,15 @@ fn shoot_enemies(
         &mut TowerState,
         &TowerStats,
         &TowerKind,
+        &TargetingMode,
         &StatusEffects,
     )>,
-    enemy_query: Query<(Entity, &HitPoints, &Transform), With<EnemyKind>>,
+    enemy_query: Query<(Entity, &HitPoints, &Transform, &EnemyPath, Has<Flying>), With<EnemyKind>>,
+    grid: Res<EnemyGrid>,
     texture_handles: Res<TextureHandles>,
     time: Res<Time>,
 ) {
-    for (transform, mut tower_state, tower_stats, tower_type, status_effects) in
+    for (transform, mut tower_state, tower_stats, tower_type, targeting, status_effects) in
         tower_query.iter_mut()
     {
         if let TowerKind::Support = *tower_type {
@@ -331,64 +653,121 @@ fn shoot_enemies(
             continue;
         }
 
-        // we are just naively iterating over every enemy right now. at some point we should
-        // investigate whether some spatial data structure is useful here. but there is overhead
-        // involved in maintaining one and I think it's unlikely that we'd break even with the
-        // small amount of enemies and towers we're dealing with here.
+        let tower_pos = transform.translation.truncate();
 
-        let mut in_range = enemy_query
-            .iter()
-            .filter(|(_, hp, _)| hp.current > 0)
-            .filter(|(_, _, enemy_transform)| {
-                let dist = enemy_transform
-                    .translation
-                    .truncate()
-                    .distance(transform.translation.truncate());
-
-                dist <= tower_stats.range
-            });
-
-        // right now, possibly coincidentally, this query seems to be iterating in the order that
-        // the enemies were spawned.
-        //
-        // with all enemies current walking at the same speed, that is equivalent to the enemy
-        // furthest along the path, which is the default behavior we probably want.
-        //
-        // other options might be to sort the in-range enemies and select
-        // - closest to tower
-        // - furthest along path
-        // - highest health
-        // - lowest health
-
-        if let Some((enemy, _, _)) = in_range.next() {
+        // Either way, the same enemies end up in range. The grid just lets us skip
+        // most of the distance checks when there are lots of enemies.
+        let candidates: Box<dyn Iterator<Item = _>> = if grid.active {
+            Box::new(
+                grid.nearby(tower_pos, tower_stats.range)
+                    .filter_map(|enemy| enemy_query.get(enemy).ok()),
+            )
+        } else {
+            Box::new(enemy_query.iter())
+        };
+
+        let in_range = candidates
+            .filter(|(_, hp, _, _, _)| hp.current > 0)
+            .filter(|(_, _, _, _, flying)| !flying || tower_stats.anti_air)
+            .map(|(enemy, hp, enemy_transform, path, _)| {
+                let pos = enemy_transform.translation.truncate();
+                (enemy, hp.current, pos.distance(tower_pos), path, pos)
+            })
+            .filter(|(_, _, dist, _, _)| *dist <= tower_stats.range);
+
+        let target = choose_target(in_range, *targeting);
+
+        if let Some((enemy, _, _, _, enemy_pos)) = target {
             let texture = match tower_type {
-                TowerKind::Basic => texture_handles.bullet_shuriken.clone(),
-                TowerKind::Debuff => texture_handles.bullet_debuff.clone(),
+                TowerKind::Basic | TowerKind::Splash | TowerKind::Railgun => {
+                    texture_handles.bullet_shuriken.clone()
+                }
+                TowerKind::Debuff | TowerKind::Poison => texture_handles.bullet_debuff.clone(),
                 _ => panic!(),
             };
 
             let status = match tower_type {
-                TowerKind::Debuff => Some(StatusEffect {
-                    kind: StatusEffectKind::SubArmor(2),
-                    timer: None,
+                TowerKind::Debuff => vec![
+                    StatusEffect {
+                        kind: StatusEffectKind::SubArmor(2),
+                        timer: None,
+                    },
+                    StatusEffect {
+                        kind: StatusEffectKind::Slow(DEBUFF_SLOW),
+                        timer: Some(Timer::from_seconds(DEBUFF_SLOW_SECS, TimerMode::Once)),
+                    },
+                ],
+                TowerKind::Poison => vec![StatusEffect {
+                    kind: StatusEffectKind::Poison { dps: POISON_DPS },
+                    timer: Some(Timer::from_seconds(POISON_SECS, TimerMode::Once)),
+                }],
+                _ => vec![],
+            };
+
+            let splash = match tower_type {
+                TowerKind::Splash => Some(Splash {
+                    radius: SPLASH_RADIUS,
+                    falloff: true,
                 }),
                 _ => None,
             };
 
-            let damage: u32 = tower_stats
-                .damage
-                .saturating_add(status_effects.get_total_add_damage());
+            let damage = tower_stats.effective_damage(status_effects);
 
             // XXX magic sprite offset
             let bullet_pos = transform.translation.truncate() + Vec2::new(0.0, 24.0);
 
-            commands.spawn(Bullet::bundle(
-                bullet_pos, texture, enemy, damage, 100.0, status,
-            ));
+            if let TowerKind::Railgun = *tower_type {
+                commands.spawn((
+                    Bullet::bundle(
+                        bullet_pos,
+                        texture,
+                        enemy,
+                        damage,
+                        RAILGUN_BULLET_SPEED,
+                        status,
+                        None,
+                    ),
+                    Piercing::new(enemy_pos - bullet_pos, RAILGUN_PIERCE, tower_stats.range),
+                ));
+            } else {
+                commands.spawn(Bullet::bundle(
+                    bullet_pos, texture, enemy, damage, 100.0, status, splash,
+                ));
+            }
         }
     }
 }
 
+/// An enemy within a tower's range: its entity, hit points, distance from the tower,
+/// path, and position.
+type Candidate<'a> = (Entity, u32, f32, &'a EnemyPath, Vec2);
+
+/// Returns the enemy that a tower with `targeting` shoots at. Ties go to the lowest
+/// `Entity`, so that the order in which enemies are found doesn't matter.
+fn choose_target<'a>(
+    in_range: impl Iterator<Item = Candidate<'a>>,
+    targeting: TargetingMode,
+) -> Option<Candidate<'a>> {
+    let path_distance = |c: &Candidate| remaining_path_distance(c.3, c.4);
+
+    match targeting {
+        TargetingMode::First => in_range.min_by(|a, b| {
+            path_distance(a)
+                .total_cmp(&path_distance(b))
+                .then(a.0.cmp(&b.0))
+        }),
+        TargetingMode::Last => in_range.max_by(|a, b| {
+            path_distance(a)
+                .total_cmp(&path_distance(b))
+                .then(b.0.cmp(&a.0))
+        }),
+        TargetingMode::Closest => in_range.min_by(|a, b| a.2.total_cmp(&b.2).then(a.0.cmp(&b.0))),
+        TargetingMode::Strongest => in_range.max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0))),
+        TargetingMode::Weakest => in_range.min_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0))),
+    }
+}
+
 fn spawn_range_indicator(mut commands: Commands, texture_handles: ResMut<TextureHandles>) {
     commands.spawn((
         Sprite {
@@ -398,5 +777,167 @@ fn spawn_range_indicator(mut commands: Commands, texture_handles: ResMut<Texture
         Visibility::Hidden,
         Transform::from_translation(Vec3::new(0.0, 0.0, layer::RANGE_INDICATOR)),
         RangeIndicator,
+        CleanupBeforeNewGame,
     ));
 }
+
+fn init_locked_towers(mut commands: Commands, tower_config: Res<TowerConfig>) {
+    commands.insert_resource(LockedTowers(
+        tower_config
+            .0
+            .iter()
+            .filter(|(_, config)| config.unlock.is_some())
+            .map(|(kind, _)| *kind)
+            .collect(),
+    ));
+}
+
+fn update_locked_towers(
+    mut locked: ResMut<LockedTowers>,
+    mut action_panel: ResMut<ActionPanel>,
+    tower_config: Res<TowerConfig>,
+    waves: Res<Waves>,
+    currency: Res<Currency>,
+) {
+    if !waves.is_changed() && !currency.is_changed() {
+        return;
+    }
+
+    let unlocked = locked
+        .0
+        .iter()
+        .filter(|kind| {
+            tower_config
+                .get(**kind)
+                .unlock
+                .is_none_or(|unlock| unlock.is_met(&waves, &currency))
+        })
+        .copied()
+        .collect::<Vec<_>>();
+
+    if unlocked.is_empty() {
+        return;
+    }
+
+    for kind in unlocked {
+        info!("Unlocked tower: {:?}", kind);
+        locked.0.remove(&kind);
+    }
+
+    action_panel.set_changed();
+}
+
+fn init_tower_config(
+    mut commands: Commands,
+    game_data_handles: Res<GameDataHandles>,
+    game_data_assets: Res<Assets<GameData>>,
+) {
+    let game_data = game_data_assets.get(&game_data_handles.game).unwrap();
+
+    commands.insert_resource(game_data.towers.clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn grid_targeting_matches_naive() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let path = EnemyPath {
+            path: vec![Vec2::ZERO, Vec2::new(512.0, 0.0), Vec2::new(512.0, 512.0)],
+            path_index: 0,
+        };
+
+        // Enemies share positions and hit points, so there are plenty of ties.
+        let enemies = (0..200)
+            .map(|i| {
+                let pos = Vec2::new(rng.gen_range(0..16) as f32, rng.gen_range(0..16) as f32);
+                (Entity::from_raw(i), rng.gen_range(1..=4), pos * 32.0)
+            })
+            .collect::<Vec<_>>();
+
+        let mut grid = EnemyGrid {
+            active: true,
+            ..default()
+        };
+        for (enemy, _, pos) in enemies.iter() {
+            grid.cells
+                .entry(EnemyGrid::cell(*pos))
+                .or_default()
+                .push(*enemy);
+        }
+
+        let in_range = |found: Vec<&(Entity, u32, Vec2)>, tower: Vec2, range: f32| {
+            found
+                .into_iter()
+                .map(|(enemy, hp, pos)| (*enemy, *hp, pos.distance(tower), &path, *pos))
+                .filter(|candidate| candidate.2 <= range)
+                .collect::<Vec<_>>()
+        };
+
+        for _ in 0..50 {
+            let tower = Vec2::new(rng.gen_range(0.0..512.0), rng.gen_range(0.0..512.0));
+            let range = rng.gen_range(32.0..192.0);
+
+            let naive = in_range(enemies.iter().rev().collect(), tower, range);
+            let nearby = grid
+                .nearby(tower, range)
+                .map(|enemy| &enemies[enemy.index() as usize])
+                .collect();
+            let nearby = in_range(nearby, tower, range);
+
+            let mut targeting = TargetingMode::First;
+            loop {
+                assert_eq!(
+                    choose_target(naive.iter().copied(), targeting).map(|c| c.0),
+                    choose_target(nearby.iter().copied(), targeting).map(|c| c.0),
+                    "{:?}",
+                    targeting
+                );
+
+                targeting = targeting.next();
+                if targeting == TargetingMode::First {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn upgrades_apply_configured_stats() {
+        let config: TowerKindConfig = ron::de::from_str(
+            "(range: 100.0, upgrade_price: 10, upgrades: [(range: 16.0, damage: 1, price: 5)], \
+             upgrade: (range: 8.0, damage: 2, price: 20))",
+        )
+        .unwrap();
+
+        let mut stats = TowerBundle::new(TowerKind::Basic, &config).stats;
+        stats.upgrade(&config);
+        stats.upgrade(&config);
+
+        assert_eq!(stats.level, 3);
+        assert_eq!(stats.range, 124.0);
+        assert_eq!(stats.damage, 4);
+        assert_eq!(stats.upgrade_price, 35);
+    }
+
+    #[test]
+    fn buffs_increase_effective_dps() {
+        let config = TowerKindConfig {
+            fire_interval: 0.5,
+            ..default()
+        };
+        let tower = TowerBundle::new(TowerKind::Basic, &config).with_damage(2);
+        let buffed = StatusEffects(vec![StatusEffect {
+            kind: StatusEffectKind::AddDamage(1),
+            timer: None,
+        }]);
+
+        assert_eq!(effective_dps(&tower.stats, &tower.state, &default()), 4.0);
+        assert_eq!(effective_dps(&tower.stats, &tower.state, &buffed), 6.0);
+    }
+}
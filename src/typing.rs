@@ -1,14 +1,18 @@
 use bevy::{
+    audio::Volume,
     input::keyboard::{Key, KeyCode, KeyboardInput},
     prelude::*,
     text::{TextReader, TextRoot, TextWriter},
 };
 
-use std::collections::VecDeque;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
 
 use crate::{
-    loading::AudioHandles, ui_color, Action, AudioSettings, FontHandles, TaipoState,
-    FONT_SIZE_INPUT,
+    loading::AudioHandles, ui_color, Action, AudioSettings, CleanupBeforeNewGame, FontHandles,
+    TaipoState, FONT_SIZE_INPUT,
 };
 
 pub struct TypingPlugin;
@@ -20,6 +24,10 @@ impl Plugin for TypingPlugin {
             TimerMode::Repeating,
         )))
         .init_resource::<TypingState>()
+        .init_resource::<TypingSettings>()
+        .init_resource::<TypoStats>()
+        .init_resource::<TypingStats>()
+        .init_resource::<Combo>()
         .init_resource::<TypingTargets>();
 
         app.add_event::<AsciiModeEvent>()
@@ -27,20 +35,32 @@ impl Plugin for TypingPlugin {
             .add_event::<TypingSubmitEvent>();
 
         // We need the font to have been loaded for this to work.
-        app.add_systems(OnEnter(TaipoState::Spawn), startup);
+        app.add_systems(
+            OnEnter(TaipoState::Spawn),
+            (startup, reset_typo_stats, reset_typing_stats, reset_combo),
+        );
         app.add_systems(
             Update,
             (ascii_mode_event, submit_event)
                 .before(keyboard)
                 .run_if(in_state(TaipoState::Playing)),
         );
-        app.add_systems(Update, keyboard.run_if(in_state(TaipoState::Playing)));
+        // Also runs while paused, so that Escape can resume the game.
+        app.add_systems(
+            Update,
+            keyboard.run_if(in_state(TaipoState::Playing).or(in_state(TaipoState::Paused))),
+        );
         app.add_systems(
             Update,
             (
                 update_target_text::<Text>,
                 update_target_text::<Text2d>,
+                update_furigana_text::<Text>,
+                update_furigana_text::<Text2d>,
+                update_number_key_text,
                 update_buffer_text,
+                record_typos,
+                update_typing_stats,
                 audio,
             )
                 .after(keyboard)
@@ -53,21 +73,197 @@ impl Plugin for TypingPlugin {
     }
 }
 
+/// The difficulty of words that aren't tagged with one in their word list.
+pub const DEFAULT_DIFFICULTY: u32 = 1;
+/// The weight of words that aren't tagged with one in their word list.
+pub const DEFAULT_WEIGHT: u32 = 1;
+
 #[derive(Clone, Component, Debug)]
 pub struct TypingTarget {
     pub displayed_chunks: Vec<String>,
     pub typed_chunks: Vec<String>,
+    /// Other accepted ways of typing each chunk in `typed_chunks`, e.g. "si" for "shi".
+    /// May be shorter than `typed_chunks`, in which case the remaining chunks have no
+    /// alternatives.
+    pub alt_typed_chunks: Vec<Vec<String>>,
+    /// The kana reading of each chunk in `displayed_chunks` that was written with one,
+    /// e.g. "かんじ" for "漢字(かんじ)". May be shorter than `displayed_chunks`.
+    pub readings: Vec<Option<String>>,
+    /// An author-defined difficulty, written as `[3]` after a word in a word list.
+    /// Higher is harder, so lists using JLPT levels should tag N5 words `[1]` and N1
+    /// words `[5]`.
+    pub difficulty: u32,
+    /// How often the word comes up compared to other words, written as `*2` after a
+    /// word (and its difficulty) in a word list to make it twice as likely.
+    pub weight: u32,
 }
 impl TypingTarget {
-    pub fn new(word: &str) -> Self {
-        let chunks: Vec<String> = word.split("").map(|s| s.to_string()).collect();
+    /// Returns the reading of the whole target, if any of its chunks have a reading.
+    pub fn furigana(&self) -> Option<String> {
+        if !self.readings.iter().any(Option::is_some) {
+            return None;
+        }
+
+        Some(
+            self.displayed_chunks
+                .iter()
+                .enumerate()
+                .map(|(i, displayed)| {
+                    self.readings
+                        .get(i)
+                        .and_then(Option::as_deref)
+                        .unwrap_or(displayed)
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the accepted ways of typing the chunk at `index`, starting with the
+    /// canonical one.
+    fn chunk_spellings(&self, index: usize) -> impl Iterator<Item = &str> {
+        std::iter::once(self.typed_chunks[index].as_str()).chain(
+            self.alt_typed_chunks
+                .get(index)
+                .into_iter()
+                .flatten()
+                .map(String::as_str),
+        )
+    }
+
+    /// Returns every accepted way of typing this target.
+    pub fn spellings(&self) -> Vec<String> {
+        (0..self.typed_chunks.len()).fold(vec![String::new()], |spellings, index| {
+            spellings
+                .iter()
+                .flat_map(|spelling| {
+                    self.chunk_spellings(index)
+                        .map(move |typed| format!("{}{}", spelling, typed))
+                })
+                .collect()
+        })
+    }
+
+    /// Returns how much of `buf` matches this target, using whichever spelling of
+    /// each chunk matches the most.
+    pub fn match_buffer(&self, buf: &str) -> BufferMatch {
+        self.match_buffer_from(buf, 0)
+    }
+
+    fn match_buffer_from(&self, buf: &str, index: usize) -> BufferMatch {
+        let mut best = BufferMatch::default();
 
-        Self {
-            displayed_chunks: chunks.clone(),
-            typed_chunks: chunks,
+        if index >= self.typed_chunks.len() {
+            return best;
         }
+
+        for spelling in self.chunk_spellings(index) {
+            let candidate = match buf.strip_prefix(spelling) {
+                Some(rest) => {
+                    let next = self.match_buffer_from(rest, index + 1);
+                    BufferMatch {
+                        chunks: next.chunks + 1,
+                        chunks_len: next.chunks_len + spelling.len(),
+                        len: next.len + spelling.len(),
+                    }
+                }
+                None => BufferMatch {
+                    len: matching_prefix_len(buf, spelling),
+                    ..default()
+                },
+            };
+
+            if (candidate.len, candidate.chunks) > (best.len, best.chunks) {
+                best = candidate;
+            }
+        }
+
+        best
+    }
+
+    /// Returns true if `text` is an accepted way of typing this target.
+    pub fn matches(&self, text: &str) -> bool {
+        let buffer_match = self.match_buffer(text);
+        buffer_match.chunks == self.typed_chunks.len() && buffer_match.len == text.len()
+    }
+
+    /// Returns true if any way of typing this target is also a way of typing `other`.
+    pub fn is_ambiguous_with(&self, other: &TypingTarget) -> bool {
+        let mut spellings = SpellingSet::default();
+        spellings.insert(self);
+        spellings.is_ambiguous_with(other)
     }
 }
+
+/// Every way of typing some targets, and every prefix of those, so that other targets
+/// can be checked against all of them at once without listing their own spellings.
+#[derive(Clone, Default)]
+struct SpellingSet {
+    spellings: HashMap<String, usize>,
+    prefixes: HashMap<String, usize>,
+}
+
+impl SpellingSet {
+    fn insert(&mut self, target: &TypingTarget) {
+        for spelling in target.spellings() {
+            for end in (1..=spelling.len()).filter(|end| spelling.is_char_boundary(*end)) {
+                *self
+                    .prefixes
+                    .entry(spelling[..end].to_string())
+                    .or_default() += 1;
+            }
+
+            *self.spellings.entry(spelling).or_default() += 1;
+        }
+    }
+
+    fn remove(&mut self, target: &TypingTarget) {
+        fn decrement(counts: &mut HashMap<String, usize>, key: &str) {
+            if let Some(count) = counts.get_mut(key) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.remove(key);
+                }
+            }
+        }
+
+        for spelling in target.spellings() {
+            for end in (1..=spelling.len()).filter(|end| spelling.is_char_boundary(*end)) {
+                decrement(&mut self.prefixes, &spelling[..end]);
+            }
+
+            decrement(&mut self.spellings, &spelling);
+        }
+    }
+
+    /// Returns true if any way of typing `target` is in this set.
+    fn is_ambiguous_with(&self, target: &TypingTarget) -> bool {
+        self.is_ambiguous_from(target, 0, "")
+    }
+
+    /// Tries each spelling of the chunk at `index` after `typed`, giving up on any
+    /// that no spelling in this set begins with.
+    fn is_ambiguous_from(&self, target: &TypingTarget, index: usize, typed: &str) -> bool {
+        if index == target.typed_chunks.len() {
+            return self.spellings.contains_key(typed);
+        }
+
+        target.chunk_spellings(index).any(|chunk| {
+            let typed = format!("{}{}", typed, chunk);
+            self.prefixes.contains_key(&typed) && self.is_ambiguous_from(target, index + 1, &typed)
+        })
+    }
+}
+/// The result of matching a typing buffer against a `TypingTarget`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BufferMatch {
+    /// The number of chunks that were completely typed.
+    pub chunks: usize,
+    /// The length in bytes of the part of the buffer that completely typed those chunks.
+    pub chunks_len: usize,
+    /// The length in bytes of the longest prefix of the buffer that matches, including
+    /// a partially typed chunk.
+    pub len: usize,
+}
 #[derive(Component, Default)]
 pub struct TypingTargetSettings {
     /// If true, do not replace the `TypingTarget` with another from the word list after it is typed.
@@ -83,6 +279,19 @@ pub struct TypingTargetBundle {
 }
 #[derive(Component)]
 pub struct TypingTargetText;
+/// Small text displaying the reading of a typing target's word above it, when
+/// `TypingSettings::show_furigana` is enabled. Must be a child of the `TypingTarget`.
+#[derive(Component)]
+pub struct FuriganaText;
+
+/// A typing target that can also be finished by pressing a digit key from 1 to 9,
+/// when `TypingSettings::number_keys` is enabled.
+#[derive(Component)]
+pub struct NumberKey(pub u32);
+/// Text displaying a typing target's `NumberKey`, which is only visible when
+/// `TypingSettings::number_keys` is enabled. Must be a child of the `TypingTarget`.
+#[derive(Component)]
+pub struct NumberKeyText;
 
 #[derive(Component)]
 struct TypingBuffer;
@@ -114,48 +323,213 @@ pub struct TypingState {
     just_typed_char: bool,
 }
 
+#[derive(Resource, Default)]
+pub struct TypingSettings {
+    /// If true, highlight the chunk of a target that has been partially typed, e.g.
+    /// "し" after typing "sh".
+    pub show_partial_chunk: bool,
+    /// If true, a tower's label is replaced with a longer word each time the tower is
+    /// upgraded.
+    pub longer_upgraded_targets: bool,
+    /// If true, show the kana reading above words that were written with one.
+    pub show_furigana: bool,
+    /// If true, pressing a digit key finishes the typing target with that `NumberKey`
+    /// instead of typing the digit.
+    pub number_keys: bool,
+}
+
+/// Counts of mistyped chunks during the current game, keyed by the typed chunk.
+#[derive(Resource, Default)]
+pub struct TypoStats {
+    pub counts: HashMap<String, TypoCount>,
+}
+impl TypoStats {
+    /// Returns up to `n` of the most frequently mistyped chunks, most frequent first.
+    pub fn worst(&self, n: usize) -> Vec<&TypoCount> {
+        let mut counts = self.counts.values().collect::<Vec<_>>();
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then(a.typed.cmp(&b.typed)));
+        counts.truncate(n);
+        counts
+    }
+}
+#[derive(Default)]
+pub struct TypoCount {
+    pub typed: String,
+    pub displayed: String,
+    pub count: u32,
+}
+
+/// Typing speed and accuracy during the current game.
+#[derive(Resource, Default)]
+pub struct TypingStats {
+    /// Characters in submitted text that completed a typing target.
+    pub correct_chars: u32,
+    pub keystrokes: u32,
+    /// Keystrokes that left the buffer not matching any typing target.
+    pub mistakes: u32,
+    /// Seconds spent playing.
+    pub elapsed: f32,
+}
+impl TypingStats {
+    /// Words per minute, counting every five correct characters as a word.
+    pub fn wpm(&self) -> f32 {
+        if self.elapsed <= 0.0 {
+            return 0.0;
+        }
+
+        self.correct_chars as f32 / 5.0 / (self.elapsed / 60.0)
+    }
+
+    /// The fraction of keystrokes that were correct, from 0 to 1.
+    pub fn accuracy(&self) -> f32 {
+        if self.keystrokes == 0 {
+            return 1.0;
+        }
+
+        self.keystrokes.saturating_sub(self.mistakes) as f32 / self.keystrokes as f32
+    }
+}
+
+/// Typed words per extra coin earned while on a combo.
+const COMBO_STEP: u32 = 5;
+/// The most extra coins that a combo can earn at once.
+const MAX_COMBO_BONUS: u32 = 3;
+
+/// The number of words typed in a row without a mistake.
+#[derive(Resource, Default)]
+pub struct Combo(pub u32);
+impl Combo {
+    /// Extra currency to award on top of any reward.
+    pub fn bonus(&self) -> u32 {
+        (self.0 / COMBO_STEP).min(MAX_COMBO_BONUS)
+    }
+}
+
 #[derive(Resource, Default)]
 pub struct TypingTargets {
     pub possible: VecDeque<TypingTarget>,
-    used_ascii: Vec<Vec<String>>,
+    used: Vec<TypingTarget>,
+    used_spellings: SpellingSet,
 }
 
 impl TypingTargets {
+    /// Returns the position of the first possible target that is not ambiguous
+    /// with another target that was previously removed from the stack.
+    fn next_position(&self) -> Option<usize> {
+        self.next_position_where(|_| true)
+    }
+
+    /// Like `next_position`, but only considers targets for which `f` returns true.
+    fn next_position_where(&self, f: impl Fn(&TypingTarget) -> bool) -> Option<usize> {
+        self.possible
+            .iter()
+            .position(|v| f(v) && !self.used_spellings.is_ambiguous_with(v))
+    }
+
+    fn use_target(&mut self, target: TypingTarget) {
+        self.used_spellings.insert(&target);
+        self.used.push(target);
+    }
+
+    /// Prevents any target that is ambiguous with `target` from being returned,
+    /// for targets that are always present, like those with `fixed` set.
+    pub fn reserve(&mut self, target: &TypingTarget) {
+        self.use_target(target.clone());
+    }
+
+    /// Returns the number of targets, up to `max`, that could be taken by calling
+    /// `pop_front` repeatedly. Targets that are ambiguous with a target taken before
+    /// them can't be used, so this may be fewer than the number of possible targets.
+    pub fn available(&self, max: usize) -> usize {
+        let mut used_spellings = self.used_spellings.clone();
+        let mut available = 0;
+
+        for target in self.possible.iter() {
+            if available == max {
+                break;
+            }
+
+            if !used_spellings.is_ambiguous_with(target) {
+                used_spellings.insert(target);
+                available += 1;
+            }
+        }
+
+        available
+    }
+
+    /// Returns the `TypingTarget` that the next call to `pop_front` would return,
+    /// without removing it.
+    #[allow(dead_code)]
+    pub fn peek_front(&self) -> Option<&TypingTarget> {
+        self.next_position().and_then(|pos| self.possible.get(pos))
+    }
+
     /// Returns the next `TypingTarget`, removing it from the list of possible
     /// targets and ensuring that it is not ambiguous with another target that
     /// was previous removed from the stack.
-    pub fn pop_front(&mut self) -> TypingTarget {
-        let next_pos = self
-            .possible
-            .iter()
-            .position(|v| {
-                !self
-                    .used_ascii
-                    .iter()
-                    .any(|ascii| *ascii.join("") == v.typed_chunks.join(""))
-            })
-            .expect("no word found");
+    ///
+    /// Returns `None` if every possible target is ambiguous with one in use.
+    pub fn pop_front(&mut self) -> Option<TypingTarget> {
+        let next_pos = self.next_position()?;
 
         let next = self.possible.remove(next_pos).unwrap();
 
-        self.used_ascii.push(next.typed_chunks.clone());
+        self.use_target(next.clone());
 
-        next
+        Some(next)
+    }
+
+    /// Puts a `TypingTarget` that is no longer in use back into the list of possible
+    /// targets.
+    ///
+    /// Targets with a higher weight are put that many times closer to the front, so
+    /// that they come around again sooner.
+    pub fn push_back(&mut self, target: TypingTarget) {
+        for used in self.used.iter() {
+            if used.typed_chunks == target.typed_chunks {
+                self.used_spellings.remove(used);
+            }
+        }
+        self.used
+            .retain(|used| used.typed_chunks != target.typed_chunks);
+
+        let index = self.possible.len() / target.weight.max(1) as usize;
+        self.possible.insert(index, target);
+    }
+
+    /// Replaces `target` with the next target that has more typed characters than it,
+    /// if there is one.
+    pub fn replace_with_longer(&mut self, target: &TypingTarget) -> Option<TypingTarget> {
+        let len = target.typed_chunks.concat().len();
+
+        let next_pos =
+            self.next_position_where(|candidate| candidate.typed_chunks.concat().len() > len)?;
+
+        let next = self.possible.remove(next_pos).unwrap();
+        self.use_target(next.clone());
+
+        self.push_back(target.clone());
+
+        Some(next)
     }
 
     /// Puts a `TypingTarget` back into the list of possible targets and returns
     /// the next target, ensuring that it is not ambiguous with another target
     /// that was previously removed from the stack or the target that was put
     /// back.
+    ///
+    /// If there is no such target, `target` stays in use and is returned again.
     pub fn push_back_pop_front(&mut self, target: TypingTarget) -> TypingTarget {
-        self.possible.push_back(target.clone());
+        let Some(next) = self.pop_front() else {
+            warn!(
+                "No unambiguous words left, reusing \"{}\"",
+                target.typed_chunks.join("")
+            );
+            return target;
+        };
 
-        let next = self.pop_front();
-
-        if next.typed_chunks != target.typed_chunks {
-            self.used_ascii
-                .retain(|ascii| *ascii != target.typed_chunks);
-        }
+        self.push_back(target);
 
         next
     }
@@ -169,24 +543,32 @@ fn submit_event(
     text_query: Query<(), With<TypingTargetText>>,
     typing_state: Res<TypingState>,
     mut typing_targets: ResMut<TypingTargets>,
+    mut typing_stats: ResMut<TypingStats>,
+    mut combo: ResMut<Combo>,
     mut text_set: ParamSet<(TextUiWriter, Text2dWriter)>,
 ) {
     for event in typing_submit_events.read() {
+        let mut completed = false;
+
         for (entity, mut target, settings) in query.iter_mut() {
             if settings.disabled {
                 continue;
             }
 
-            if target.typed_chunks.join("") != event.text {
+            if !target.matches(&event.text) {
                 continue;
             }
 
+            completed = true;
+
             typing_target_finished_events.send(TypingTargetFinishedEvent { entity });
 
             if settings.fixed {
                 continue;
             }
 
+            combo.0 += 1;
+
             let new_target = typing_targets.push_back_pop_front(target.clone());
 
             if let Ok(children) = children_query.get(entity) {
@@ -212,6 +594,13 @@ fn submit_event(
             target
                 .displayed_chunks
                 .clone_from(&new_target.displayed_chunks);
+            target.readings.clone_from(&new_target.readings);
+        }
+
+        if completed {
+            typing_stats.correct_chars += event.text.chars().count() as u32;
+        } else {
+            combo.0 = 0;
         }
     }
 }
@@ -242,6 +631,7 @@ fn startup(mut commands: Commands, font_handles: Res<FontHandles>) {
                 ..default()
             },
             BackgroundColor(ui_color::TRANSPARENT_BACKGROUND.into()),
+            CleanupBeforeNewGame,
         ))
         .with_children(|parent| {
             parent.spawn((
@@ -261,16 +651,26 @@ fn startup(mut commands: Commands, font_handles: Res<FontHandles>) {
                     ..default()
                 },
             ));
-            parent.spawn((
-                Text::default(),
-                TextFont {
-                    font: font_handles.jptext.clone(),
-                    font_size: FONT_SIZE_INPUT,
-                    ..default()
-                },
-                TextColor(ui_color::NORMAL_TEXT.into()),
-                TypingBuffer,
-            ));
+            parent
+                .spawn((
+                    Text::default(),
+                    TextFont {
+                        font: font_handles.jptext.clone(),
+                        font_size: FONT_SIZE_INPUT,
+                        ..default()
+                    },
+                    TextColor(ui_color::NORMAL_TEXT.into()),
+                    TypingBuffer,
+                ))
+                .with_child((
+                    TextSpan::default(),
+                    TextFont {
+                        font: font_handles.jptext.clone(),
+                        font_size: FONT_SIZE_INPUT,
+                        ..default()
+                    },
+                    TextColor(ui_color::BAD_TEXT.into()),
+                ));
             parent.spawn((
                 Text::new("_"),
                 TextFont {
@@ -284,21 +684,36 @@ fn startup(mut commands: Commands, font_handles: Res<FontHandles>) {
         });
 }
 
+/// The frequency of the progress tone played after the first correct character.
+const PROGRESS_TONE_BASE_FREQUENCY: f32 = 440.0;
+const PROGRESS_TONE_DURATION: Duration = Duration::from_millis(40);
+const PROGRESS_TONE_VOLUME: f32 = 0.1;
+const CORRECT_TICK_FREQUENCY: f32 = 2000.0;
+const CORRECT_TICK_DURATION: Duration = Duration::from_millis(8);
+const CORRECT_TICK_VOLUME: f32 = 0.05;
+
 fn audio(
     mut commands: Commands,
     state: Res<TypingState>,
     query: Query<(&TypingTarget, &TypingTargetSettings)>,
     audio_handles: Res<AudioHandles>,
     audio_settings: Res<AudioSettings>,
+    mut pitch_assets: ResMut<Assets<Pitch>>,
+    mut combo: ResMut<Combo>,
+    mut last_longest: Local<usize>,
 ) {
     if !state.is_changed() {
         return;
     }
 
     let mut longest: usize = 0;
+    // The fraction of chunks typed in the most complete matching target.
+    let mut progress: f32 = 0.0;
 
     for (target, _) in query.iter().filter(|(_t, s)| !s.disabled) {
-        let matched_length = if target.typed_chunks.join("").starts_with(&state.buf) {
+        let buffer_match = target.match_buffer(&state.buf);
+
+        let matched_length = if buffer_match.len == state.buf.len() {
             state.buf.len()
         } else {
             0
@@ -307,52 +722,101 @@ fn audio(
         if matched_length > longest {
             longest = matched_length;
         }
+
+        if matched_length > 0 && !target.typed_chunks.is_empty() {
+            progress = progress.max(buffer_match.chunks as f32 / target.typed_chunks.len() as f32);
+        }
     }
 
-    if !audio_settings.mute && state.just_typed_char && longest < state.buf.len() {
+    if state.just_typed_char
+        && longest > 0
+        && longest == state.buf.len()
+        && progress < 1.0
+        && audio_settings.progress_tones
+        && !audio_settings.mute
+    {
+        // Rise by up to an octave as the target nears completion.
+        let frequency = PROGRESS_TONE_BASE_FREQUENCY * 2.0_f32.powf(progress);
+
         commands.spawn((
-            AudioPlayer(audio_handles.wrong_character.clone()),
-            PlaybackSettings::DESPAWN,
+            AudioPlayer(pitch_assets.add(Pitch::new(frequency, PROGRESS_TONE_DURATION))),
+            PlaybackSettings::DESPAWN.with_volume(Volume::new(
+                PROGRESS_TONE_VOLUME * audio_settings.sfx_volume(),
+            )),
         ));
     }
+
+    // Backspace and Enter aren't typed characters, and a wrong character doesn't
+    // lengthen the match, so only correct characters tick.
+    if state.just_typed_char
+        && longest > *last_longest
+        && audio_settings.correct_ticks
+        && !audio_settings.mute
+    {
+        commands.spawn((
+            AudioPlayer(
+                pitch_assets.add(Pitch::new(CORRECT_TICK_FREQUENCY, CORRECT_TICK_DURATION)),
+            ),
+            PlaybackSettings::DESPAWN.with_volume(Volume::new(
+                CORRECT_TICK_VOLUME * audio_settings.sfx_volume(),
+            )),
+        ));
+    }
+
+    *last_longest = longest;
+
+    if state.just_typed_char && longest < state.buf.len() {
+        combo.0 = 0;
+
+        if !audio_settings.mute {
+            commands.spawn((
+                AudioPlayer(audio_handles.wrong_character.clone()),
+                PlaybackSettings::DESPAWN.with_volume(Volume::new(audio_settings.sfx_volume())),
+            ));
+        }
+    }
 }
 
 fn update_target_text<R: TextRoot>(
     state: Res<TypingState>,
+    settings: Res<TypingSettings>,
     text_query: Query<(), (With<R>, With<TypingTargetText>)>,
     query: Query<(&TypingTarget, &TypingTargetSettings, &Children)>,
+    changed_query: Query<(), Changed<TypingTarget>>,
     mut text_set: ParamSet<(TextReader<R>, TextWriter<R>)>,
 ) {
-    if !state.is_changed() {
+    if !state.is_changed() && !settings.is_changed() && changed_query.is_empty() {
         return;
     }
 
-    for (target, settings, target_children) in query.iter() {
-        if settings.disabled {
+    for (target, target_settings, target_children) in query.iter() {
+        if target_settings.disabled {
             continue;
         }
 
         let mut matched = "".to_string();
+        let mut partial = "".to_string();
         let mut unmatched = "".to_string();
-        let mut buf = state.buf.clone();
-        let mut fail = false;
 
-        let render_iter = if state.ascii_mode {
-            target.typed_chunks.iter()
+        let render_chunks = if state.ascii_mode {
+            &target.typed_chunks
         } else {
-            target.displayed_chunks.iter()
+            &target.displayed_chunks
         };
 
-        for (ascii, render) in target.typed_chunks.iter().zip(render_iter) {
-            match (fail, buf.strip_prefix(ascii)) {
-                (false, Some(leftover)) => {
-                    matched.push_str(render);
-                    buf.clone_from(&leftover.to_string());
-                }
-                (true, _) | (_, None) => {
-                    fail = true;
-                    unmatched.push_str(render);
-                }
+        let buffer_match = target.match_buffer(&state.buf);
+        let partial_index = (settings.show_partial_chunk
+            && buffer_match.len == state.buf.len()
+            && buffer_match.len > buffer_match.chunks_len)
+            .then_some(buffer_match.chunks);
+
+        for (i, render) in render_chunks.iter().enumerate() {
+            if i < buffer_match.chunks {
+                matched.push_str(render);
+            } else if Some(i) == partial_index {
+                partial.push_str(render);
+            } else {
+                unmatched.push_str(render);
             }
         }
 
@@ -360,33 +824,209 @@ fn update_target_text<R: TextRoot>(
             if text_query.get(*child).is_ok() {
                 let changed = {
                     let mut reader = text_set.p0();
-                    reader.text(*child, 0) != matched || reader.text(*child, 1) != unmatched
+                    reader.text(*child, 0) != matched
+                        || reader.text(*child, 1) != partial
+                        || reader.text(*child, 2) != unmatched
                 };
 
                 if changed {
                     let mut writer = text_set.p1();
                     writer.text(*child, 0).clone_from(&matched);
-                    writer.text(*child, 1).clone_from(&unmatched);
+                    writer.text(*child, 1).clone_from(&partial);
+                    writer.text(*child, 2).clone_from(&unmatched);
                 }
             }
         }
     }
 }
 
-fn update_buffer_text(state: Res<TypingState>, mut query: Query<&mut Text, With<TypingBuffer>>) {
+fn update_furigana_text<R: TextRoot>(
+    state: Res<TypingState>,
+    settings: Res<TypingSettings>,
+    query: Query<(&TypingTarget, &Children)>,
+    changed_query: Query<(), Changed<TypingTarget>>,
+    mut furigana_query: Query<&mut Visibility, (With<R>, With<FuriganaText>)>,
+    mut writer: TextWriter<R>,
+) {
+    if !state.is_changed() && !settings.is_changed() && changed_query.is_empty() {
+        return;
+    }
+
+    for (target, children) in query.iter() {
+        // Romaji don't need a reading.
+        let furigana = target
+            .furigana()
+            .filter(|_| settings.show_furigana && !state.ascii_mode);
+
+        for child in children.iter() {
+            let Ok(mut visibility) = furigana_query.get_mut(*child) else {
+                continue;
+            };
+
+            match &furigana {
+                Some(furigana) => {
+                    writer.text(*child, 0).clone_from(furigana);
+                    *visibility = Visibility::Inherited;
+                }
+                None => {
+                    *visibility = Visibility::Hidden;
+                }
+            }
+        }
+    }
+}
+
+fn update_number_key_text(
+    settings: Res<TypingSettings>,
+    query: Query<&Children, With<NumberKey>>,
+    mut number_key_query: Query<&mut Visibility, With<NumberKeyText>>,
+    added_query: Query<(), Added<NumberKeyText>>,
+) {
+    if !settings.is_changed() && added_query.is_empty() {
+        return;
+    }
+
+    for children in query.iter() {
+        for child in children.iter() {
+            if let Ok(mut visibility) = number_key_query.get_mut(*child) {
+                *visibility = if settings.number_keys {
+                    Visibility::Inherited
+                } else {
+                    Visibility::Hidden
+                };
+            }
+        }
+    }
+}
+
+/// Returns the length in bytes of the longest prefix of `buf` that matches `typed`.
+fn matching_prefix_len(buf: &str, typed: &str) -> usize {
+    buf.char_indices()
+        .zip(typed.chars())
+        .take_while(|((_, a), b)| a == b)
+        .last()
+        .map(|((i, a), _)| i + a.len_utf8())
+        .unwrap_or(0)
+}
+
+fn reset_typo_stats(mut stats: ResMut<TypoStats>) {
+    stats.counts.clear();
+}
+
+fn reset_combo(mut combo: ResMut<Combo>) {
+    combo.0 = 0;
+}
+
+fn reset_typing_stats(mut stats: ResMut<TypingStats>) {
+    *stats = TypingStats::default();
+}
+
+fn update_typing_stats(
+    state: Res<TypingState>,
+    mut stats: ResMut<TypingStats>,
+    query: Query<(&TypingTarget, &TypingTargetSettings)>,
+    // Typing speed shouldn't depend on the game speed or slow motion.
+    time: Res<Time<Real>>,
+) {
+    stats.elapsed += time.delta_secs();
+
+    if !state.is_changed() || !state.just_typed_char {
+        return;
+    }
+
+    stats.keystrokes += 1;
+
+    // Like the wrong character sound in `audio`
+    let matched = query
+        .iter()
+        .filter(|(_, settings)| !settings.disabled)
+        .any(|(target, _)| target.match_buffer(&state.buf).len == state.buf.len());
+
+    if !matched {
+        stats.mistakes += 1;
+    }
+}
+
+/// Records the chunk that the player was trying to type when they typed a wrong
+/// character after an otherwise valid buffer.
+fn record_typos(
+    state: Res<TypingState>,
+    mut stats: ResMut<TypoStats>,
+    query: Query<(&TypingTarget, &TypingTargetSettings)>,
+) {
+    if !state.is_changed() || !state.just_typed_char {
+        return;
+    }
+
+    let Some(last_char) = state.buf.chars().last() else {
+        return;
+    };
+    let prev_len = state.buf.len() - last_char.len_utf8();
+
+    let mut best: Option<(BufferMatch, &TypingTarget)> = None;
+    for (target, _) in query.iter().filter(|(_, s)| !s.disabled) {
+        let buffer_match = target.match_buffer(&state.buf);
+        if best.is_none_or(|(best_match, _)| buffer_match.len > best_match.len) {
+            best = Some((buffer_match, target));
+        }
+    }
+
+    // Only count the first wrong character, and not every character typed after it
+    let Some((buffer_match, target)) = best else {
+        return;
+    };
+    if buffer_match.len != prev_len {
+        return;
+    }
+
+    let (Some(typed), Some(displayed)) = (
+        target.typed_chunks.get(buffer_match.chunks),
+        target.displayed_chunks.get(buffer_match.chunks),
+    ) else {
+        return;
+    };
+
+    let entry = stats
+        .counts
+        .entry(typed.clone())
+        .or_insert_with(|| TypoCount {
+            typed: typed.clone(),
+            displayed: displayed.clone(),
+            count: 0,
+        });
+    entry.count += 1;
+}
+
+fn update_buffer_text(
+    state: Res<TypingState>,
+    target_query: Query<(&TypingTarget, &TypingTargetSettings)>,
+    buffer_query: Query<Entity, With<TypingBuffer>>,
+    mut writer: TextUiWriter,
+) {
     if !state.is_changed() {
         return;
     }
 
-    for mut target in query.iter_mut() {
-        target.0.clone_from(&state.buf);
+    // Show the part of the buffer that can't match any target in a different color
+    let valid_len = target_query
+        .iter()
+        .filter(|(_, settings)| !settings.disabled)
+        .map(|(target, _)| target.match_buffer(&state.buf).len)
+        .max()
+        .unwrap_or(0);
+
+    let (valid, invalid) = state.buf.split_at(valid_len);
+
+    for entity in buffer_query.iter() {
+        *writer.text(entity, 0) = valid.to_string();
+        *writer.text(entity, 1) = invalid.to_string();
     }
 }
 
 fn update_cursor_text(
     mut timer: ResMut<TypingCursorTimer>,
     mut query: Query<&mut TextColor, With<TypingCursor>>,
-    time: Res<Time>,
+    time: Res<Time<Real>>,
 ) {
     if !timer.0.tick(time.delta()).just_finished() {
         return;
@@ -401,13 +1041,54 @@ fn update_cursor_text(
     }
 }
 
+/// Returns the digit, from 1 to 9, that `key_code` types.
+fn number_key(key_code: KeyCode) -> Option<u32> {
+    match key_code {
+        KeyCode::Digit1 | KeyCode::Numpad1 => Some(1),
+        KeyCode::Digit2 | KeyCode::Numpad2 => Some(2),
+        KeyCode::Digit3 | KeyCode::Numpad3 => Some(3),
+        KeyCode::Digit4 | KeyCode::Numpad4 => Some(4),
+        KeyCode::Digit5 | KeyCode::Numpad5 => Some(5),
+        KeyCode::Digit6 | KeyCode::Numpad6 => Some(6),
+        KeyCode::Digit7 | KeyCode::Numpad7 => Some(7),
+        KeyCode::Digit8 | KeyCode::Numpad8 => Some(8),
+        KeyCode::Digit9 | KeyCode::Numpad9 => Some(9),
+        _ => None,
+    }
+}
+
 fn keyboard(
     mut typing_state: ResMut<TypingState>,
     mut typing_submit_events: EventWriter<TypingSubmitEvent>,
+    mut typing_target_finished_events: EventWriter<TypingTargetFinishedEvent>,
     mut keyboard_input_events: EventReader<KeyboardInput>,
+    state: Res<State<TaipoState>>,
+    mut next_state: ResMut<NextState<TaipoState>>,
+    settings: Res<TypingSettings>,
+    number_key_query: Query<(Entity, &NumberKey)>,
 ) {
     for ev in keyboard_input_events.read() {
+        // Keys pressed while paused are read here rather than being typed once the
+        // game resumes.
+        if *state.get() == TaipoState::Paused {
+            if ev.state.is_pressed() && ev.key_code == KeyCode::Escape {
+                next_state.set(TaipoState::Playing);
+            }
+
+            continue;
+        }
+
         if ev.state.is_pressed() {
+            if let Some(number) = number_key(ev.key_code).filter(|_| settings.number_keys) {
+                typing_state.just_typed_char = false;
+
+                if let Some((entity, _)) = number_key_query.iter().find(|(_, n)| n.0 == number) {
+                    typing_target_finished_events.send(TypingTargetFinishedEvent { entity });
+                }
+
+                continue;
+            }
+
             if let Key::Character(ref s) = ev.logical_key {
                 typing_state.buf.push_str(s.as_str());
                 typing_state.just_typed_char = true;
@@ -425,8 +1106,13 @@ fn keyboard(
                 KeyCode::Backspace => {
                     typing_state.buf.pop();
                 }
+                // Escape clears the buffer, or pauses the game if there is nothing to clear.
                 KeyCode::Escape => {
-                    typing_state.buf.clear();
+                    if typing_state.buf.is_empty() {
+                        next_state.set(TaipoState::Paused);
+                    } else {
+                        typing_state.buf.clear();
+                    }
                 }
                 _ => {}
             }
@@ -439,6 +1125,80 @@ fn reset_target_text<R: TextRoot>(mut writer: TextWriter<R>, entity: Entity, val
         section_0.clear();
     }
     if let Some(mut section_1) = writer.get_text(entity, 1) {
-        section_1.clone_from(val);
+        section_1.clear();
+    }
+    if let Some(mut section_2) = writer.get_text(entity, 2) {
+        section_2.clone_from(val);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::parse_plain, japanese_parser};
+
+    #[test]
+    fn ambiguous_targets_are_not_reused() {
+        let mut targets = TypingTargets {
+            possible: japanese_parser::parse("し\nシ").unwrap().into(),
+            ..default()
+        };
+
+        let first = targets.pop_front().unwrap();
+        assert_eq!(first.displayed_chunks, vec!["し"]);
+        assert!(targets.pop_front().is_none());
+
+        let again = targets.push_back_pop_front(first);
+        assert_eq!(again.displayed_chunks, vec!["し"]);
+        assert_eq!(targets.possible.len(), 1);
+    }
+
+    #[test]
+    fn reserved_targets_are_not_handed_out() {
+        let mut targets = TypingTargets {
+            possible: parse_plain("help\nmute\nhelp\nundo").unwrap().into(),
+            ..default()
+        };
+        let help = parse_plain("help").unwrap().remove(0);
+        targets.reserve(&help);
+
+        let mut popped = vec![];
+        while let Some(target) = targets.pop_front() {
+            popped.push(target.typed_chunks.join(""));
+        }
+        assert_eq!(popped, vec!["mute", "undo"]);
+    }
+
+    #[test]
+    fn drained_targets_are_reused_without_panicking() {
+        let mut targets = TypingTargets {
+            possible: parse_plain("a\nb").unwrap().into(),
+            ..default()
+        };
+
+        let first = targets.pop_front().unwrap();
+        targets.pop_front().unwrap();
+        assert!(targets.pop_front().is_none());
+        assert!(targets.possible.is_empty());
+
+        let again = targets.push_back_pop_front(first);
+        assert_eq!(again.typed_chunks, vec!["a"]);
+        assert!(targets.possible.is_empty());
+    }
+
+    #[test]
+    fn peek_front_agrees_with_pop_front() {
+        let mut targets = TypingTargets {
+            possible: japanese_parser::parse("し\nシ\nか").unwrap().into(),
+            ..default()
+        };
+
+        while let Some(peeked) = targets.peek_front().cloned() {
+            let popped = targets.pop_front().unwrap();
+            assert_eq!(peeked.displayed_chunks, popped.displayed_chunks);
+        }
+
+        assert!(targets.pop_front().is_none());
+        assert_eq!(targets.possible.len(), 1);
     }
 }
@@ -1,28 +1,67 @@
 use bevy::{prelude::*, utils::HashMap};
 
 use anyhow::anyhow;
+use rand::{seq::SliceRandom, Rng};
 use tiled::Object;
 
 use crate::{
     atlas_loader::AtlasImage,
-    enemy::{EnemyBundle, EnemyKind, EnemyPath},
+    boss::Boss,
+    enemy::{
+        ArmorRamp, ArmorRegen, Bounty, EnemyBundle, EnemyKind, EnemyPath, EnemySettings, Flying,
+        SplitsInto, TargetGoal, BOSS_BOUNTY, DEFAULT_BOUNTY,
+    },
+    game_over::RunStats,
     healthbar::HealthBar,
     layer,
-    loading::EnemyAtlasHandles,
-    map::{get_float_property, get_int_property, get_string_property},
-    Armor, HitPoints, Speed, TaipoState,
+    loading::{EnemyAtlasHandles, FontHandles},
+    map::{
+        get_bool_property, get_color_property, get_float_property, get_int_property,
+        get_string_property, MapSettings, YSort,
+    },
+    typing::{TypingTargetBundle, TypingTargetSettings, TypingTargetText, TypingTargets},
+    ui_color, Action, Armor, CleanupBeforeNewGame, GameRng, HitPoints, Speed, TaipoState,
+    FONT_SIZE_LABEL,
 };
 
 pub struct WavePlugin;
 
 impl Plugin for WavePlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<Waves>().init_resource::<WaveState>();
+        app.init_resource::<Waves>()
+            .init_resource::<WaveSettings>()
+            .init_resource::<WaveState>()
+            .init_resource::<EnemyPaths>();
 
-        app.add_systems(Update, spawn_enemies.run_if(in_state(TaipoState::Playing)));
+        app.add_systems(
+            Update,
+            (spawn_enemies, update_wave_tint).run_if(in_state(TaipoState::Playing)),
+        );
+
+        app.add_systems(OnEnter(TaipoState::Spawn), spawn_wave_tint);
     }
 }
 
+const BOSS_SCALE: f32 = 1.5;
+
+/// How quickly the screen eases towards the current wave's tint, as a fraction
+/// of the remaining difference per second.
+const WAVE_TINT_RATE: f32 = 0.5;
+
+#[derive(Resource, Default)]
+pub struct WaveSettings {
+    /// If true, each wave waits for the player to type the "start" word instead of
+    /// starting automatically when its delay runs out.
+    pub wait_for_ready: bool,
+    /// If true, increasingly difficult waves keep coming after the map's waves have
+    /// all been spawned, and the game can't be won.
+    pub endless: bool,
+}
+
+/// The enemy paths defined in the map, by their index.
+#[derive(Resource, Default)]
+pub struct EnemyPaths(pub HashMap<i32, Vec<Vec2>>);
+
 #[derive(Resource, Default)]
 pub struct Waves {
     pub waves: Vec<Wave>,
@@ -36,63 +75,243 @@ impl Waves {
         self.current += 1;
         self.current()
     }
+    /// Adds a wave that is somewhat harder than the last one, following the paths of a
+    /// randomly chosen existing wave.
+    pub fn push_endless(&mut self, rng: &mut impl Rng) {
+        let (Some(last), Some(route)) = (self.waves.last(), self.waves.choose(rng)) else {
+            return;
+        };
+
+        let wave = Wave {
+            paths: route.paths.clone(),
+            goal: route.goal,
+            hp: (last.hp as f32 * ENDLESS_HP_GROWTH).ceil() as u32,
+            num: (last.num as f32 * ENDLESS_NUM_GROWTH).ceil() as usize,
+            speed: (last.speed * ENDLESS_SPEED_GROWTH).min(ENDLESS_MAX_SPEED),
+            ..last.clone()
+        };
+
+        self.waves.push(wave);
+    }
 }
 
+// Each endless wave scales the previous one, so difficulty compounds.
+const ENDLESS_HP_GROWTH: f32 = 1.2;
+const ENDLESS_NUM_GROWTH: f32 = 1.1;
+const ENDLESS_SPEED_GROWTH: f32 = 1.05;
+/// Enemies faster than this are hard to keep up with, even for very fast typists.
+const ENDLESS_MAX_SPEED: f32 = 60.0;
+
 #[derive(Clone, Debug)]
 pub struct Wave {
-    pub path: Vec<Vec2>,
+    /// The paths that this wave's enemies follow. Enemies take turns between them.
+    pub paths: Vec<Vec<Vec2>>,
     pub enemy: String,
+    /// Enemy kinds and their relative weights. If not empty, each spawned enemy is
+    /// randomly chosen from these instead of using `enemy`.
+    pub enemy_weights: Vec<(String, u32)>,
     pub num: usize,
     pub hp: u32,
     pub armor: u32,
     pub speed: f32,
     pub interval: f32,
     pub delay: f32,
+    /// A color that the screen is tinted with while this wave is active.
+    pub tint: Option<Color>,
+    /// The index of the goal that this wave's enemies attack.
+    pub goal: i32,
+    /// The maximum distance that enemies are randomly offset from the start of the
+    /// path, perpendicular to its first segment.
+    pub spawn_spread: f32,
+    /// If set, armor removed by debuffs is restored this many seconds after the
+    /// debuff was applied.
+    pub armor_regen: Option<f32>,
+    /// If set, enemies gain one armor every this many seconds, up to `max_armor`.
+    pub armor_ramp_interval: Option<f32>,
+    pub max_armor: u32,
+    /// If true, this wave's enemies fly, and can only be shot by anti-air towers.
+    pub flying: bool,
+    /// If true, this wave's enemies are bosses, which are larger, have a health bar
+    /// at the top of the screen, and are worth more.
+    pub boss: bool,
+    /// If set, this wave's enemies split into other enemies when they die.
+    pub splits_into: Option<SplitsInto>,
+    /// The currency awarded for killing each of this wave's enemies.
+    pub bounty: u32,
 }
 impl Default for Wave {
     fn default() -> Self {
         Wave {
-            path: vec![],
+            paths: vec![],
             enemy: "skeleton".to_string(),
+            enemy_weights: vec![],
             hp: 5,
             num: 10,
             armor: 0,
             speed: 20.0,
             interval: 3.0,
             delay: 30.0,
+            tint: None,
+            goal: 0,
+            spawn_spread: 0.0,
+            armor_regen: None,
+            armor_ramp_interval: None,
+            max_armor: 0,
+            flying: false,
+            boss: false,
+            splits_into: None,
+            bounty: DEFAULT_BOUNTY,
         }
     }
 }
 
 impl Wave {
     pub fn new(object: &Object, paths: &HashMap<i32, Vec<Vec2>>) -> anyhow::Result<Wave> {
-        let enemy = get_string_property(object, "enemy")?;
+        let enemy_weights = match get_string_property(object, "enemies") {
+            Ok(enemies) => parse_enemy_weights(&enemies)?,
+            Err(_) => vec![],
+        };
+        let enemy = match get_string_property(object, "enemy") {
+            Ok(enemy) => enemy,
+            Err(err) => enemy_weights
+                .first()
+                .map(|(enemy, _)| enemy.clone())
+                .ok_or(err)?,
+        };
         let num = get_int_property(object, "num")? as usize;
         let delay = get_float_property(object, "delay")?;
         let interval = get_float_property(object, "interval")?;
         let hp = get_int_property(object, "hp")? as u32;
         let armor = get_int_property(object, "armor")? as u32;
         let speed = get_float_property(object, "speed")?;
-        let path_index = get_int_property(object, "path_index")?;
+        let path_indices = path_indices(object)?;
+        let tint = object
+            .properties
+            .contains_key("tint")
+            .then(|| get_color_property(object, "tint"))
+            .transpose()?;
+        let goal = get_int_property(object, "goal_index").unwrap_or(0);
+        let spawn_spread = get_float_property(object, "spawn_spread").unwrap_or(0.0);
+        let armor_regen = get_float_property(object, "armor_regen").ok();
+        let armor_ramp_interval = get_float_property(object, "armor_ramp_interval").ok();
+        let max_armor = get_int_property(object, "max_armor")
+            .map(|max| max as u32)
+            .unwrap_or(armor);
+        let flying = get_bool_property(object, "flying").unwrap_or(false);
+        let boss = get_bool_property(object, "boss").unwrap_or(false);
+        let splits_into = match get_string_property(object, "split_into") {
+            Ok(kind) => {
+                let count = match get_int_property(object, "split_count") {
+                    Ok(count) => usize::try_from(count)
+                        .map_err(|_| anyhow!("property \"split_count\" must not be negative."))?,
+                    Err(_) => 2,
+                };
+
+                Some(SplitsInto { kind, count })
+            }
+            Err(_) => None,
+        };
+
+        let bounty = match get_int_property(object, "bounty") {
+            Ok(bounty) => u32::try_from(bounty)
+                .map_err(|_| anyhow!("property \"bounty\" must not be negative."))?,
+            Err(_) if boss => BOSS_BOUNTY,
+            Err(_) => DEFAULT_BOUNTY,
+        };
 
-        let path = paths
-            .get(&path_index)
-            .ok_or_else(|| anyhow!("no path for path_index"))?
-            .clone();
+        let paths = path_indices
+            .iter()
+            .map(|index| {
+                paths
+                    .get(index)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("no path for path_index {}.", index))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
 
         Ok(Wave {
-            path,
+            paths,
             enemy,
+            enemy_weights,
             num,
             hp,
             armor,
             speed,
             interval,
             delay,
+            tint,
+            goal,
+            spawn_spread,
+            armor_regen,
+            armor_ramp_interval,
+            max_armor,
+            flying,
+            boss,
+            splits_into,
+            bounty,
         })
     }
+
+    /// Chooses the kind of the next enemy to spawn.
+    pub fn pick_enemy(&self, rng: &mut impl Rng) -> &str {
+        self.enemy_weights
+            .choose_weighted(rng, |(_, weight)| *weight)
+            .map(|(enemy, _)| enemy.as_str())
+            .unwrap_or(&self.enemy)
+    }
+}
+
+/// Parses a list of weighted enemy kinds, e.g. `skeleton:70, snake:30`. Kinds without
+/// a weight have a weight of 1.
+fn parse_enemy_weights(input: &str) -> anyhow::Result<Vec<(String, u32)>> {
+    input
+        .split(',')
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once(':') {
+            Some((enemy, weight)) => weight
+                .trim()
+                .parse::<u32>()
+                .map(|weight| (enemy.trim().to_string(), weight))
+                .map_err(|_| anyhow!("invalid weight for enemy \"{}\".", enemy.trim())),
+            None => Ok((entry.to_string(), 1)),
+        })
+        .collect()
+}
+
+/// Returns the indices of the paths that a wave object's enemies follow, from either
+/// its `path_indices` or its `path_index`.
+pub fn path_indices(object: &Object) -> anyhow::Result<Vec<i32>> {
+    // Waves may follow several paths at once, e.g. `0, 1`.
+    match get_string_property(object, "path_indices") {
+        Ok(indices) => parse_path_indices(&indices),
+        Err(_) => Ok(vec![get_int_property(object, "path_index")?]),
+    }
 }
 
+/// Parses a list of path indices, e.g. `0, 1`.
+fn parse_path_indices(input: &str) -> anyhow::Result<Vec<i32>> {
+    let indices = input
+        .split(',')
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .parse::<i32>()
+                .map_err(|_| anyhow!("invalid path index \"{}\".", entry))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if indices.is_empty() {
+        return Err(anyhow!("path_indices is empty."));
+    }
+
+    Ok(indices)
+}
+
+#[derive(Component)]
+struct WaveTint;
+
 #[derive(Resource)]
 pub struct WaveState {
     pub delay_timer: Timer,
@@ -109,6 +328,16 @@ impl Default for WaveState {
     }
 }
 
+impl WaveState {
+    /// Skips the rest of the delay before the current wave starts spawning.
+    pub fn start(&mut self) {
+        let duration = self.delay_timer.duration();
+        self.delay_timer.set_elapsed(duration);
+        // Make sure that `finished` is updated
+        self.delay_timer.tick(std::time::Duration::ZERO);
+    }
+}
+
 impl From<&Wave> for WaveState {
     fn from(value: &Wave) -> Self {
         Self {
@@ -126,12 +355,22 @@ pub fn spawn_enemies(
     time: Res<Time>,
     enemy_atlas_handles: Res<EnemyAtlasHandles>,
     atlas_images: Res<Assets<AtlasImage>>,
+    map_settings: Res<MapSettings>,
+    enemy_settings: Res<EnemySettings>,
+    mut typing_targets: ResMut<TypingTargets>,
+    font_handles: Res<FontHandles>,
+    mut rng: ResMut<GameRng>,
+    settings: Res<WaveSettings>,
+    mut run_stats: ResMut<RunStats>,
 ) {
     let Some(current_wave) = waves.current() else {
         return;
     };
 
-    wave_state.delay_timer.tick(time.delta());
+    // When waiting for the player, the delay is only finished by `WaveState::start`.
+    if !settings.wait_for_ready {
+        wave_state.delay_timer.tick(time.delta());
+    }
     if !wave_state.delay_timer.finished() {
         return;
     }
@@ -141,14 +380,30 @@ pub fn spawn_enemies(
         return;
     }
 
-    let path = current_wave.path.clone();
-    let point = path[0];
+    let spawned = current_wave.num - wave_state.remaining;
+    let path = current_wave.paths[spawned % current_wave.paths.len()].clone();
+    let mut point = path[0];
+
+    if current_wave.spawn_spread > 0.0 {
+        if let Some(next) = path.get(1) {
+            let perp = (*next - point).normalize_or_zero().perp();
+            point += perp * rng.0.gen_range(-1.0..=1.0) * current_wave.spawn_spread;
+        }
+    }
+
+    let enemy_kind = current_wave.pick_enemy(&mut rng.0).to_string();
 
     let atlas_image = atlas_images
-        .get(&enemy_atlas_handles.by_key(&current_wave.enemy))
+        .get(&enemy_atlas_handles.by_key(&enemy_kind))
         .unwrap();
 
-    commands.spawn((
+    let z = if current_wave.flying {
+        layer::FLYING
+    } else {
+        layer::ENEMY
+    };
+
+    let mut enemy = commands.spawn((
         Sprite {
             image: atlas_image.image.clone(),
             texture_atlas: Some(TextureAtlas {
@@ -157,26 +412,188 @@ pub fn spawn_enemies(
             }),
             ..default()
         },
-        Transform::from_translation(Vec3::new(point.x, point.y, layer::ENEMY)),
+        Transform::from_translation(Vec3::new(point.x, point.y, z)),
         EnemyBundle {
-            kind: EnemyKind(current_wave.enemy.to_string()),
+            kind: EnemyKind(enemy_kind),
             path: EnemyPath { path, ..default() },
             hit_points: HitPoints::full(current_wave.hp),
             armor: Armor(current_wave.armor),
             speed: Speed(current_wave.speed),
+            target_goal: TargetGoal(current_wave.goal),
             health_bar: HealthBar {
                 offset: Vec2::new(0.0, 14.0),
                 ..default()
             },
+            bounty: Bounty(current_wave.bounty),
             ..default()
         },
+        CleanupBeforeNewGame,
     ));
 
+    if current_wave.boss {
+        enemy.insert((
+            Boss,
+            Transform {
+                translation: Vec3::new(point.x, point.y, z),
+                scale: Vec3::splat(BOSS_SCALE),
+                ..default()
+            },
+        ));
+    }
+
+    if let Some(splits_into) = &current_wave.splits_into {
+        enemy.insert(splits_into.clone());
+    }
+
+    if current_wave.flying {
+        enemy.insert(Flying);
+    } else if map_settings.y_sort {
+        enemy.insert(YSort(layer::Y_SORTED));
+    }
+
+    if let Some(secs) = current_wave.armor_regen {
+        enemy.insert(ArmorRegen(secs));
+    }
+
+    if let Some(interval) = current_wave.armor_ramp_interval {
+        enemy.insert(ArmorRamp {
+            timer: Timer::from_seconds(interval, TimerMode::Repeating),
+            max: current_wave.max_armor,
+        });
+    }
+
+    // Enemies are only typed to finish them off, so running out of words just leaves
+    // them to the towers.
+    let target = enemy_settings
+        .typing_targets
+        .then(|| typing_targets.pop_front())
+        .flatten();
+
+    if let Some(target) = target {
+        enemy
+            .insert(TypingTargetBundle {
+                target: target.clone(),
+                settings: TypingTargetSettings::default(),
+                action: Action::KillEnemy,
+            })
+            .with_children(|parent| {
+                parent
+                    .spawn((
+                        Text2d::new(""),
+                        TextFont {
+                            font: font_handles.jptext.clone(),
+                            font_size: FONT_SIZE_LABEL,
+                            ..default()
+                        },
+                        TextColor(ui_color::GOOD_TEXT.into()),
+                        // Just above the healthbar
+                        Transform::from_xyz(0.0, 28.0, layer::HEALTHBAR),
+                        TypingTargetText,
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            TextSpan::default(),
+                            TextFont {
+                                font: font_handles.jptext.clone(),
+                                font_size: FONT_SIZE_LABEL,
+                                ..default()
+                            },
+                            TextColor(ui_color::PARTIAL_TEXT.into()),
+                        ));
+                        parent.spawn((
+                            TextSpan::new(target.displayed_chunks.join("")),
+                            TextFont {
+                                font: font_handles.jptext.clone(),
+                                font_size: FONT_SIZE_LABEL,
+                                ..default()
+                            },
+                            TextColor(ui_color::NORMAL_TEXT.into()),
+                        ));
+                    });
+            });
+    }
+
     wave_state.remaining -= 1;
 
     if wave_state.remaining == 0 {
+        run_stats.waves_cleared += 1;
+
+        if settings.endless && waves.current + 1 >= waves.waves.len() {
+            waves.push_endless(&mut rng.0);
+        }
+
         if let Some(next) = waves.advance() {
             commands.insert_resource(WaveState::from(next));
         }
     }
 }
+
+fn spawn_wave_tint(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            ..default()
+        },
+        BackgroundColor(Color::NONE),
+        // Draw underneath the rest of the UI so that only the map is tinted.
+        GlobalZIndex(-1),
+        WaveTint,
+        CleanupBeforeNewGame,
+    ));
+}
+
+fn update_wave_tint(
+    mut query: Query<&mut BackgroundColor, With<WaveTint>>,
+    waves: Res<Waves>,
+    time: Res<Time>,
+) {
+    let target = waves
+        .current()
+        .and_then(|wave| wave.tint)
+        .unwrap_or(Color::NONE);
+
+    let factor = (WAVE_TINT_RATE * time.delta_secs()).min(1.0);
+
+    for mut color in query.iter_mut() {
+        if color.0 == target {
+            continue;
+        }
+
+        color.0 = color.0.mix(&target, factor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn pick_enemy_follows_weights() {
+        let wave = Wave {
+            enemy_weights: parse_enemy_weights("skeleton:70, snake:30").unwrap(),
+            ..default()
+        };
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let snakes = (0..1000)
+            .filter(|_| wave.pick_enemy(&mut rng) == "snake")
+            .count();
+
+        assert!((250..350).contains(&snakes), "{} snakes", snakes);
+    }
+
+    #[test]
+    fn pick_enemy_without_weights_uses_enemy() {
+        let wave = Wave {
+            enemy: "crab".to_string(),
+            ..default()
+        };
+
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(wave.pick_enemy(&mut rng), "crab");
+    }
+}
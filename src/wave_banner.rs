@@ -0,0 +1,157 @@
+use bevy::prelude::*;
+
+use crate::{
+    action_panel::ActionPanelSettings,
+    loading::FontHandles,
+    ui_color,
+    wave::{WaveState, Waves},
+    CleanupBeforeNewGame, TaipoState, TimerDisplaySettings, FONT_SIZE,
+};
+
+pub struct WaveBannerPlugin;
+
+impl Plugin for WaveBannerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnTransition {
+                exited: TaipoState::Spawn,
+                entered: TaipoState::Playing,
+            },
+            spawn_wave_banner,
+        );
+
+        app.add_systems(
+            Update,
+            (update_wave_banner, animate_wave_banner)
+                .chain()
+                .run_if(in_state(TaipoState::Playing)),
+        );
+    }
+}
+
+/// Announces the upcoming wave while its delay timer counts down, and slides out of
+/// view once it starts spawning.
+#[derive(Component, Default)]
+struct WaveBanner {
+    visible: bool,
+    /// How far the banner has slid into view, from 0 to 1.
+    progress: f32,
+}
+#[derive(Component)]
+struct WaveBannerText;
+
+const BANNER_HEIGHT: f32 = 42.0;
+/// Distance from the top of the screen when fully visible, just below the HUD.
+const BANNER_TOP: f32 = 48.0;
+/// Seconds taken for the banner to slide in or out.
+const BANNER_TRANSITION_DURATION: f32 = 0.3;
+
+fn spawn_wave_banner(mut commands: Commands, font_handles: Res<FontHandles>) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(-BANNER_HEIGHT),
+                width: Val::Percent(100.0),
+                height: Val::Px(BANNER_HEIGHT),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            Visibility::Hidden,
+            WaveBanner::default(),
+            CleanupBeforeNewGame,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    Node {
+                        padding: UiRect::axes(Val::Px(12.0), Val::Px(6.0)),
+                        ..default()
+                    },
+                    BackgroundColor(ui_color::TRANSPARENT_BACKGROUND.into()),
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new(""),
+                        TextFont {
+                            font: font_handles.jptext.clone(),
+                            font_size: FONT_SIZE,
+                            ..default()
+                        },
+                        TextColor(ui_color::NORMAL_TEXT.into()),
+                        WaveBannerText,
+                    ));
+                });
+        });
+}
+
+fn update_wave_banner(
+    mut banner_query: Query<&mut WaveBanner>,
+    mut text_query: Query<(&mut Text, &mut TextColor), With<WaveBannerText>>,
+    waves: Res<Waves>,
+    wave_state: Res<WaveState>,
+    timer_settings: Res<TimerDisplaySettings>,
+) {
+    if !waves.is_changed() && !wave_state.is_changed() {
+        return;
+    }
+
+    let visible = waves.current().is_some() && !wave_state.delay_timer.finished();
+
+    for mut banner in banner_query.iter_mut() {
+        banner.visible = visible;
+    }
+
+    if !visible {
+        return;
+    }
+
+    let remaining = wave_state.delay_timer.remaining_secs();
+    let urgent = remaining <= timer_settings.urgent_secs;
+
+    for (mut text, mut color) in text_query.iter_mut() {
+        text.0 = format!(
+            "Wave {} incoming: {}",
+            waves.current + 1,
+            timer_settings.format.format(remaining)
+        );
+        color.0 = if urgent {
+            ui_color::BAD_TEXT.into()
+        } else {
+            ui_color::NORMAL_TEXT.into()
+        };
+    }
+}
+
+fn animate_wave_banner(
+    mut query: Query<(&mut Node, &mut Visibility, &mut WaveBanner)>,
+    settings: Res<ActionPanelSettings>,
+    time: Res<Time<Real>>,
+) {
+    for (mut node, mut visibility, mut banner) in query.iter_mut() {
+        let target = if banner.visible { 1.0 } else { 0.0 };
+
+        if banner.progress == target {
+            continue;
+        }
+
+        banner.progress = if settings.reduce_motion {
+            target
+        } else {
+            let step = time.delta_secs() / BANNER_TRANSITION_DURATION;
+            if target > banner.progress {
+                (banner.progress + step).min(target)
+            } else {
+                (banner.progress - step).max(target)
+            }
+        };
+
+        node.top = Val::Px(-BANNER_HEIGHT + (BANNER_TOP + BANNER_HEIGHT) * banner.progress);
+        *visibility = if banner.progress > 0.0 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}